@@ -2,11 +2,23 @@
 
 in VS_OUTPUT {
     vec4 Color;
+    vec2 TexCoord;
 } IN;
 
+uniform vec4 WireframeColor;
+uniform int UseWireframeColor;
+uniform sampler2D GroupTexture;
+uniform int UseGroupTexture;
+
 out vec4 Color;
 
 void main()
 {
-    Color = IN.Color;
+    if (UseWireframeColor != 0) {
+        Color = WireframeColor;
+    } else if (UseGroupTexture != 0) {
+        Color = IN.Color * texture(GroupTexture, IN.TexCoord);
+    } else {
+        Color = IN.Color;
+    }
 }
\ No newline at end of file
@@ -9,11 +9,13 @@ layout (location = 5) in vec4 ModelCol1;
 layout (location = 6) in vec4 ModelCol2;
 layout (location = 7) in vec4 ModelCol3;
 layout (location = 8) in vec4 Color;
+layout (location = 9) in float Rotation;
 
 uniform mat4 ViewProjection;
 
 out VS_OUTPUT {
     vec4 Color;
+    vec2 TexCoord;
 } OUT;
 
 void main()
@@ -24,7 +26,15 @@ void main()
     Model[2] = ModelCol2;
     Model[3] = ModelCol3;
 
-    vec4 OutPos = ViewProjection * Model * vec4(Position.x + OffsetX, Position.y + OffsetY, 0.0, 1.0);
+    float RotationSin = sin(Rotation);
+    float RotationCos = cos(Rotation);
+    vec2 RotatedPosition = vec2(
+        Position.x * RotationCos - Position.y * RotationSin,
+        Position.x * RotationSin + Position.y * RotationCos
+    );
+
+    vec4 OutPos = ViewProjection * Model * vec4(RotatedPosition.x + OffsetX, RotatedPosition.y + OffsetY, 0.0, 1.0);
     gl_Position = vec4(OutPos.x, OutPos.y, OutPos.z, OutPos.w);
     OUT.Color = Color;
+    OUT.TexCoord = Normal;
 }
\ No newline at end of file
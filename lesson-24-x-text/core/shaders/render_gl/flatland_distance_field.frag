@@ -0,0 +1,27 @@
+#version 330 core
+
+in VS_OUTPUT {
+    vec4 Color;
+    vec2 TexCoord;
+} IN;
+
+uniform vec4 WireframeColor;
+uniform int UseWireframeColor;
+uniform sampler2D GroupTexture;
+uniform int UseGroupTexture;
+
+out vec4 Color;
+
+// textured groups sample a signed distance in alpha, the glyph edge at 0.5
+void main()
+{
+    if (UseWireframeColor != 0) {
+        Color = WireframeColor;
+    } else if (UseGroupTexture != 0) {
+        float distance = texture(GroupTexture, IN.TexCoord).a;
+        float width = fwidth(distance);
+        Color = vec4(IN.Color.rgb, IN.Color.a * smoothstep(0.5 - width, 0.5 + width, distance));
+    } else {
+        Color = IN.Color;
+    }
+}
\ No newline at end of file
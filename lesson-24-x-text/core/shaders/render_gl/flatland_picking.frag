@@ -0,0 +1,17 @@
+#version 330 core
+
+in VS_OUTPUT {
+    vec4 Color;
+    vec2 TexCoord;
+} IN;
+
+out vec4 Color;
+
+// the color encodes the group, shadows have none
+void main()
+{
+    if (IN.Color.a == 0.0) {
+        discard;
+    }
+    Color = IN.Color;
+}
\ No newline at end of file
@@ -0,0 +1,183 @@
+//! Spawns many animated flatland groups and prints frame times.
+//!
+//! Usage: `cargo run --release --example flatland_stress -- [group count]`
+
+extern crate failure;
+extern crate floating_duration;
+extern crate gl;
+extern crate lesson_24_x_render_gl as render_gl;
+extern crate nalgebra as na;
+extern crate resources;
+extern crate sdl2;
+
+use failure::err_msg;
+use floating_duration::TimeAsFloat;
+use render_gl::data;
+use render_gl::{FlatlandGroup, FlatlandItem, FlatlanderVertex};
+use std::time::Instant;
+
+const DEFAULT_GROUP_COUNT: usize = 5000;
+const WIDTH: u32 = 960;
+const HEIGHT: u32 = 600;
+
+fn main() {
+    if let Err(e) = run() {
+        println!("{}", e);
+    }
+}
+
+/// Xorshift, good enough for scattering groups around.
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / ::std::u32::MAX as f32
+    }
+}
+
+struct Animated {
+    group: FlatlandGroup,
+    origin: na::Vector2<f32>,
+    phase: f32,
+    speed: f32,
+}
+
+fn square(size: f32) -> (Vec<FlatlanderVertex>, Vec<u16>) {
+    let vertex = |x: f32, y: f32| FlatlanderVertex {
+        pos: data::f16_f16::from((x, y)),
+        normal: data::f16_f16::from((0.0, 0.0)),
+    };
+
+    (
+        vec![vertex(0.0, 0.0), vertex(size, 0.0), vertex(size, size), vertex(0.0, size)],
+        vec![0, 1, 2, 0, 2, 3],
+    )
+}
+
+fn transform_at(origin: na::Vector2<f32>, angle: f32) -> na::Projective3<f32> {
+    let position = origin + na::Vector2::new(angle.cos(), angle.sin()) * 20.0;
+    na::Projective3::from_matrix_unchecked(
+        na::Matrix4::new_translation(&na::Vector3::new(position.x, position.y, 0.0))
+    )
+}
+
+fn run() -> Result<(), failure::Error> {
+    let group_count = ::std::env::args()
+        .nth(1)
+        .map(|arg| arg.parse::<usize>())
+        .unwrap_or(Ok(DEFAULT_GROUP_COUNT))?;
+
+    let resources = resources::Resources::new().loaded_from(
+        "core",
+        0,
+        resources::backend::FileSystem::from_rel_path(env!("CARGO_MANIFEST_DIR"), "core"),
+    );
+
+    let sdl = sdl2::init().map_err(err_msg)?;
+    let video_subsystem = sdl.video().map_err(err_msg)?;
+
+    let gl_attr = video_subsystem.gl_attr();
+    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+    gl_attr.set_context_version(4, 1);
+    gl_attr.set_double_buffer(true);
+
+    let window = video_subsystem
+        .window("Flatland stress test", WIDTH, HEIGHT)
+        .opengl()
+        .build()?;
+
+    let _gl_context = window.gl_create_context().map_err(err_msg)?;
+    let gl = gl::Gl::load_with(|s| {
+        video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void
+    });
+    video_subsystem.gl_set_swap_interval(0);
+
+    let viewport = render_gl::Viewport::for_window(WIDTH as i32, HEIGHT as i32);
+    let color_buffer = render_gl::ColorBuffer::new();
+    viewport.set_used(&gl);
+    color_buffer.set_clear_color(&gl, na::Vector3::new(1.0, 1.0, 1.0));
+
+    let mut flatlander = render_gl::Flatlander::new(&gl, &resources)?;
+    println!("draw path: {:?}", flatlander.active_draw_path());
+
+    let alphabet = flatlander.create_alphabet();
+    let (vertices, indices) = square(8.0);
+    let entry = alphabet.add_entry(0, vertices, indices);
+
+    let mut random = Random(0x2545_f491);
+    let created = Instant::now();
+
+    flatlander.begin_batch();
+    let groups: Vec<Animated> = (0..group_count)
+        .map(|_| {
+            let origin = na::Vector2::new(random.next() * WIDTH as f32, random.next() * HEIGHT as f32);
+            let phase = random.next() * 6.28;
+            let items = vec![FlatlandItem::new(entry, 0.0, 0.0)];
+
+            Animated {
+                group: FlatlandGroup::new(&transform_at(origin, phase), na::Vector4::new(0, 0, 0, 255), alphabet.clone(), items),
+                origin,
+                phase,
+                speed: 0.5 + random.next() * 2.0,
+            }
+        })
+        .collect();
+    flatlander.end_batch(&gl);
+
+    println!("created {} groups in {:.2} ms", group_count, created.elapsed().as_fractional_millis());
+
+    let vp_matrix = render_gl::ortho_screen(WIDTH as f32, HEIGHT as f32);
+    let started = Instant::now();
+    let mut report = Instant::now();
+    let mut frames = 0;
+    let mut slowest_frame = 0.0;
+
+    let mut event_pump = sdl.event_pump().map_err(err_msg)?;
+    'main: loop {
+        for event in event_pump.poll_iter() {
+            if let sdl2::event::Event::Quit { .. } = event {
+                break 'main;
+            }
+        }
+
+        let frame = Instant::now();
+        let time = started.elapsed().as_fractional_secs() as f32;
+
+        let updates: Vec<_> = groups.iter()
+            .map(|a| (a.group.id(), transform_at(a.origin, a.phase + time * a.speed)))
+            .collect();
+        flatlander.update_transforms(&updates);
+
+        for a in &groups {
+            let shade = ((a.phase + time).sin() * 0.5 + 0.5) * 200.0;
+            a.group.update_color(na::Vector4::new(shade as u8, 0, 200 - shade as u8, 255));
+        }
+
+        color_buffer.clear(&gl);
+        flatlander.render(&gl, &color_buffer, &vp_matrix);
+        window.gl_swap_window();
+
+        let frame_time = frame.elapsed().as_fractional_millis();
+        if frame_time > slowest_frame {
+            slowest_frame = frame_time;
+        }
+        frames += 1;
+
+        if report.elapsed().as_fractional_secs() >= 1.0 {
+            println!(
+                "{} groups: {:.2} ms per frame on average, {:.2} ms slowest",
+                group_count,
+                report.elapsed().as_fractional_millis() / frames as f64,
+                slowest_frame
+            );
+            report = Instant::now();
+            frames = 0;
+            slowest_frame = 0.0;
+        }
+    }
+
+    Ok(())
+}
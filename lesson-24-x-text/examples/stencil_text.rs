@@ -0,0 +1,178 @@
+//! Reveals text through a pulsing circle by masking it with the stencil buffer.
+//!
+//! Usage: `cargo run --example stencil_text`
+
+extern crate failure;
+extern crate floating_duration;
+extern crate gl;
+extern crate lesson_24_x_render_gl as render_gl;
+extern crate nalgebra as na;
+extern crate resources;
+extern crate sdl2;
+
+use failure::err_msg;
+use floating_duration::TimeAsFloat;
+use render_gl::data;
+use render_gl::{FlatlandGroup, FlatlandItem, FlatlanderVertex};
+use std::time::Instant;
+
+const WIDTH: u32 = 960;
+const HEIGHT: u32 = 600;
+const TEXT: &str = "STENCIL";
+/// Size of a glyph pixel on screen.
+const PIXEL: f32 = 16.0;
+const CIRCLE_SEGMENTS: u16 = 64;
+/// `user_data` of the circle group, to draw it apart from the text.
+const MASK: u64 = 1;
+
+fn main() {
+    if let Err(e) = run() {
+        println!("{}", e);
+    }
+}
+
+fn vertex(x: f32, y: f32) -> FlatlanderVertex {
+    FlatlanderVertex {
+        pos: data::f16_f16::from((x, y)),
+        normal: data::f16_f16::from((0.0, 0.0)),
+    }
+}
+
+/// Rows of a blocky glyph from the top, `#` for filled pixels.
+fn bitmap(ch: char) -> Option<[&'static str; 5]> {
+    Some(match ch {
+        'S' => ["###", "#..", "###", "..#", "###"],
+        'T' => ["###", ".#.", ".#.", ".#.", ".#."],
+        'E' => ["###", "#..", "##.", "#..", "###"],
+        'N' => ["#.#", "###", "###", "#.#", "#.#"],
+        'C' => ["###", "#..", "#..", "#..", "###"],
+        'I' => ["###", ".#.", ".#.", ".#.", "###"],
+        'L' => ["#..", "#..", "#..", "#..", "###"],
+        _ => return None,
+    })
+}
+
+/// Glyph with a quad per filled pixel, a pixel wide gap after it.
+fn glyph(ch: char, _tolerance: f32) -> Option<(Vec<FlatlanderVertex>, Vec<u16>, f32)> {
+    let rows = bitmap(ch)?;
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for (y, row) in rows.iter().enumerate() {
+        for (x, _) in row.chars().enumerate().filter(|&(_, c)| c == '#') {
+            let (x, y) = (x as f32 * PIXEL, y as f32 * PIXEL);
+            let first = vertices.len() as u16;
+            vertices.extend_from_slice(&[vertex(x, y), vertex(x + PIXEL, y), vertex(x + PIXEL, y + PIXEL), vertex(x, y + PIXEL)]);
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    Some((vertices, indices, 4.0 * PIXEL))
+}
+
+/// Circle of radius 1 around the origin, scaled to the mask size.
+fn circle() -> (Vec<FlatlanderVertex>, Vec<u16>) {
+    let rim = (0..CIRCLE_SEGMENTS).map(|i| {
+        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * ::std::f32::consts::PI;
+        vertex(angle.cos(), angle.sin())
+    });
+    let vertices = ::std::iter::once(vertex(0.0, 0.0)).chain(rim).collect();
+    let indices = (0..CIRCLE_SEGMENTS)
+        .flat_map(|i| vec![0, 1 + i, 1 + (i + 1) % CIRCLE_SEGMENTS])
+        .collect();
+
+    (vertices, indices)
+}
+
+fn translation(x: f32, y: f32) -> na::Projective3<f32> {
+    na::Projective3::from_matrix_unchecked(na::Matrix4::new_translation(&na::Vector3::new(x, y, 0.0)))
+}
+
+fn run() -> Result<(), failure::Error> {
+    let resources = resources::Resources::new().loaded_from(
+        "core",
+        0,
+        resources::backend::FileSystem::from_rel_path(env!("CARGO_MANIFEST_DIR"), "core"),
+    );
+
+    let sdl = sdl2::init().map_err(err_msg)?;
+    let video_subsystem = sdl.video().map_err(err_msg)?;
+
+    let gl_attr = video_subsystem.gl_attr();
+    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+    gl_attr.set_context_version(4, 1);
+    gl_attr.set_double_buffer(true);
+    gl_attr.set_stencil_size(8);
+
+    let window = video_subsystem
+        .window("Stencil masked text", WIDTH, HEIGHT)
+        .opengl()
+        .build()?;
+
+    let _gl_context = window.gl_create_context().map_err(err_msg)?;
+    let gl = gl::Gl::load_with(|s| {
+        video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void
+    });
+
+    let viewport = render_gl::Viewport::for_window(WIDTH as i32, HEIGHT as i32);
+    let color_buffer = render_gl::ColorBuffer::new();
+    viewport.set_used(&gl);
+    color_buffer.set_clear_color(&gl, na::Vector3::new(0.1, 0.1, 0.15));
+
+    let mut flatlander = render_gl::Flatlander::new(&gl, &resources)?;
+
+    let chars: Vec<char> = TEXT.chars().collect();
+    let (font, char_to_entry) = flatlander.create_alphabet_from_source(&glyph, &chars, 1.0);
+    let text_width = chars.len() as f32 * 4.0 * PIXEL - PIXEL;
+    let (_text, _) = flatlander.draw_text(
+        &font,
+        &char_to_entry,
+        TEXT,
+        &translation((WIDTH as f32 - text_width) / 2.0, (HEIGHT as f32 - 5.0 * PIXEL) / 2.0),
+        na::Vector4::new(255, 200, 40, 255),
+    );
+
+    let shapes = flatlander.create_alphabet();
+    let (vertices, indices) = circle();
+    let item = FlatlandItem::new(shapes.add_entry(0, vertices, indices), 0.0, 0.0);
+    let center = na::Vector2::new(WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+    let mask = FlatlandGroup::new(&translation(center.x, center.y), na::Vector4::new(255, 255, 255, 255), shapes, vec![item]);
+    mask.set_user_data(MASK);
+
+    let vp_matrix = render_gl::ortho_screen(WIDTH as f32, HEIGHT as f32);
+    let started = Instant::now();
+
+    let mut event_pump = sdl.event_pump().map_err(err_msg)?;
+    'main: loop {
+        for event in event_pump.poll_iter() {
+            if let sdl2::event::Event::Quit { .. } = event {
+                break 'main;
+            }
+        }
+
+        let time = started.elapsed().as_fractional_secs() as f32;
+        mask.update_transform(&translation(center.x + (time * 0.7).sin() * 200.0, center.y));
+        mask.set_scale(80.0 + (time * 2.0).sin() * 30.0);
+
+        color_buffer.clear(&gl);
+        color_buffer.clear_stencil(&gl, 0);
+        color_buffer.enable_stencil_test(&gl);
+
+        // the circle only marks the stencil with 1
+        color_buffer.set_color_write(&gl, false);
+        color_buffer.set_stencil_func(&gl, gl::ALWAYS, 1, 0xff);
+        color_buffer.set_stencil_op(&gl, gl::KEEP, gl::KEEP, gl::REPLACE);
+        flatlander.render_filtered(&gl, &color_buffer, &vp_matrix, |info| info.user_data == MASK);
+
+        // the text shows only where the stencil was marked
+        color_buffer.set_color_write(&gl, true);
+        color_buffer.set_stencil_func(&gl, gl::EQUAL, 1, 0xff);
+        color_buffer.set_stencil_op(&gl, gl::KEEP, gl::KEEP, gl::KEEP);
+        flatlander.render_filtered(&gl, &color_buffer, &vp_matrix, |info| info.user_data != MASK);
+
+        color_buffer.disable_stencil_test(&gl);
+        window.gl_swap_window();
+    }
+
+    Ok(())
+}
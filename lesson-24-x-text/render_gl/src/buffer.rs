@@ -32,6 +32,10 @@ impl Buffer where {
         }
     }
 
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vbo
+    }
+
     pub fn bind(&self) {
         unsafe {
             self.gl.BindBuffer(self.buffer_type, self.vbo);
@@ -77,6 +81,76 @@ impl Buffer where {
         }
     }
 
+    /// Reallocates the buffer storage to `size` uninitialized elements with a usage hint.
+    pub fn draw_data_null<T>(&self, size: usize, usage: gl::types::GLenum) {
+        unsafe {
+            self.gl.BufferData(
+                self.buffer_type, // target
+                (size * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr, // size of data in bytes
+                ::std::ptr::null() as *const gl::types::GLvoid, // pointer to data
+                usage,
+            );
+        }
+    }
+
+    /// Reallocates the buffer storage to `size` elements, keeping the first `preserved` elements.
+    ///
+    /// The data is copied through a temporary buffer on the gpu, and the buffer keeps its name,
+    /// so vertex array objects that reference it stay valid.
+    pub fn stream_draw_data_null_preserving<T>(&self, size: usize, preserved: usize) {
+        let preserved_size = (preserved * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr;
+        let mut scratch: gl::types::GLuint = 0;
+
+        unsafe {
+            if preserved_size > 0 {
+                self.gl.GenBuffers(1, &mut scratch);
+                self.gl.BindBuffer(gl::COPY_WRITE_BUFFER, scratch);
+                self.gl.BufferData(gl::COPY_WRITE_BUFFER, preserved_size, ::std::ptr::null(), gl::STREAM_COPY);
+                self.gl.BindBuffer(gl::COPY_READ_BUFFER, self.vbo);
+                self.gl.CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER, 0, 0, preserved_size);
+            }
+
+            self.gl.BindBuffer(self.buffer_type, self.vbo);
+            self.stream_draw_data_null::<T>(size);
+
+            if preserved_size > 0 {
+                self.gl.BindBuffer(gl::COPY_READ_BUFFER, scratch);
+                self.gl.BindBuffer(gl::COPY_WRITE_BUFFER, self.vbo);
+                self.gl.CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER, 0, 0, preserved_size);
+                self.gl.BindBuffer(gl::COPY_READ_BUFFER, 0);
+                self.gl.BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+                self.gl.DeleteBuffers(1, &mut scratch);
+            }
+        }
+    }
+
+    /// Writes `data` starting at element `offset`; the buffer must be bound and large enough.
+    pub fn sub_data<T>(&self, offset: usize, data: &[T]) {
+        unsafe {
+            self.gl.BufferSubData(
+                self.buffer_type,
+                (offset * ::std::mem::size_of::<T>()) as gl::types::GLintptr,
+                (data.len() * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+    }
+
+    /// Reads `len` elements starting at element `offset` back from the gpu; the buffer must be bound.
+    pub fn get_sub_data<T: Copy>(&self, offset: usize, len: usize) -> Vec<T> {
+        let mut data = Vec::<T>::with_capacity(len);
+        unsafe {
+            self.gl.GetBufferSubData(
+                self.buffer_type,
+                (offset * ::std::mem::size_of::<T>()) as gl::types::GLintptr,
+                (len * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                data.as_mut_ptr() as *mut gl::types::GLvoid,
+            );
+            data.set_len(len);
+        }
+        data
+    }
+
     pub unsafe fn map_buffer_range_write_invalidate<'r, T>(
         &self,
         offset: usize,
@@ -168,6 +242,10 @@ impl VertexArray {
         }
     }
 
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vao
+    }
+
     pub fn bind(&self) {
         unsafe {
             self.gl.BindVertexArray(self.vao);
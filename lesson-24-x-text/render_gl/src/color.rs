@@ -0,0 +1,38 @@
+use crate::na;
+
+/// Multiplies the color channels by alpha, rounding to the nearest value.
+///
+/// Premultiplied colors should be drawn with `BlendMode::PremultipliedAlpha`.
+pub fn premultiply(c: na::Vector4<u8>) -> na::Vector4<u8> {
+    let channel = |v: u8| ((v as u32 * c.w as u32 + 127) / 255) as u8;
+    na::Vector4::new(channel(c.x), channel(c.y), channel(c.z), c.w)
+}
+
+/// Multiplies two colors channel by channel, e.g. to tint a color by another.
+pub fn multiply(a: na::Vector4<u8>, b: na::Vector4<u8>) -> na::Vector4<u8> {
+    let channel = |x: u8, y: u8| ((x as u32 * y as u32 + 127) / 255) as u8;
+    na::Vector4::new(channel(a.x, b.x), channel(a.y, b.y), channel(a.z, b.z), channel(a.w, b.w))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn premultiplies_half_transparent_white() {
+        assert_eq!(na::Vector4::new(128, 128, 128, 128), premultiply(na::Vector4::new(255, 255, 255, 128)));
+    }
+
+    #[test]
+    fn opaque_and_transparent_colors() {
+        assert_eq!(na::Vector4::new(10, 20, 30, 255), premultiply(na::Vector4::new(10, 20, 30, 255)));
+        assert_eq!(na::Vector4::new(0, 0, 0, 0), premultiply(na::Vector4::new(10, 20, 30, 0)));
+    }
+
+    #[test]
+    fn white_leaves_colors_unchanged() {
+        let c = na::Vector4::new(10, 20, 30, 40);
+        assert_eq!(c, multiply(c, na::Vector4::new(255, 255, 255, 255)));
+        assert_eq!(na::Vector4::new(5, 10, 15, 40), multiply(c, na::Vector4::new(128, 128, 128, 255)));
+    }
+}
@@ -26,12 +26,95 @@ impl ColorBuffer {
         }
     }
 
+    pub fn set_premultiplied_blend_func(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        }
+    }
+
+    pub fn set_additive_blend_func(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.BlendFunc(gl::SRC_ALPHA, gl::ONE);
+        }
+    }
+
+    pub fn set_multiply_blend_func(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.BlendFunc(gl::DST_COLOR, gl::ONE_MINUS_SRC_ALPHA);
+        }
+    }
+
+    /// Blends by the alpha of `set_blend_color` instead of the fragment's, e.g. to fade
+    /// everything drawn by a renderer at once without changing its colors.
+    pub fn set_constant_alpha_blend_func(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.BlendFunc(gl::CONSTANT_ALPHA, gl::ONE_MINUS_CONSTANT_ALPHA);
+        }
+    }
+
+    /// Constant used by the `CONSTANT_COLOR` and `CONSTANT_ALPHA` blend factors.
+    pub fn set_blend_color(&self, gl: &gl::Gl, color: na::Vector4<f32>) {
+        unsafe {
+            gl.BlendColor(color.x, color.y, color.z, color.w);
+        }
+    }
+
     pub fn clear(&self, gl: &gl::Gl) {
         unsafe {
             gl.Clear(gl::COLOR_BUFFER_BIT);
         }
     }
 
+    /// Sets every stencil value to `value`.
+    pub fn clear_stencil(&self, gl: &gl::Gl, value: i32) {
+        unsafe {
+            gl.ClearStencil(value);
+            gl.Clear(gl::STENCIL_BUFFER_BIT);
+        }
+    }
+
+    /// Draws only fragments passing `set_stencil_func`. The window needs stencil bits,
+    /// e.g. from `gl_attr.set_stencil_size(8)`.
+    ///
+    /// To clip drawing to a shape, clear the stencil, draw the shape with `gl::ALWAYS` and
+    /// `gl::REPLACE` and color writes off, then draw everything else with `gl::EQUAL`
+    /// and `gl::KEEP`. The flatlander leaves the stencil state alone, so it can draw either part.
+    pub fn enable_stencil_test(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.Enable(gl::STENCIL_TEST);
+        }
+    }
+
+    pub fn disable_stencil_test(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.Disable(gl::STENCIL_TEST);
+        }
+    }
+
+    /// Passes fragments for which `func`, e.g. `gl::EQUAL`, holds between `reference`
+    /// and the stencil value, both masked by `mask`.
+    pub fn set_stencil_func(&self, gl: &gl::Gl, func: gl::types::GLenum, reference: i32, mask: u32) {
+        unsafe {
+            gl.StencilFunc(func, reference, mask);
+        }
+    }
+
+    /// What happens to the stencil value, e.g. `gl::KEEP` or `gl::REPLACE`, when the stencil
+    /// test fails, when the depth test fails, and when both pass.
+    pub fn set_stencil_op(&self, gl: &gl::Gl, stencil_fail: gl::types::GLenum, depth_fail: gl::types::GLenum, pass: gl::types::GLenum) {
+        unsafe {
+            gl.StencilOp(stencil_fail, depth_fail, pass);
+        }
+    }
+
+    /// Turns color writes on or off, e.g. off while drawing a stencil mask.
+    pub fn set_color_write(&self, gl: &gl::Gl, enabled: bool) {
+        let enabled = if enabled { gl::TRUE } else { gl::FALSE };
+        unsafe {
+            gl.ColorMask(enabled, enabled, enabled, enabled);
+        }
+    }
+
     pub fn enable_blend(&self, gl: &gl::Gl) {
         unsafe {
             gl.Enable(gl::BLEND);
@@ -44,6 +127,18 @@ impl ColorBuffer {
         }
     }
 
+    pub fn enable_alpha_to_coverage(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.Enable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+        }
+    }
+
+    pub fn disable_alpha_to_coverage(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.Disable(gl::SAMPLE_ALPHA_TO_COVERAGE);
+        }
+    }
+
     pub fn front_face_cw(&self, gl: &gl::Gl) {
         unsafe {
             gl.FrontFace(gl::CW);
@@ -67,4 +162,41 @@ impl ColorBuffer {
             gl.PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
         }
     }
+
+    /// Current front face winding, `gl::CW` or `gl::CCW`.
+    ///
+    /// The queries stall the pipeline; they are for checking that renderers restore state, not for frames.
+    pub fn query_front_face(&self, gl: &gl::Gl) -> gl::types::GLenum {
+        get_integer(gl, gl::FRONT_FACE) as gl::types::GLenum
+    }
+
+    /// Current polygon mode, e.g. `gl::FILL` or `gl::LINE`.
+    pub fn query_polygon_mode(&self, gl: &gl::Gl) -> gl::types::GLenum {
+        // front and back; core profiles only set both together
+        let mut mode: [gl::types::GLint; 2] = [0; 2];
+        unsafe {
+            gl.GetIntegerv(gl::POLYGON_MODE, mode.as_mut_ptr());
+        }
+        mode[0] as gl::types::GLenum
+    }
+
+    pub fn query_blend_enabled(&self, gl: &gl::Gl) -> bool {
+        unsafe { gl.IsEnabled(gl::BLEND) == gl::TRUE }
+    }
+
+    /// Current source and destination blend factors for rgb.
+    pub fn query_blend_func(&self, gl: &gl::Gl) -> (gl::types::GLenum, gl::types::GLenum) {
+        (
+            get_integer(gl, gl::BLEND_SRC_RGB) as gl::types::GLenum,
+            get_integer(gl, gl::BLEND_DST_RGB) as gl::types::GLenum,
+        )
+    }
+}
+
+fn get_integer(gl: &gl::Gl, name: gl::types::GLenum) -> gl::types::GLint {
+    let mut value = 0;
+    unsafe {
+        gl.GetIntegerv(name, &mut value);
+    }
+    value
 }
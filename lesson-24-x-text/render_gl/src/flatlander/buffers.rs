@@ -2,6 +2,7 @@ use crate::na;
 use crate::data;
 use crate::buffer::Buffer;
 use crate::buffer::VertexArray;
+use super::{BlendMode, BufferUpdateMode, GrowthPolicy, RenderStyle, Usage};
 
 #[derive(VertexAttribPointers, Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -17,10 +18,10 @@ pub struct FlatlanderVertex {
 pub struct FlatlanderVertexDrawId {
     #[location = "2"]
     #[divisor = "1"]
-    pub x_offset: data::f16_,
+    pub x_offset: data::f32_,
     #[location = "3"]
     #[divisor = "1"]
-    pub y_offset: data::f16_,
+    pub y_offset: data::f32_,
     #[location = "4"]
     #[divisor = "1"]
     pub model_col0: data::f16_f16_f16_f16,
@@ -36,6 +37,9 @@ pub struct FlatlanderVertexDrawId {
     #[location = "8"]
     #[divisor = "1"]
     pub color: data::u8_u8_u8_u8_float,
+    #[location = "9"]
+    #[divisor = "1"]
+    pub rotation: data::f16_,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -48,56 +52,146 @@ pub struct DrawIndirectCmd {
     pub base_instance: u32,
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct DrawBatch {
+    /// Selects the instance buffer, and vertex array, the batch draws from.
+    pub usage: Usage,
+    /// Texture name bound for the batch, 0 for none.
+    pub texture: u32,
+    pub blend_mode: BlendMode,
+    pub style: RenderStyle,
+    /// Drawn with the other front face, since the transform flips the winding.
+    pub mirrored: bool,
+    pub first_command: usize,
+    pub command_count: usize,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct FlatlanderGroupDrawData {
     pub cmd: DrawIndirectCmd,
+    pub blend_mode: BlendMode,
+    pub style: RenderStyle,
     pub x_offset: f32,
     pub y_offset: f32,
+    pub rotation: f32,
     pub transform: na::Projective3<f32>,
     pub color: na::Vector4<u8>,
+    /// Drawn in the shadow pass before all other draws.
+    pub shadow: bool,
+    /// Texture name sampled by the draw, 0 for none.
+    pub texture: u32,
+    /// Whether the group's flip turns its triangles to the other winding.
+    pub mirrored: bool,
+    /// `cmd.base_instance` counts from the start of this usage's instance buffer.
+    pub usage: Usage,
+    /// Hidden items keep their instance data but get no draw command.
+    pub visible: bool,
+}
+
+/// Elements and bytes written to one buffer.
+#[cfg(feature = "upload_report")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct UploadStats {
+    pub elements: usize,
+    pub bytes: usize,
+}
+
+#[cfg(feature = "upload_report")]
+impl UploadStats {
+    fn record<T>(&mut self, elements: usize) {
+        self.elements += elements;
+        self.bytes += elements * ::std::mem::size_of::<T>();
+    }
+}
+
+/// What the uploads of the last render wrote to each buffer.
+#[cfg(feature = "upload_report")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct UploadReport {
+    pub vertices: UploadStats,
+    pub indices: UploadStats,
+    /// Instance data of every draw, rewritten whenever any group changes.
+    pub groups: UploadStats,
+    pub draw_commands: UploadStats,
 }
 
 pub struct Buffers {
     vertices: Storage,
     indices: Storage,
     draw_id: Storage,
+    static_draw_id: Storage,
     pub indirect: Storage,
+    pub batches: Vec<DrawBatch>,
 
     pub groups_simple: Vec<FlatlanderGroupDrawData>,
 
     pub lines_vao: VertexArray,
+    /// Like `lines_vao`, with the instance data of static groups.
+    pub static_vao: VertexArray,
+
+    #[cfg(feature = "upload_report")]
+    pub report: UploadReport,
+    /// Bytes written to every buffer since they were created.
+    pub uploaded_bytes: usize,
 }
 
 pub struct Storage {
     pub buffer: Buffer,
     pub len: usize,
+    capacity: usize,
+    /// Usage hint of the storage when it's reallocated.
+    usage: gl::types::GLenum,
+    /// How `upload` writes the data.
+    pub update_mode: BufferUpdateMode,
+    /// How much larger the storage is reallocated when it's too small.
+    pub growth_policy: GrowthPolicy,
 }
 
 impl Storage {
     pub fn new(buffer: Buffer, len: usize) -> Storage {
+        Storage::with_usage(buffer, len, gl::STREAM_DRAW)
+    }
+
+    pub fn with_usage(buffer: Buffer, len: usize, usage: gl::types::GLenum) -> Storage {
         Storage {
             buffer,
             len,
+            capacity: len,
+            usage,
+            update_mode: BufferUpdateMode::default(),
+            growth_policy: GrowthPolicy::default(),
         }
     }
 
     pub fn upload<T, I: Iterator<Item = T>>(&mut self, items_len: usize, items: I) {
         if items_len > 0 {
-            let should_recreate_buffer = self.len < items_len;
+            let should_recreate_buffer = self.capacity < items_len;
 
             self.buffer.bind();
 
             if should_recreate_buffer {
-                self.buffer.stream_draw_data_null::<T>(items_len);
+                self.capacity = grown_capacity(self.growth_policy, self.capacity, items_len, ::std::mem::size_of::<T>());
+                self.buffer.draw_data_null::<T>(self.capacity, self.usage);
+            } else if self.update_mode == BufferUpdateMode::Orphan {
+                // the driver can hand out new storage while draws in flight still read the old one
+                self.buffer.draw_data_null::<T>(self.capacity, self.usage);
             }
 
-            if let Some(mut buffer) = unsafe {
-                self.buffer
-                    .map_buffer_range_write_invalidate::<T>(0, items_len)
-            } {
-                for (index, item) in items.enumerate().take(items_len) {
-                    *unsafe { buffer.get_unchecked_mut(index) } = item;
-                }
+            match self.update_mode {
+                BufferUpdateMode::MapRange => {
+                    if let Some(mut buffer) = unsafe {
+                        self.buffer
+                            .map_buffer_range_write_invalidate::<T>(0, items_len)
+                    } {
+                        for (index, item) in items.enumerate().take(items_len) {
+                            *unsafe { buffer.get_unchecked_mut(index) } = item;
+                        }
+                    }
+                },
+                BufferUpdateMode::SubData | BufferUpdateMode::Orphan => {
+                    let data: Vec<T> = items.take(items_len).collect();
+                    self.buffer.sub_data(0, &data);
+                },
             }
 
             self.buffer.unbind();
@@ -105,6 +199,68 @@ impl Storage {
 
         self.len = items_len;
     }
+
+    /// Resizes to `len` elements and writes each run of items at its start. Of the elements
+    /// not written, the first `preserved_len` keep their contents.
+    pub fn upload_runs<T: Copy>(&mut self, preserved_len: usize, len: usize, runs: &[(usize, Vec<T>)]) {
+        if !runs.is_empty() {
+            self.buffer.bind();
+
+            if self.capacity < len {
+                let capacity = grown_capacity(self.growth_policy, self.capacity, len, ::std::mem::size_of::<T>());
+                self.buffer.stream_draw_data_null_preserving::<T>(capacity, preserved_len.min(self.len));
+                self.capacity = capacity;
+            }
+
+            for &(start, ref items) in runs {
+                if let Some(mut buffer) = unsafe {
+                    self.buffer
+                        .map_buffer_range_write_invalidate::<T>(start, items.len())
+                } {
+                    for (index, item) in items.iter().enumerate() {
+                        *unsafe { buffer.get_unchecked_mut(index) } = *item;
+                    }
+                }
+            }
+
+            self.buffer.unbind();
+        }
+
+        self.len = len;
+    }
+
+    /// Copies the first `len` elements back from the gpu.
+    pub fn read_back<T: Copy>(&self) -> Vec<T> {
+        self.buffer.bind();
+        let data = self.buffer.get_sub_data(0, self.len);
+        self.buffer.unbind();
+        data
+    }
+}
+
+/// Capacity to reallocate storage of `capacity` elements of `element_size` bytes to for `needed` elements.
+fn grown_capacity(policy: GrowthPolicy, capacity: usize, needed: usize, element_size: usize) -> usize {
+    match policy {
+        GrowthPolicy::Exact => needed,
+        GrowthPolicy::Geometric { cap_bytes } => {
+            let cap = (cap_bytes / element_size.max(1)).max(1);
+            let grown = if capacity < cap {
+                (capacity * 2).min(cap)
+            } else {
+                capacity + cap
+            };
+            grown.max(needed)
+        },
+    }
+}
+
+/// Names a GL object for debuggers; does nothing without GL 4.3 or KHR_debug.
+pub fn object_label(gl: &gl::Gl, identifier: gl::types::GLenum, name: gl::types::GLuint, label: &str) {
+    if gl.ObjectLabel.is_loaded() {
+        unsafe {
+            gl.ObjectLabel(identifier, name, label.len() as i32, label.as_ptr() as *const gl::types::GLchar);
+        }
+    }
 }
 
 impl Buffers {
@@ -113,19 +269,25 @@ impl Buffers {
         let indices = Buffer::new_element_array(&gl);
         let indirect = Buffer::new_draw_indirect(&gl);
         let draw_id = Buffer::new_array(&gl);
+        let static_draw_id = Buffer::new_array(&gl);
 
-        let lines_vao = VertexArray::new(gl);
-
-        lines_vao.bind();
+        // both vertex arrays share the alphabet geometry
+        let vertex_array = |instances: &Buffer| {
+            let vao = VertexArray::new(gl);
+            vao.bind();
 
-        vertices.bind();
-        FlatlanderVertex::vertex_attrib_pointers(gl);
-        draw_id.bind();
-        FlatlanderVertexDrawId::vertex_attrib_pointers(gl);
-        draw_id.unbind();
+            vertices.bind();
+            FlatlanderVertex::vertex_attrib_pointers(gl);
+            instances.bind();
+            FlatlanderVertexDrawId::vertex_attrib_pointers(gl);
+            instances.unbind();
 
-        indices.bind();
-        lines_vao.unbind();
+            indices.bind();
+            vao.unbind();
+            vao
+        };
+        let lines_vao = vertex_array(&draw_id);
+        let static_vao = vertex_array(&static_draw_id);
 
         indices.unbind();
 
@@ -133,45 +295,172 @@ impl Buffers {
             vertices: Storage::new(vertices, 0),
             indices: Storage::new(indices, 0),
             indirect: Storage::new(indirect, 0),
-            draw_id: Storage::new(draw_id, 0),
+            draw_id: Storage::with_usage(draw_id, 0, gl::DYNAMIC_DRAW),
+            static_draw_id: Storage::with_usage(static_draw_id, 0, gl::STATIC_DRAW),
+            batches: Vec::new(),
             lines_vao,
+            static_vao,
             groups_simple: Vec::new(),
+            #[cfg(feature = "upload_report")]
+            report: UploadReport::default(),
+            uploaded_bytes: 0,
         }
     }
 
 
-    pub fn upload_vertices(&mut self, items_len: usize, items: impl Iterator<Item = FlatlanderVertex>) {
-        self.vertices.upload(items_len, items);
+    pub fn set_debug_labels(&self, gl: &gl::Gl) {
+        object_label(gl, gl::VERTEX_ARRAY, self.lines_vao.id(), "flatland_vao");
+        object_label(gl, gl::VERTEX_ARRAY, self.static_vao.id(), "flatland_static_vao");
+        object_label(gl, gl::BUFFER, self.vertices.buffer.id(), "flatland_vertices");
+        object_label(gl, gl::BUFFER, self.indices.buffer.id(), "flatland_indices");
+        object_label(gl, gl::BUFFER, self.draw_id.buffer.id(), "flatland_draw_id");
+        object_label(gl, gl::BUFFER, self.static_draw_id.buffer.id(), "flatland_static_draw_id");
+        object_label(gl, gl::BUFFER, self.indirect.buffer.id(), "flatland_indirect");
     }
 
-    pub fn upload_indices(&mut self, items_len: usize, items: impl Iterator<Item = u16>) {
-        self.indices.upload(items_len, items);
+    pub fn upload_vertices(&mut self, uploaded_len: usize, len: usize, runs: &[(usize, Vec<FlatlanderVertex>)]) {
+        self.vertices.upload_runs(uploaded_len, len, runs);
+        let written: usize = runs.iter().map(|&(_, ref items)| items.len()).sum();
+        self.uploaded_bytes += written * ::std::mem::size_of::<FlatlanderVertex>();
+        #[cfg(feature = "upload_report")]
+        self.report.vertices.record::<FlatlanderVertex>(written);
     }
 
-    pub fn upload_groups(&mut self, items_len: usize, items: impl Iterator<Item = FlatlanderGroupDrawData>) {
-        self.draw_id.upload(items_len, items
-            .map(|i| {
-                let mat: na::Matrix4<f32> = na::convert::<_, na::Matrix4<f32>>(i.transform) *
-                    na::Matrix4::<f32>::new_nonuniform_scaling(&na::Vector3::new(1.0, -1.0, 1.0));
-                let col0 = mat.column(0);
-                let col1 = mat.column(1);
-                let col2 = mat.column(2);
-                let col3 = mat.column(3);
+    pub fn upload_indices(&mut self, uploaded_len: usize, len: usize, runs: &[(usize, Vec<u16>)]) {
+        self.indices.upload_runs(uploaded_len, len, runs);
+        let written: usize = runs.iter().map(|&(_, ref items)| items.len()).sum();
+        self.uploaded_bytes += written * ::std::mem::size_of::<u16>();
+        #[cfg(feature = "upload_report")]
+        self.report.indices.record::<u16>(written);
+    }
 
-                FlatlanderVertexDrawId {
-                    x_offset: i.x_offset.into(),
-                    y_offset: i.y_offset.into(),
-                    model_col0: data::f16_f16_f16_f16::from((col0[0], col0[1], col0[2], col0[3])),
-                    model_col1: data::f16_f16_f16_f16::from((col1[0], col1[1], col1[2], col1[3])),
-                    model_col2: data::f16_f16_f16_f16::from((col2[0], col2[1], col2[2], col2[3])),
-                    model_col3: data::f16_f16_f16_f16::from((col3[0], col3[1], col3[2], col3[3])),
-                    color: (i.color.x, i.color.y, i.color.z, i.color.w).into(),
-                }
-            }));
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy) {
+        for storage in &mut [&mut self.vertices, &mut self.indices, &mut self.draw_id, &mut self.static_draw_id, &mut self.indirect] {
+            storage.growth_policy = policy;
+        }
+    }
+
+    /// Picks how instance data and draw commands, which are rewritten on changes, are uploaded.
+    pub fn set_update_mode(&mut self, mode: BufferUpdateMode) {
+        self.draw_id.update_mode = mode;
+        self.static_draw_id.update_mode = mode;
+        self.indirect.update_mode = mode;
+    }
+
+    pub fn upload_groups(&mut self, usage: Usage, items_len: usize, items: impl Iterator<Item = FlatlanderGroupDrawData>) {
+        let storage = match usage {
+            Usage::Static => &mut self.static_draw_id,
+            Usage::Dynamic => &mut self.draw_id,
+        };
+        storage.upload(items_len, items.map(|i| draw_id(&i)));
+        self.uploaded_bytes += items_len * ::std::mem::size_of::<FlatlanderVertexDrawId>();
+        #[cfg(feature = "upload_report")]
+        self.report.groups.record::<FlatlanderVertexDrawId>(items_len);
+    }
+
+    pub fn upload_draw_commands(&mut self, commands: &[DrawIndirectCmd], batches: &[DrawBatch]) {
+        self.indirect.upload(commands.len(), commands.iter().cloned());
+        self.uploaded_bytes += commands.len() * ::std::mem::size_of::<DrawIndirectCmd>();
+        #[cfg(feature = "upload_report")]
+        self.report.draw_commands.record::<DrawIndirectCmd>(commands.len());
+        self.batches.clear();
+        self.batches.extend_from_slice(batches);
+    }
+
+    pub fn read_vertices(&self) -> Vec<FlatlanderVertex> {
+        self.vertices.read_back()
+    }
+
+    pub fn read_indices(&self) -> Vec<u16> {
+        self.indices.read_back()
+    }
+
+    pub fn read_draw_ids(&self) -> Vec<FlatlanderVertexDrawId> {
+        // in the order of Flatland::groups_draw_data
+        let mut draw_ids = self.static_draw_id.read_back();
+        draw_ids.extend(self.draw_id.read_back::<FlatlanderVertexDrawId>());
+        draw_ids
+    }
+
+    pub fn read_draw_commands(&self) -> Vec<DrawIndirectCmd> {
+        self.indirect.read_back()
+    }
+}
+
+/// Instance data uploaded for a draw.
+pub fn draw_id(i: &FlatlanderGroupDrawData) -> FlatlanderVertexDrawId {
+    let mat = super::flatland::model_matrix(&i.transform);
+    let col0 = mat.column(0);
+    let col1 = mat.column(1);
+    let col2 = mat.column(2);
+    let col3 = mat.column(3);
+
+    FlatlanderVertexDrawId {
+        x_offset: i.x_offset.into(),
+        y_offset: i.y_offset.into(),
+        model_col0: data::f16_f16_f16_f16::from((col0[0], col0[1], col0[2], col0[3])),
+        model_col1: data::f16_f16_f16_f16::from((col1[0], col1[1], col1[2], col1[3])),
+        model_col2: data::f16_f16_f16_f16::from((col2[0], col2[1], col2[2], col2[3])),
+        model_col3: data::f16_f16_f16_f16::from((col3[0], col3[1], col3[2], col3[3])),
+        color: (i.color.x, i.color.y, i.color.z, i.color.w).into(),
+        rotation: i.rotation.into(),
+    }
+}
+
+/// Where a gpu buffer stopped matching the cpu data, see `Flatlander::verify_gpu_sync`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BufferMismatch {
+    pub buffer: &'static str,
+    /// First element that differs, or is missing from the gpu buffer.
+    pub first_difference: usize,
+}
+
+#[derive(Debug, Fail)]
+pub enum SyncError {
+    #[fail(display = "can't verify flatland buffers: alphabet geometry was not retained")]
+    NotRetained,
+    #[fail(display = "can't verify flatland buffers: changes are waiting for upload")]
+    Pending,
+    #[fail(display = "flatland buffers differ from the cpu data: {:?}", _0)]
+    Mismatch(Vec<BufferMismatch>),
+}
+
+/// Index of the first element of `expected` that differs from `gpu` at `offset`, compared bytewise.
+pub fn first_difference<T>(gpu: &[T], offset: usize, expected: &[T]) -> Option<usize> {
+    fn bytes<T>(items: &[T]) -> &[u8] {
+        unsafe {
+            ::std::slice::from_raw_parts(items.as_ptr() as *const u8, items.len() * ::std::mem::size_of::<T>())
+        }
     }
 
-    pub fn upload_draw_commands(&mut self, items_len: usize, items: impl Iterator<Item = FlatlanderGroupDrawData>) {
-        self.indirect.upload(items_len, items
-            .map(|i| i.cmd));
+    let start = offset.min(gpu.len());
+    let available = (gpu.len() - start).min(expected.len());
+    let size = ::std::mem::size_of::<T>();
+    let differing = bytes(&gpu[start..start + available])
+        .chunks(size)
+        .zip(bytes(&expected[..available]).chunks(size))
+        .position(|(g, e)| g != e);
+
+    match differing {
+        Some(i) => Some(offset + i),
+        None if available < expected.len() => Some(offset + available),
+        None => None,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_doubles_up_to_the_cap_then_grows_by_it() {
+        let policy = GrowthPolicy::Geometric { cap_bytes: 400 };
+
+        assert_eq!(10, grown_capacity(policy, 0, 10, 4));
+        assert_eq!(20, grown_capacity(policy, 10, 11, 4));
+        assert_eq!(100, grown_capacity(policy, 60, 61, 4));
+        assert_eq!(200, grown_capacity(policy, 100, 101, 4));
+        assert_eq!(500, grown_capacity(policy, 100, 500, 4));
+        assert_eq!(11, grown_capacity(GrowthPolicy::Exact, 10, 11, 4));
+    }
+}
@@ -1,7 +1,36 @@
-use metrohash::MetroHashMap;
+use metrohash::{MetroHashMap, MetroHashSet, MetroHash64};
 use slotmap;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::Range;
+use std::rc::Rc;
 use crate::na;
-use super::{FlatlanderVertex, FlatlanderGroupDrawData, DrawIndirectCmd, FlatlandItem};
+use crate::data;
+use crate::color;
+use crate::Texture;
+use super::layout::{self, GlyphMetrics, GlyphBounds, LayoutContext};
+use super::{FlatlanderVertex, FlatlanderGroupDrawData, DrawIndirectCmd, DrawBatch, FlatlandItem, BlendMode, RenderStyle, Decoration, DecorationBaseline, GroupAnimation, GlyphEffect, Winding, Pivot, Anchor, CullResult, Usage, LayerHandle};
+
+/// Vertices reachable with `u16` indices, which count from the first vertex of their entry.
+pub const MAX_ALPHABET_VERTICES: usize = 65536;
+
+/// Weights between regular and bold that get geometry of their own; others are rounded to the closest.
+pub const WEIGHT_STEPS: usize = 16;
+
+/// Geometry of an entry copied to another alphabet: plain, or its layers with their colors.
+enum EntryCopy {
+    Plain(Vec<FlatlanderVertex>, Vec<u16>),
+    Layered(Vec<(na::Vector4<u8>, Vec<FlatlanderVertex>, Vec<u16>)>),
+}
+
+impl EntryCopy {
+    fn vertex_count(&self) -> usize {
+        match *self {
+            EntryCopy::Plain(ref vertices, _) => vertices.len(),
+            EntryCopy::Layered(ref layers) => layers.iter().map(|&(_, ref vertices, _)| vertices.len()).sum(),
+        }
+    }
+}
 
 #[derive(Copy, Clone)]
 pub struct AlphabetSlotData {
@@ -10,7 +39,23 @@ pub struct AlphabetSlotData {
 
 pub struct AlphabetData {
     pub map: MetroHashMap<u32, usize>,
+    pub dedup: MetroHashMap<u64, usize>,
+    /// Shared with frozen alphabet handles; copied on write.
+    pub metrics: Rc<GlyphMetrics>,
     pub entries: Vec<AlphabetEntry>,
+    /// Layers drawn in place of layered entries, in order, with their colors.
+    pub layers: MetroHashMap<usize, Vec<(na::Vector4<u8>, usize)>>,
+    /// Some entries were added since the last upload.
+    pub has_pending: bool,
+    pub frozen: bool,
+    /// Groups using a hidden alphabet are left out of the draw commands.
+    pub visible: bool,
+    /// Entry drawn by `Flatlander::draw_text` in place of missing characters.
+    pub fallback_entry: Option<usize>,
+    /// Regular and bold vertices, sharing the indices, of entries with a variable weight.
+    pub interpolable: MetroHashMap<usize, (Vec<FlatlanderVertex>, Vec<FlatlanderVertex>, Vec<u16>)>,
+    /// Entries generated for a weight step above 0 of an interpolable entry.
+    pub weighted: MetroHashMap<(usize, usize), usize>,
     total_vertices: usize,
     total_indices: usize,
 }
@@ -19,7 +64,16 @@ impl AlphabetData {
     pub fn new() -> AlphabetData {
         AlphabetData {
             map: MetroHashMap::default(),
+            dedup: MetroHashMap::default(),
+            metrics: Rc::new(GlyphMetrics::default()),
             entries: Vec::with_capacity(4096),
+            layers: MetroHashMap::default(),
+            has_pending: false,
+            frozen: false,
+            visible: true,
+            fallback_entry: None,
+            interpolable: MetroHashMap::default(),
+            weighted: MetroHashMap::default(),
             total_vertices: 0,
             total_indices: 0,
         }
@@ -29,53 +83,737 @@ impl AlphabetData {
         self.map.get(&id).map(|v| *v)
     }
 
-    pub fn add(&mut self, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
-        let index = self.entries.len();
+    pub fn get_deduped_index(&self, key: u64, vertices: &[FlatlanderVertex], indices: &[u16]) -> Option<usize> {
+        self.dedup.get(&key)
+            .cloned()
+            .filter(|index| self.entries[*index].has_geometry(vertices, indices))
+    }
 
-        let previous_indices = self.total_indices;
+    pub fn add(&mut self, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>, advance: f32, first_vertex: usize, first_index: usize) -> usize {
+        let index = self.entries.len();
 
         self.total_vertices += vertices.len();
         self.total_indices += indices.len();
 
-        self.entries.push(AlphabetEntry { vertices, indices, previous_indices });
-        self.map.insert(id, index);
+        let hash = geometry_hash(&vertices, &indices);
+
+        let metrics = Rc::make_mut(&mut self.metrics);
+        metrics.advances.push(advance);
+        metrics.bounds.push(geometry_bounds(&vertices));
+        self.has_pending = true;
+
+        self.entries.push(AlphabetEntry {
+            vertex_count: vertices.len(),
+            index_count: indices.len(),
+            vertices,
+            indices,
+            first_vertex,
+            first_index,
+            hash,
+            uploaded: false,
+        });
 
         index
     }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.dedup.clear();
+        self.metrics = Rc::new(GlyphMetrics::default());
+        self.entries.clear();
+        self.layers.clear();
+        self.fallback_entry = None;
+        self.interpolable.clear();
+        self.weighted.clear();
+        self.total_vertices = 0;
+        self.total_indices = 0;
+    }
+
+    /// Drops the entries not marked in `keep` and renumbers the others in order, returning
+    /// the new index of each old entry. Kept entries stay where they were in the gpu buffers.
+    ///
+    /// Ids, shapes and fallbacks of dropped entries are forgotten; layers and weight steps
+    /// must be kept along with the entries they belong to.
+    pub fn retain_entries(&mut self, keep: &[bool]) -> Vec<Option<usize>> {
+        let mut next = 0;
+        let remap: Vec<Option<usize>> = keep.iter()
+            .map(|&kept| if kept { next += 1; Some(next - 1) } else { None })
+            .collect();
+        let new = |index: usize| remap.get(index).and_then(|i| *i);
+
+        for (entry, _) in self.entries.iter().zip(keep).filter(|&(_, &kept)| !kept) {
+            self.total_vertices -= entry.vertex_count;
+            self.total_indices -= entry.index_count;
+        }
+        let entries = ::std::mem::replace(&mut self.entries, Vec::new());
+        self.entries = entries.into_iter().zip(keep).filter(|&(_, &kept)| kept).map(|(entry, _)| entry).collect();
+
+        let metrics = Rc::make_mut(&mut self.metrics);
+        metrics.advances = metrics.advances.iter().zip(keep).filter(|&(_, &kept)| kept).map(|(a, _)| *a).collect();
+        metrics.bounds = metrics.bounds.iter().zip(keep).filter(|&(_, &kept)| kept).map(|(b, _)| *b).collect();
+        metrics.kerning = metrics.kerning.iter()
+            .filter_map(|(&(left, right), &k)| Some(((new(left)?, new(right)?), k)))
+            .collect();
+
+        self.map = self.map.iter().filter_map(|(&id, &index)| Some((id, new(index)?))).collect();
+        self.dedup = self.dedup.iter().filter_map(|(&key, &index)| Some((key, new(index)?))).collect();
+        self.layers = self.layers.iter()
+            .filter_map(|(&index, layers)| {
+                let layers = layers.iter().map(|&(color, layer)| (color, new(layer).expect("layers are kept with their entry"))).collect();
+                Some((new(index)?, layers))
+            })
+            .collect();
+        self.fallback_entry = self.fallback_entry.and_then(new);
+        self.interpolable = self.interpolable.drain().filter_map(|(index, geometry)| Some((new(index)?, geometry))).collect();
+        self.weighted = self.weighted.iter()
+            .filter_map(|(&(index, step), &entry)| Some(((new(index)?, step), new(entry).expect("weight steps are kept with their entry"))))
+            .collect();
+
+        remap
+    }
+
+    /// Entry drawn for the entry at `index` at `weight`: the generated entry of the closest weight
+    /// step for interpolable entries, the entry itself otherwise.
+    pub fn weighted_entry(&self, index: usize, weight: f32) -> usize {
+        match weight_step(weight) {
+            0 => index,
+            step => self.weighted.get(&(index, step)).cloned().unwrap_or(index),
+        }
+    }
+
+    /// Entries drawn for the entry at `index`, with their colors:
+    /// the entry itself, or its layers multiplied by `color`.
+    pub fn draw_entries<'r>(&'r self, index: usize, color: na::Vector4<u8>) -> impl Iterator<Item = (usize, na::Vector4<u8>)> + 'r {
+        let layers = self.layers.get(&index);
+        let single = match layers {
+            None => Some((index, color)),
+            Some(_) => None,
+        };
+
+        single.into_iter().chain(
+            layers.into_iter()
+                .flat_map(|layers| layers.iter())
+                .map(move |&(layer_color, layer)| (layer, color::multiply(color, layer_color)))
+        )
+    }
+
+    /// Triangles drawn for the entries, counting every layer of layered ones.
+    pub fn triangle_count(&self, entry_indices: &[usize]) -> usize {
+        entry_indices.iter()
+            .flat_map(|&index| self.draw_entries(index, na::Vector4::new(255, 255, 255, 255)))
+            .map(|(entry, _)| self.entries[entry].index_count / 3)
+            .sum()
+    }
+
+    pub fn draw_entries_len(&self, index: usize) -> usize {
+        self.layers.get(&index).map(|layers| layers.len()).unwrap_or(1)
+    }
+
+    /// Corners of the item's entry bounds, rotated and offset into group space.
+    pub fn item_corners(&self, item: &FlatlandItem) -> Option<[na::Vector2<f32>; 4]> {
+        let b = self.metrics.bounds(item.alphabet_entry_index)?;
+        let (sin, cos) = item.rotation.sin_cos();
+        let offset = item.position();
+        let place = |c: na::Vector2<f32>| na::Vector2::new(c.x * cos - c.y * sin, c.x * sin + c.y * cos) + offset;
+
+        Some([
+            place(b.min),
+            place(na::Vector2::new(b.max.x, b.min.y)),
+            place(b.max),
+            place(na::Vector2::new(b.min.x, b.max.y)),
+        ])
+    }
+
+    /// Extent of the items' geometry, following their offsets and rotations.
+    pub fn items_bounds<'i>(&self, items: impl Iterator<Item = &'i FlatlandItem>) -> Option<GlyphBounds> {
+        self.draws_bounds(items.map(|item| GroupDraw::Item(item, None)))
+    }
+
+    /// Extent of items of the alphabet and of generated rectangles together.
+    pub fn draws_bounds<'i>(&self, draws: impl Iterator<Item = GroupDraw<'i>>) -> Option<GlyphBounds> {
+        let mut bounds: Option<GlyphBounds> = None;
+
+        for draw in draws {
+            let corners = match draw {
+                GroupDraw::Item(item, _) => match self.item_corners(item) {
+                    Some(c) => c,
+                    None => continue,
+                },
+                GroupDraw::Quad(quad) => quad.corners(),
+            };
+
+            for &p in corners.iter() {
+                bounds = Some(match bounds {
+                    None => GlyphBounds { min: p, max: p },
+                    Some(b) => GlyphBounds {
+                        min: na::Vector2::new(b.min.x.min(p.x), b.min.y.min(p.y)),
+                        max: na::Vector2::new(b.max.x.max(p.x), b.max.y.max(p.y)),
+                    },
+                });
+            }
+        }
+
+        bounds
+    }
+
+    /// Horizontal extent of the items, from the leftmost origin to the furthest advance.
+    pub fn items_span(&self, items: &[FlatlandItem]) -> Option<(f32, f32)> {
+        items.iter()
+            .map(|i| (i.x(), i.x() + self.metrics.advance(i.alphabet_entry_index)))
+            .fold(None, |span, (start, end)| match span {
+                None => Some((start, end)),
+                Some((min, max)) => Some((min.min(start), max.max(end))),
+            })
+    }
+
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = MetroHash64::default();
+        for (index, entry) in self.entries.iter().enumerate() {
+            hasher.write_u64(entry.hash);
+            for &(color, layer) in self.layers.get(&index).into_iter().flat_map(|layers| layers.iter()) {
+                hasher.write(color.as_slice());
+                hasher.write_usize(layer);
+            }
+        }
+        hasher.finish()
+    }
 }
 
 pub struct AlphabetEntry {
+    /// Emptied after upload unless cpu data is retained.
     pub vertices: Vec<FlatlanderVertex>,
     pub indices: Vec<u16>,
-    pub previous_indices: usize,
+    pub vertex_count: usize,
+    pub index_count: usize,
+    /// Placement in the gpu buffers, kept for the lifetime of the entry.
+    pub first_vertex: usize,
+    pub first_index: usize,
+    pub hash: u64,
+    /// Written to the gpu buffers since it was placed.
+    pub uploaded: bool,
+}
+
+impl AlphabetEntry {
+    pub fn is_retained(&self) -> bool {
+        self.vertices.len() == self.vertex_count && self.indices.len() == self.index_count
+    }
+
+    pub fn has_geometry(&self, vertices: &[FlatlanderVertex], indices: &[u16]) -> bool {
+        if self.is_retained() {
+            self.indices[..] == indices[..] && vertex_bytes(&self.vertices) == vertex_bytes(vertices)
+        } else {
+            self.vertex_count == vertices.len()
+                && self.index_count == indices.len()
+                && self.hash == geometry_hash(vertices, indices)
+        }
+    }
+
+    fn release(&mut self) {
+        self.vertices = Vec::new();
+        self.indices = Vec::new();
+    }
+
+    /// Vertex and index ranges the entry takes in the gpu buffers.
+    fn space(&self) -> (Range<usize>, Range<usize>) {
+        (self.first_vertex..self.first_vertex + self.vertex_count, self.first_index..self.first_index + self.index_count)
+    }
+}
+
+/// Ranges of a gpu buffer left free by removed entries, reused by the first new entries that fit.
+#[derive(Default)]
+struct FreeRanges {
+    /// Sorted, with no two ranges touching.
+    ranges: Vec<Range<usize>>,
+}
+
+impl FreeRanges {
+    fn len(&self) -> usize {
+        self.ranges.iter().map(|r| r.end - r.start).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Start of `len` free elements taken from the first range long enough.
+    fn take(&mut self, len: usize) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        let i = self.ranges.iter().position(|r| r.end - r.start >= len)?;
+        let start = self.ranges[i].start;
+        self.ranges[i].start += len;
+        if self.ranges[i].start == self.ranges[i].end {
+            self.ranges.remove(i);
+        }
+        Some(start)
+    }
+
+    fn free(&mut self, range: Range<usize>) {
+        if range.start == range.end {
+            return;
+        }
+        let i = self.ranges.iter().position(|r| r.start > range.start).unwrap_or(self.ranges.len());
+        self.ranges.insert(i, range);
+        if i + 1 < self.ranges.len() && self.ranges[i].end == self.ranges[i + 1].start {
+            self.ranges[i].end = self.ranges.remove(i + 1).end;
+        }
+        if i > 0 && self.ranges[i - 1].end == self.ranges[i].start {
+            self.ranges[i - 1].end = self.ranges.remove(i).end;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.ranges.clear();
+    }
+}
+
+/// Consecutive runs of `items` placed at the start of each of `places`, in buffer order.
+fn runs<'e, T: Copy + 'e>(mut places: Vec<(usize, &'e [T])>) -> Vec<(usize, Vec<T>)> {
+    places.sort_by_key(|&(start, _)| start);
+
+    let mut runs: Vec<(usize, Vec<T>)> = Vec::new();
+    for (start, items) in places.into_iter().filter(|&(_, items)| !items.is_empty()) {
+        match runs.last_mut() {
+            Some(&mut (run_start, ref mut run)) if run_start + run.len() == start => run.extend_from_slice(items),
+            _ => runs.push((start, items.to_vec())),
+        }
+    }
+    runs
+}
+
+pub(super) fn quad(min: na::Vector2<f32>, max: na::Vector2<f32>) -> (Vec<FlatlanderVertex>, Vec<u16>) {
+    let vertex = |x: f32, y: f32| FlatlanderVertex {
+        pos: data::f16_f16::from((x, y)),
+        normal: data::f16_f16::from((0.0, 0.0)),
+    };
+
+    (
+        vec![vertex(min.x, min.y), vertex(max.x, min.y), vertex(max.x, max.y), vertex(min.x, max.y)],
+        vec![0, 1, 2, 0, 2, 3],
+    )
+}
+
+/// Rectangle generated for a group, drawn with the flatland's unit quad scaled to its size,
+/// so that changing it never adds geometry to an alphabet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QuadItem {
+    /// Corners in the space of the group's items.
+    pub min: na::Vector2<f32>,
+    pub max: na::Vector2<f32>,
+}
+
+impl QuadItem {
+    /// Quad for the corners, `None` if it has no area.
+    pub fn new(min: na::Vector2<f32>, max: na::Vector2<f32>) -> Option<QuadItem> {
+        if max.x > min.x && max.y > min.y { Some(QuadItem { min, max }) } else { None }
+    }
+
+    pub fn size(&self) -> na::Vector2<f32> {
+        self.max - self.min
+    }
+
+    fn corners(&self) -> [na::Vector2<f32>; 4] {
+        [self.min, na::Vector2::new(self.max.x, self.min.y), self.max, na::Vector2::new(self.min.x, self.max.y)]
+    }
+}
+
+/// Something drawn for a group: an item of its alphabet, with its index in `items` if it
+/// is a glyph, or a generated rectangle.
+#[derive(Copy, Clone)]
+pub enum GroupDraw<'r> {
+    Item(&'r FlatlandItem, Option<usize>),
+    Quad(&'r QuadItem),
+}
+
+/// Swaps the last two corners of the triangles that don't have `winding`.
+pub fn normalize_winding(vertices: &[FlatlanderVertex], indices: &mut [u16], winding: Winding) {
+    let position = |index: u16| {
+        let pos = vertices[index as usize].pos;
+        na::Vector2::new(pos.d0.to_f32(), pos.d1.to_f32())
+    };
+
+    for triangle in indices.chunks_mut(3).filter(|t| t.len() == 3) {
+        let (a, b, c) = (position(triangle[0]), position(triangle[1]), position(triangle[2]));
+        let signed_area = (b - a).perp(&(c - a));
+        let reversed = match winding {
+            Winding::CounterClockwise => signed_area < 0.0,
+            Winding::Clockwise => signed_area > 0.0,
+        };
+        if reversed {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+/// Opaque color encoding the group at `index` among all groups for the picking pass,
+/// in the low 24 bits; 0 is left for no group.
+pub fn picking_color(index: usize) -> na::Vector4<u8> {
+    let id = index + 1;
+    na::Vector4::new(id as u8, (id >> 8) as u8, (id >> 16) as u8, 255)
+}
+
+/// Index of the group a picking pixel was drawn by, `None` where no group was drawn.
+pub fn picking_index(pixel: [u8; 4]) -> Option<usize> {
+    let id = pixel[0] as usize | (pixel[1] as usize) << 8 | (pixel[2] as usize) << 16;
+    match (id, pixel[3]) {
+        (0, _) | (_, 0) => None,
+        (id, _) => Some(id - 1),
+    }
+}
+
+/// Whether `p` is inside the triangle or on its edges, whatever its winding.
+fn point_in_triangle(p: na::Vector2<f32>, a: na::Vector2<f32>, b: na::Vector2<f32>, c: na::Vector2<f32>) -> bool {
+    let sides = [(b - a).perp(&(p - a)), (c - b).perp(&(p - b)), (a - c).perp(&(p - c))];
+    !(sides.iter().any(|&s| s < 0.0) && sides.iter().any(|&s| s > 0.0))
+}
+
+fn weight_step(weight: f32) -> usize {
+    (weight.max(0.0).min(1.0) * WEIGHT_STEPS as f32).round() as usize
+}
+
+/// Vertices moved `t` of the way from `regular` to `bold`.
+fn lerp_vertices(regular: &[FlatlanderVertex], bold: &[FlatlanderVertex], t: f32) -> Vec<FlatlanderVertex> {
+    let lerp = |a: data::f16_f16, b: data::f16_f16| data::f16_f16::from((
+        a.d0.to_f32() + (b.d0.to_f32() - a.d0.to_f32()) * t,
+        a.d1.to_f32() + (b.d1.to_f32() - a.d1.to_f32()) * t,
+    ));
+
+    regular.iter()
+        .zip(bold.iter())
+        .map(|(r, b)| FlatlanderVertex { pos: lerp(r.pos, b.pos), normal: lerp(r.normal, b.normal) })
+        .collect()
+}
+
+fn geometry_bounds(vertices: &[FlatlanderVertex]) -> Option<GlyphBounds> {
+    vertices.iter()
+        .map(|v| {
+            let pos = v.pos;
+            na::Vector2::new(pos.d0.to_f32(), pos.d1.to_f32())
+        })
+        .fold(None, |bounds, p| match bounds {
+            None => Some(GlyphBounds { min: p, max: p }),
+            Some(GlyphBounds { min, max }) => Some(GlyphBounds {
+                min: na::Vector2::new(min.x.min(p.x), min.y.min(p.y)),
+                max: na::Vector2::new(max.x.max(p.x), max.y.max(p.y)),
+            }),
+        })
+}
+
+/// Replaces the x and y scale of an affine transform, keeping its rotation and translation.
+///
+/// The scale is the length of the x and y basis vectors; they are rescaled in place.
+/// A collapsed basis vector is replaced by the unrotated axis.
+fn with_scale(transform: &na::Projective3<f32>, sx: f32, sy: f32) -> na::Projective3<f32> {
+    let mut transform = *transform;
+    {
+        let matrix = transform.matrix_mut_unchecked();
+        for &(column, scale) in &[(0, sx), (1, sy)] {
+            let length = (0..3).map(|row| matrix[(row, column)] * matrix[(row, column)]).sum::<f32>().sqrt();
+            for row in 0..3 {
+                matrix[(row, column)] = if length > 0.0 {
+                    matrix[(row, column)] / length * scale
+                } else if row == column {
+                    scale
+                } else {
+                    0.0
+                };
+            }
+        }
+    }
+    transform
+}
+
+/// Point of the z = 0 plane under the `screen` pixel of a `viewport` sized target, with the
+/// pixel origin in the top-left corner. `None` if `vp` can't be inverted or looks along the plane.
+pub fn screen_to_world(screen: na::Vector2<f32>, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> Option<na::Vector2<f32>> {
+    let inverse = vp.try_inverse()?;
+    let ndc = na::Vector2::new(screen.x / viewport.x * 2.0 - 1.0, 1.0 - screen.y / viewport.y * 2.0);
+    let near = inverse.transform_point(&na::Point3::new(ndc.x, ndc.y, -1.0));
+    let far = inverse.transform_point(&na::Point3::new(ndc.x, ndc.y, 1.0));
+
+    let direction = far - near;
+    if direction.z.abs() <= ::std::f32::EPSILON {
+        return None;
+    }
+    let t = -near.z / direction.z;
+    Some(na::Vector2::new(near.x + direction.x * t, near.y + direction.y * t))
+}
+
+/// Size of the screen rectangle covering `corners`, in pixels of a `viewport` sized target.
+fn projected_size(transform: &na::Projective3<f32>, corners: &[na::Vector2<f32>; 4], vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> na::Vector2<f32> {
+    let (min, max) = projected_rect(transform, corners, vp, viewport);
+    max - min
+}
+
+/// Screen rectangle covering `corners`, in pixels from the bottom-left corner of a `viewport` sized target.
+fn projected_rect(transform: &na::Projective3<f32>, corners: &[na::Vector2<f32>; 4], vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> (na::Vector2<f32>, na::Vector2<f32>) {
+    let mvp = vp * model_matrix(transform);
+    let screen = corners.iter().map(|c| {
+        let clip = mvp * na::Vector4::new(c.x, c.y, 0.0, 1.0);
+        na::Vector2::new(clip.x / clip.w * 0.5 * viewport.x, clip.y / clip.w * 0.5 * viewport.y)
+    });
+
+    let (min, max) = screen.fold(
+        (na::Vector2::repeat(::std::f32::MAX), na::Vector2::repeat(::std::f32::MIN)),
+        |(min, max), p| (
+            na::Vector2::new(min.x.min(p.x), min.y.min(p.y)),
+            na::Vector2::new(max.x.max(p.x), max.y.max(p.y)),
+        )
+    );
+    (min + viewport * 0.5, max + viewport * 0.5)
+}
+
+fn union_rects(a: Option<(na::Vector2<f32>, na::Vector2<f32>)>, b: Option<(na::Vector2<f32>, na::Vector2<f32>)>) -> Option<(na::Vector2<f32>, na::Vector2<f32>)> {
+    match (a, b) {
+        (Some((a_min, a_max)), Some((b_min, b_max))) => Some((
+            na::Vector2::new(a_min.x.min(b_min.x), a_min.y.min(b_min.y)),
+            na::Vector2::new(a_max.x.max(b_max.x), a_max.y.max(b_max.y)),
+        )),
+        (rect, None) | (None, rect) => rect,
+    }
+}
+
+/// Model matrix of a group as uploaded, with the y flip of the flatland geometry.
+pub fn model_matrix(transform: &na::Projective3<f32>) -> na::Matrix4<f32> {
+    na::convert::<_, na::Matrix4<f32>>(*transform) *
+        na::Matrix4::<f32>::new_nonuniform_scaling(&na::Vector3::new(1.0, -1.0, 1.0))
+}
+
+/// A point in the space the group transform applies to, in the space of the item offsets.
+///
+/// Items are flipped on y before the transform applies, as in `model_matrix`; the flip is its
+/// own inverse, so this also takes item offsets back to the transform's space.
+fn to_item_space(p: na::Vector2<f32>) -> na::Vector2<f32> {
+    na::Vector2::new(p.x, -p.y)
+}
+
+fn vertex_bytes(vertices: &[FlatlanderVertex]) -> &[u8] {
+    unsafe {
+        ::std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            vertices.len() * ::std::mem::size_of::<FlatlanderVertex>()
+        )
+    }
 }
 
-#[derive(Debug)]
-struct AlphabetDataIndexOffset {
-    first_index: usize,
+pub fn geometry_hash(vertices: &[FlatlanderVertex], indices: &[u16]) -> u64 {
+    let mut hasher = MetroHash64::default();
+    hasher.write(vertex_bytes(vertices));
+    for index in indices {
+        hasher.write_u16(*index);
+    }
+    hasher.finish()
 }
 
 #[derive(Copy, Clone)]
 pub struct GroupSlotData {
+    /// Opaque tag set by the application, 0 by default.
+    pub user_data: u64,
 }
 
 pub struct GroupData {
     pub transform: na::Projective3<f32>,
+    /// Point in the transform's space that the transform rotates and scales about.
+    pub pivot: na::Vector2<f32>,
+    /// Whether the group is mirrored horizontally and vertically, about `flip_center`.
+    pub flip: (bool, bool),
+    /// Center of the group bounds in the transform's space, kept up to date while flipped.
+    pub flip_center: na::Vector2<f32>,
     pub color: na::Vector4<u8>,
+    pub blend_mode: BlendMode,
+    pub style: RenderStyle,
     pub alphabet_slot: AlphabetSlot,
     pub items: Vec<FlatlandItem>,
+    /// Color of each item in `items`, replacing the group color; items past the end use the group color.
+    pub item_colors: Vec<na::Vector4<u8>>,
+    pub decoration: Decoration,
+    pub decoration_baseline: DecorationBaseline,
+    /// Generated from `decoration`, drawn after `items`.
+    pub decoration_items: Vec<QuadItem>,
+    /// Color and padding of the quad behind the group.
+    pub background: Option<(na::Vector4<u8>, f32)>,
+    /// Generated from `background`, drawn before `items`.
+    pub background_item: Option<QuadItem>,
+    /// Character range and color of the selection highlight.
+    pub selection: Option<(usize, usize, na::Vector4<u8>)>,
+    /// Generated from `selection`, a rectangle per line, drawn after the background.
+    pub selection_items: Vec<QuadItem>,
+    /// Offset in the transform's space and color of the drop shadow, drawn in a pass before all groups.
+    pub shadow: Option<(na::Vector2<f32>, na::Vector4<u8>)>,
+    /// Screen point and offset in pixels the group's translation follows.
+    pub screen_anchor: Option<(Anchor, na::Vector2<f32>)>,
+    /// World point the group's translation follows on screen.
+    pub world_anchor: Option<na::Point3<f32>>,
+    /// The world anchor was behind the camera at the last `update_anchored_groups`;
+    /// the group is left out of the draw commands.
+    pub anchor_behind_camera: bool,
+    /// Animation and the seconds it has been playing.
+    pub animation: Option<(GroupAnimation, f32)>,
+    /// Effect on each item and the seconds it has been playing.
+    pub glyph_effect: Option<(GlyphEffect, f32)>,
+    /// Colors the group color steps through, the seconds a full cycle takes and the seconds it has been playing.
+    pub palette_cycle: Option<(Vec<na::Vector4<u8>>, f32, f32)>,
+    /// Sampled by the group's draws, except shadows, with the vertex normals as coordinates.
+    pub texture: Option<Texture>,
+    /// Instance buffer the group's data is uploaded to.
+    pub usage: Usage,
+    /// Weight from regular at 0 to bold at 1 that interpolable entries are drawn with.
+    pub weight: f32,
+    /// Alphabet the group was created with, drawn when it has no levels of detail.
+    pub base_alphabet_slot: AlphabetSlot,
+    /// Minimum apparent height in pixels and alphabet of each level of detail, by increasing height.
+    pub lod_levels: Vec<(f32, AlphabetSlot)>,
+    /// `Flatland::generation` at the group's last change.
+    pub changed_generation: u64,
+}
+
+impl GroupData {
+    /// Everything drawn for the group in draw order, with its color.
+    pub fn draw_items<'r>(&'r self) -> impl Iterator<Item = (GroupDraw<'r>, na::Vector4<u8>)> + 'r {
+        let background_color = self.background.map(|(color, _)| color).unwrap_or(self.color);
+        let selection_color = self.selection.map(|(_, _, color)| color).unwrap_or(self.color);
+
+        self.background_item.iter()
+            .map(move |q| (GroupDraw::Quad(q), background_color))
+            .chain(self.selection_items.iter().map(move |q| (GroupDraw::Quad(q), selection_color)))
+            .chain(self.glyph_items(self.color).map(move |(draw, color)| {
+                let color = match draw {
+                    GroupDraw::Item(_, Some(index)) => self.item_colors.get(index).cloned().unwrap_or(color),
+                    _ => color,
+                };
+                (draw, color)
+            }))
+    }
+
+    pub fn draw_items_len(&self) -> usize {
+        self.background_item.iter().count() + self.selection_items.len() + self.items.len() + self.decoration_items.len()
+    }
+
+    /// Draws of the shadow pass, with the shadow color; the background casts no shadow.
+    pub fn shadow_items<'r>(&'r self) -> impl Iterator<Item = (GroupDraw<'r>, na::Vector4<u8>)> + 'r {
+        self.shadow.into_iter().flat_map(move |(_, color)| self.glyph_items(color))
+    }
+
+    /// Items, then decorations, in `color`.
+    fn glyph_items<'r>(&'r self, color: na::Vector4<u8>) -> impl Iterator<Item = (GroupDraw<'r>, na::Vector4<u8>)> + 'r {
+        self.items.iter()
+            .enumerate()
+            .map(move |(index, i)| (GroupDraw::Item(i, Some(index)), color))
+            .chain(self.decoration_items.iter().map(move |q| (GroupDraw::Quad(q), color)))
+    }
+
+    /// Whether the glyph effect still changes how the group is drawn.
+    pub fn is_glyph_effect_playing(&self) -> bool {
+        match self.glyph_effect {
+            Some((effect, elapsed)) => effect.is_playing(self.items.len(), elapsed),
+            None => false,
+        }
+    }
+
+    /// Whether an item is drawn, and its vertical offset, in the current state of the glyph effect.
+    fn glyph_effect_at(&self, glyph_index: Option<usize>) -> (bool, f32) {
+        match (self.glyph_effect, glyph_index) {
+            (Some((effect, elapsed)), Some(index)) => (effect.visible(index, elapsed), effect.y_offset(index, elapsed)),
+            _ => (true, 0.0),
+        }
+    }
+
+    /// Group transform applied about the pivot, as drawn.
+    pub fn placed_transform(&self) -> na::Projective3<f32> {
+        if self.pivot == na::Vector2::zeros() && self.flip == (false, false) {
+            return self.transform;
+        }
+
+        let to_pivot = na::Matrix4::new_translation(&na::Vector3::new(self.pivot.x, self.pivot.y, 0.0));
+        let from_pivot = na::Matrix4::new_translation(&na::Vector3::new(-self.pivot.x, -self.pivot.y, 0.0));
+        let mut matrix = to_pivot * self.transform.matrix() * from_pivot;
+
+        if self.flip != (false, false) {
+            let scale = |flip| if flip { -1.0 } else { 1.0 };
+            let to_center = na::Matrix4::new_translation(&na::Vector3::new(self.flip_center.x, self.flip_center.y, 0.0));
+            let from_center = na::Matrix4::new_translation(&na::Vector3::new(-self.flip_center.x, -self.flip_center.y, 0.0));
+            let mirror = na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(scale(self.flip.0), scale(self.flip.1), 1.0));
+            matrix = matrix * to_center * mirror * from_center;
+        }
+
+        na::Projective3::from_matrix_unchecked(matrix)
+    }
+
+    /// Whether the flip turns triangles to the other winding on screen, which happens
+    /// when only one axis is flipped.
+    pub fn is_mirrored(&self) -> bool {
+        self.flip.0 != self.flip.1
+    }
+
+    /// Placed transform moved by the shadow offset.
+    pub fn shadow_transform(&self) -> na::Projective3<f32> {
+        match self.shadow {
+            Some((offset, _)) => translated(&self.placed_transform(), offset),
+            None => self.placed_transform(),
+        }
+    }
+
+    /// Whether the animation still changes how the group is drawn.
+    pub fn is_animating(&self) -> bool {
+        match self.animation {
+            Some((animation, elapsed)) => animation.duration().map(|d| elapsed < d).unwrap_or(true),
+            None => false,
+        }
+    }
+
+    /// Color and transform of a draw in the current state of the animation.
+    fn animated(&self, mut color: na::Vector4<u8>, transform: na::Projective3<f32>) -> (na::Vector4<u8>, na::Projective3<f32>) {
+        match self.animation {
+            Some((animation, elapsed)) => {
+                color.w = (color.w as f32 * animation.alpha(elapsed)).round() as u8;
+                (color, translated(&transform, animation.offset(elapsed)))
+            },
+            None => (color, transform),
+        }
+    }
+}
+
+/// Transform moved by `offset` in its own space.
+fn translated(transform: &na::Projective3<f32>, offset: na::Vector2<f32>) -> na::Projective3<f32> {
+    na::Projective3::from_matrix_unchecked(
+        transform.matrix() * na::Matrix4::new_translation(&na::Vector3::new(offset.x, offset.y, 0.0))
+    )
+}
+
+/// The item as placed by `transform`, in the space of a group with the identity transform.
+///
+/// Only the translation and the rotation about the z axis carry over.
+fn frozen_item(transform: &na::Projective3<f32>, item: &FlatlandItem) -> FlatlandItem {
+    // item offsets are flipped on y before the transform applies
+    let flip = na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(1.0, -1.0, 1.0));
+    let m = flip * transform.matrix() * flip;
+
+    let (x, y) = (item.x(), item.y());
+    FlatlandItem {
+        rotation: item.rotation + m[(1, 0)].atan2(m[(0, 0)]),
+        ..FlatlandItem::new(
+            item.alphabet_entry_index,
+            m[(0, 0)] * x + m[(0, 1)] * y + m[(0, 3)],
+            m[(1, 0)] * x + m[(1, 1)] * y + m[(1, 3)],
+        )
+    }
 }
 
 new_key_type! { pub struct AlphabetSlot; }
 new_key_type! { pub struct GroupSlot; }
 
+/// Named set of groups drawn together, see `Flatlander::render_layers`.
+pub struct Layer {
+    pub name: String,
+    pub enabled: bool,
+    pub groups: MetroHashSet<GroupSlot>,
+}
+
 pub struct Flatland {
     pub alphabet_slots: slotmap::SlotMap<AlphabetSlot, AlphabetSlotData>,
     pub alphabet_data: slotmap::SecondaryMap<AlphabetSlot, AlphabetData>,
 
-    alphabet_data_index_offsets: slotmap::SecondaryMap<AlphabetSlot, AlphabetDataIndexOffset>,
-    alphabet_data_index_offsets_invalidated: bool,
-
     pub group_slots: slotmap::SlotMap<GroupSlot, GroupSlotData>,
     pub group_data: slotmap::SecondaryMap<GroupSlot, GroupData>,
 
@@ -83,8 +821,38 @@ pub struct Flatland {
     pub groups_invalidated: bool,
     pub draw_invalidated: bool,
 
-    total_alphabet_vertices: usize,
-    total_alphabet_indices: usize,
+    /// Screen size in pixels that anchored groups are placed on.
+    viewport_size: na::Vector2<f32>,
+
+    /// `generation` and camera at the last `take_dirty_screen_rect`, with the screen rectangle
+    /// each group covered then.
+    dirty_generation: u64,
+    dirty_camera: Option<(na::Matrix4<f32>, na::Vector2<f32>)>,
+    screen_rects: MetroHashMap<GroupSlot, (na::Vector2<f32>, na::Vector2<f32>)>,
+
+    /// In registration order, which is also their draw order. A group is in one layer at most.
+    pub layers: Vec<Layer>,
+
+    /// Bumped on every group change, see `groups_changed_since`.
+    generation: u64,
+    /// `generation` at the last upload of static group data.
+    static_groups_uploaded_generation: u64,
+    /// Static groups were added or removed since their last upload.
+    static_groups_changed: bool,
+    retain_cpu_data: bool,
+
+    // entries never move in the gpu buffers unless compacted, so removed entries leave holes
+    // behind, which new entries fill before the buffers are extended
+    alphabet_vertices_end: usize,
+    alphabet_indices_end: usize,
+    uploaded_alphabet_vertices_end: usize,
+    uploaded_alphabet_indices_end: usize,
+    free_alphabet_vertices: FreeRanges,
+    free_alphabet_indices: FreeRanges,
+
+    /// Alphabet of the unit quad that generated rectangles are drawn with, created when first
+    /// needed. It is never released after upload, nor listed with the other alphabets.
+    shapes_alphabet: Option<AlphabetSlot>,
 }
 
 impl Flatland {
@@ -93,9 +861,6 @@ impl Flatland {
             alphabet_slots: slotmap::SlotMap::with_key(),
             alphabet_data: slotmap::SecondaryMap::new(),
 
-            alphabet_data_index_offsets: slotmap::SecondaryMap::new(),
-            alphabet_data_index_offsets_invalidated: false,
-
             group_slots: slotmap::SlotMap::with_key(),
             group_data: slotmap::SecondaryMap::new(),
 
@@ -103,194 +868,3100 @@ impl Flatland {
             groups_invalidated: false,
             draw_invalidated: false,
 
-            total_alphabet_vertices: 0,
-            total_alphabet_indices: 0,
+            viewport_size: na::Vector2::zeros(),
+
+            dirty_generation: 0,
+            dirty_camera: None,
+            screen_rects: MetroHashMap::default(),
+
+            layers: Vec::new(),
+
+            generation: 0,
+            static_groups_uploaded_generation: 0,
+            static_groups_changed: false,
+            retain_cpu_data: false,
+
+            alphabet_vertices_end: 0,
+            alphabet_indices_end: 0,
+            uploaded_alphabet_vertices_end: 0,
+            uploaded_alphabet_indices_end: 0,
+            free_alphabet_vertices: FreeRanges::default(),
+            free_alphabet_indices: FreeRanges::default(),
+
+            shapes_alphabet: None,
         }
     }
 
-    pub fn alphabet_vertices_len(&self) -> usize {
-        self.total_alphabet_vertices
+    pub fn set_retain_cpu_data(&mut self, retain: bool) {
+        self.retain_cpu_data = retain;
     }
 
-    pub fn alphabet_vertices<'r>(&'r self) -> impl Iterator<Item = FlatlanderVertex> + 'r {
-        self.alphabet_data
-            .values()
-            .flat_map(|data|
-                data.entries.iter()
-            )
-            .flat_map(|entry|
-                entry.vertices.iter().map(|v| *v)
-            )
+    pub fn retains_cpu_data(&self) -> bool {
+        self.retain_cpu_data
+    }
+
+    pub fn uploaded_alphabet_vertices_len(&self) -> usize {
+        self.uploaded_alphabet_vertices_end
+    }
+
+    pub fn uploaded_alphabet_indices_len(&self) -> usize {
+        self.uploaded_alphabet_indices_end
+    }
+
+    /// Vertices the alphabet buffers need room for, holes included.
+    pub fn alphabet_vertices_len(&self) -> usize {
+        self.alphabet_vertices_end
     }
 
     pub fn alphabet_indices_len(&self) -> usize {
-        self.total_alphabet_indices
+        self.alphabet_indices_end
     }
 
-    pub fn alphabet_indices<'r>(&'r self) -> impl Iterator<Item = u16> + 'r {
+    fn pending_alphabet_entries(&self) -> impl Iterator<Item = &AlphabetEntry> {
         self.alphabet_data
             .values()
-            .flat_map(|data|
-                data.entries.iter()
-            )
-            .scan(0, |base_index, AlphabetEntry { ref vertices, ref indices, .. }| {
-                let previous_base_index = *base_index;
-                *base_index += vertices.len() as u16;
-                Some((previous_base_index, indices))
-            })
-            .flat_map(|(base_index, indices)|
-                indices.iter().map(move |index| *index + base_index)
-            )
+            .filter(|data| data.has_pending)
+            .flat_map(|data| data.entries.iter())
+            .filter(|entry| !entry.uploaded)
     }
 
-    fn ensure_alphabet_data_index_offsets(&mut self) {
-        if self.alphabet_data_index_offsets_invalidated {
+    /// Vertices placed since the last upload, in runs of neighbouring entries with the place
+    /// each run is written to. Entries filling the holes of removed ones go below the uploaded end.
+    pub fn pending_alphabet_vertices(&self) -> Vec<(usize, Vec<FlatlanderVertex>)> {
+        runs(self.pending_alphabet_entries().map(|e| (e.first_vertex, &e.vertices[..])).collect())
+    }
 
-            self.alphabet_data_index_offsets.clear();
-            self.alphabet_data_index_offsets.extend(self.alphabet_data
-                .iter()
-                .scan(0, |previous_indices, (slot, AlphabetData { ref total_indices, .. })| {
-                    let first_index = *previous_indices;
-                    *previous_indices += total_indices;
-                    Some((slot, first_index))
-                })
-                .map(|(slot, first_index)|
-                    (slot, AlphabetDataIndexOffset {
-                        first_index,
-                    })
-                ));
+    /// Indices placed since the last upload, in runs like `pending_alphabet_vertices`.
+    ///
+    /// Indices stay local to their entry; draws add the entry's `first_vertex` as base vertex.
+    pub fn pending_alphabet_indices(&self) -> Vec<(usize, Vec<u16>)> {
+        runs(self.pending_alphabet_entries().map(|e| (e.first_index, &e.indices[..])).collect())
+    }
+
+    pub fn mark_alphabets_uploaded(&mut self) {
+        self.uploaded_alphabet_vertices_end = self.alphabet_vertices_end;
+        self.uploaded_alphabet_indices_end = self.alphabet_indices_end;
 
-            self.alphabet_data_index_offsets_invalidated = false;
+        let shapes_alphabet = self.shapes_alphabet;
+        for (slot, data) in self.alphabet_data.iter_mut().filter(|(_, data)| data.has_pending) {
+            for entry in data.entries.iter_mut() {
+                if !self.retain_cpu_data && Some(slot) != shapes_alphabet {
+                    entry.release();
+                }
+                entry.uploaded = true;
+            }
+            data.has_pending = false;
         }
+
+        self.alphabets_invalidated = false;
     }
 
-    pub fn groups_len(&self) -> usize {
-        self.group_data.values().map(|g| g.items.len()).sum()
+    pub fn has_wasted_alphabet_space(&self) -> bool {
+        !self.free_alphabet_vertices.is_empty() || !self.free_alphabet_indices.is_empty()
     }
 
-    pub fn groups_draw_data<'r>(&'r mut self) -> impl Iterator<Item = FlatlanderGroupDrawData> + 'r {
-        self.ensure_alphabet_data_index_offsets();
+    /// Part of the placed vertices or indices, whichever is larger, left as holes by removed entries.
+    pub fn wasted_alphabet_fraction(&self) -> f32 {
+        let fraction = |wasted: usize, end: usize| if end > 0 { wasted as f32 / end as f32 } else { 0.0 };
 
-        fn unpack<'p>(
-            group_data: &'p slotmap::SecondaryMap<GroupSlot, GroupData>,
-            alphabet_data: &'p slotmap::SecondaryMap<AlphabetSlot, AlphabetData>,
-            alphabet_data_index_offsets: &'p slotmap::SecondaryMap<AlphabetSlot, AlphabetDataIndexOffset>
-        ) -> impl Iterator<Item = FlatlanderGroupDrawData> + 'p {
-            group_data
-                .values()
-                .flat_map(move |group| group.items.iter().map(move |i| {
-                    let alphabet_slot = group.alphabet_slot;
-                    let (previous_indices, num_indices) = alphabet_data[alphabet_slot].entries.get(i.alphabet_entry_index)
-                        .map(|e| (e.previous_indices as u32, e.indices.len() as u32))
-                        .expect("expected alphabet entry to exist");
-                    let first_alphabet_index = alphabet_data_index_offsets[alphabet_slot].first_index as u32;
-
-                    (num_indices, first_alphabet_index + previous_indices, i.x_offset, i.y_offset, group.transform, group.color)
-                }))
-                .enumerate()
-                .map(|(i, (num_indices, first_index, x_offset, y_offset, transform, color))| FlatlanderGroupDrawData {
-                    cmd: DrawIndirectCmd {
-                        count: num_indices,
-                        prim_count: 1,
-                        first_index,
-                        base_vertex: 0,
-                        base_instance: i as u32
-                    },
-                    x_offset: x_offset as f32,
-                    y_offset: y_offset as f32,
-                    transform,
-                    color
-                })
+        fraction(self.free_alphabet_vertices.len(), self.alphabet_vertices_end)
+            .max(fraction(self.free_alphabet_indices.len(), self.alphabet_indices_end))
+    }
+
+    /// Packs entries together again, so that everything is uploaded from scratch without holes.
+    ///
+    /// Returns `false` without doing anything if some entries no longer have their cpu data.
+    pub fn compact_alphabets(&mut self) -> bool {
+        let all_retained = self.alphabet_data
+            .values()
+            .all(|data| data.entries.iter().all(|entry| entry.is_retained()));
+        if !all_retained {
+            return false;
         }
 
-        unpack(&self.group_data, &self.alphabet_data, &self.alphabet_data_index_offsets)
-    }
+        let mut vertices_end = 0;
+        let mut indices_end = 0;
+        for data in self.alphabet_data.values_mut() {
+            data.has_pending = true;
+            for entry in data.entries.iter_mut() {
+                entry.uploaded = false;
+                entry.first_vertex = vertices_end;
+                entry.first_index = indices_end;
+                vertices_end += entry.vertex_count;
+                indices_end += entry.index_count;
+            }
+        }
 
-    pub fn create_flatland_group_with_items(&mut self, &transform: &na::Projective3<f32>, color: na::Vector4<u8>, alphabet_slot: AlphabetSlot, items: Vec<FlatlandItem>) -> GroupSlot {
-        let slot = self.group_slots.insert(GroupSlotData {});
-        self.group_data.insert(slot, GroupData {
-            transform,
-            alphabet_slot,
-            items,
-            color,
-        });
+        self.alphabet_vertices_end = vertices_end;
+        self.alphabet_indices_end = indices_end;
+        self.uploaded_alphabet_vertices_end = 0;
+        self.uploaded_alphabet_indices_end = 0;
+        self.free_alphabet_vertices.clear();
+        self.free_alphabet_indices.clear();
 
-        self.groups_invalidated = true;
+        self.alphabets_invalidated = true;
         self.draw_invalidated = true;
 
-        slot
+        true
     }
 
-    pub fn update_items<'p>(&mut self, slot: GroupSlot, items: impl Iterator<Item = &'p FlatlandItem>) {
-        self.group_data[slot].items.clear();
-        self.group_data[slot].items.extend(items);
+    /// Marks alphabets and groups as not uploaded, for gpu buffers recreated from scratch.
+    ///
+    /// Returns `false` without doing anything if some entries no longer have their cpu data.
+    pub fn invalidate_gpu_data(&mut self) -> bool {
+        if !self.compact_alphabets() {
+            return false;
+        }
 
         self.groups_invalidated = true;
-        self.draw_invalidated = true;
+        true
     }
 
-    pub fn update_transform(&mut self, slot: GroupSlot, &transform: &na::Projective3<f32>) {
-        self.group_data[slot].transform = transform;
+    /// Every alphabet entry with its geometry, `None` if some geometry was released after upload.
+    pub fn retained_entries(&self) -> Option<Vec<&AlphabetEntry>> {
+        let entries: Vec<&AlphabetEntry> = self.alphabet_data.values().flat_map(|a| a.entries.iter()).collect();
+        if entries.iter().all(|e| e.is_retained()) {
+            Some(entries)
+        } else {
+            None
+        }
+    }
 
-        self.draw_invalidated = true;
+    pub fn alphabet_entry_geometry(&self, slot: AlphabetSlot, index: usize) -> Option<(Vec<FlatlanderVertex>, Vec<u16>)> {
+        self.alphabet_data[slot].entries.get(index)
+            .filter(|entry| entry.is_retained())
+            .map(|entry| (entry.vertices.clone(), entry.indices.clone()))
     }
 
-    pub fn update_color(&mut self, slot: GroupSlot, color: na::Vector4<u8>) {
-        self.group_data[slot].color = color;
+    pub fn groups_len_with_usage(&self, usage: Usage) -> usize {
+        self.group_data.values().filter(|g| g.usage == usage).map(|g| self.group_draw_len(g)).sum()
+    }
+
+    /// Like `groups_draw_data_with_usage`, with each group in its `picking_color` and shadows transparent.
+    pub fn picking_draw_data<'r>(&'r self, usage: Usage) -> impl Iterator<Item = FlatlanderGroupDrawData> + 'r {
+        let colors = self.group_data.values()
+            .enumerate()
+            .filter(move |&(_, group)| group.usage == usage)
+            .flat_map(move |(index, group)| ::std::iter::repeat(picking_color(index)).take(self.group_draw_len(group)));
+
+        self.groups_draw_data_with_usage(usage)
+            .zip(colors)
+            .map(|(d, color)| FlatlanderGroupDrawData {
+                color: if d.shadow { na::Vector4::new(0, 0, 0, 0) } else { color },
+                ..d
+            })
+    }
+
+    /// Draw data of the unit quad stretched over rectangles given by their lower left corner and
+    /// size in world space, e.g. for debug overlays drawn in place of the dynamic group data.
+    ///
+    /// Instances are numbered from 0. Nothing is added to the groups or alphabets, apart from
+    /// the unit quad itself if it didn't exist yet.
+    pub fn world_quads_draw_data(&mut self, rects: &[(na::Vector2<f32>, na::Vector2<f32>)], color: na::Vector4<u8>) -> Vec<FlatlanderGroupDrawData> {
+        self.ensure_unit_quad();
+        let quad = &self.alphabet_data[self.shapes_alphabet.unwrap()].entries[0];
+
+        rects.iter().enumerate()
+            .map(|(i, &(min, size))| FlatlanderGroupDrawData {
+                cmd: DrawIndirectCmd {
+                    count: quad.index_count as u32,
+                    prim_count: 1,
+                    first_index: quad.first_index as u32,
+                    base_vertex: quad.first_vertex as u32,
+                    base_instance: i as u32,
+                },
+                blend_mode: BlendMode::Alpha,
+                style: RenderStyle::Fill,
+                x_offset: 0.0,
+                y_offset: 0.0,
+                rotation: 0.0,
+                // starts at the top edge, since the y flip of `model_matrix` comes first
+                transform: na::Projective3::from_matrix_unchecked(
+                    na::Matrix4::new_translation(&na::Vector3::new(min.x, min.y + size.y, 0.0)) *
+                        na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(size.x, size.y, 1.0))
+                ),
+                color,
+                shadow: false,
+                texture: 0,
+                mirrored: false,
+                usage: Usage::Dynamic,
+                visible: true,
+            })
+            .collect()
+    }
+
+    /// Uploads the data of every group again on the next render, e.g. after the buffers held something else.
+    pub fn invalidate_group_uploads(&mut self) {
+        self.static_groups_changed = true;
+        self.groups_invalidated = true;
+    }
+
+    /// Whether the static group data changed since `mark_static_groups_uploaded`.
+    pub fn static_groups_dirty(&self) -> bool {
+        self.static_groups_changed || self.group_data.values()
+            .any(|g| g.usage == Usage::Static && g.changed_generation > self.static_groups_uploaded_generation)
+    }
+
+    pub fn mark_static_groups_uploaded(&mut self) {
+        self.static_groups_uploaded_generation = self.generation;
+        self.static_groups_changed = false;
+    }
+
+    /// Number of draws for the group, with layered entries expanded and shadows included.
+    fn group_draw_len(&self, group: &GroupData) -> usize {
+        let alphabet = &self.alphabet_data[group.alphabet_slot];
+        if alphabet.layers.is_empty() {
+            group.shadow_items().count() + group.draw_items_len()
+        } else {
+            group.shadow_items().chain(group.draw_items())
+                .map(|(draw, _)| match draw {
+                    GroupDraw::Item(i, _) => alphabet.draw_entries_len(i.alphabet_entry_index),
+                    GroupDraw::Quad(_) => 1,
+                })
+                .sum()
+        }
+    }
+
+    /// Draw data of static groups, then of dynamic ones, each numbering its instances from 0.
+    pub fn groups_draw_data<'r>(&'r self) -> impl Iterator<Item = FlatlanderGroupDrawData> + 'r {
+        self.groups_draw_data_with_usage(Usage::Static).chain(self.groups_draw_data_with_usage(Usage::Dynamic))
+    }
+
+    pub fn groups_draw_data_with_usage<'r>(&'r self, usage: Usage) -> impl Iterator<Item = FlatlanderGroupDrawData> + 'r {
+        fn unpack<'p>(
+            group_data: &'p slotmap::SecondaryMap<GroupSlot, GroupData>,
+            alphabet_data: &'p slotmap::SecondaryMap<AlphabetSlot, AlphabetData>,
+            unit_quad: Option<&'p AlphabetEntry>,
+            usage: Usage
+        ) -> impl Iterator<Item = FlatlanderGroupDrawData> + 'p {
+            group_data
+                .values()
+                .filter(move |group| group.usage == usage)
+                .flat_map(move |group| {
+                    let alphabet = &alphabet_data[group.alphabet_slot];
+                    let shadow_transform = group.shadow_transform();
+                    let transform = group.placed_transform();
+
+                    // each draw of an item or quad, with its entry, color, transform and whether it is a shadow;
+                    // shadows reuse the glyph entries, with every layer in the shadow color
+                    let entries = move |draw: GroupDraw<'p>, color: na::Vector4<u8>, transform: na::Projective3<f32>, shadow: bool| {
+                        let (item, quad) = match draw {
+                            GroupDraw::Item(i, glyph) => (Some((i, glyph)), None),
+                            GroupDraw::Quad(q) => (None, Some(q)),
+                        };
+                        let items = item.into_iter().flat_map(move |(i, glyph)| {
+                            alphabet.draw_entries(alphabet.weighted_entry(i.alphabet_entry_index, group.weight), color)
+                                .map(move |(entry_index, layer_color)| {
+                                    let color = if shadow { color } else { layer_color };
+                                    (alphabet.entries.get(entry_index), glyph, (i.x(), i.y(), i.rotation), None, color, transform, shadow)
+                                })
+                        });
+                        let quads = quad.into_iter().map(move |q| {
+                            // the unit quad is scaled by the transform, offsets are in the scaled space
+                            let size = q.size();
+                            (unit_quad, None, (q.min.x / size.x, q.min.y / size.y, 0.0), Some(size), color, transform, shadow)
+                        });
+                        items.chain(quads)
+                    };
+                    let shadows = group.shadow_items().flat_map(move |(draw, color)| entries(draw, color, shadow_transform, true));
+                    let items = group.draw_items().flat_map(move |(draw, color)| entries(draw, color, transform, false));
+
+                    shadows.chain(items).map(move |(entry, glyph, (x_offset, y_offset, rotation), size, color, transform, shadow)| {
+                        // items pointing past the end of a cleared alphabet draw nothing,
+                        // hidden glyphs keep their command with nothing to draw
+                        let (glyph_visible, glyph_y_offset) = group.glyph_effect_at(glyph);
+                        let (first_index, base_vertex, num_indices) = entry
+                            .filter(|_| glyph_visible)
+                            .map(|e| (e.first_index as u32, e.first_vertex as u32, e.index_count as u32))
+                            .unwrap_or((0, 0, 0));
+                        let (color, mut transform) = group.animated(color, transform);
+                        if let Some(size) = size {
+                            transform = na::Projective3::from_matrix_unchecked(
+                                transform.matrix() * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(size.x, size.y, 1.0))
+                            );
+                        }
+                        let texture = match group.texture {
+                            Some(ref texture) if !shadow => texture.id(),
+                            _ => 0,
+                        };
+
+                        (num_indices, first_index, base_vertex, x_offset, y_offset + glyph_y_offset, rotation, transform, color, group.blend_mode, group.style, shadow, texture, group.is_mirrored(), alphabet.visible && !group.anchor_behind_camera)
+                    })
+                })
+                .enumerate()
+                .map(move |(i, (num_indices, first_index, base_vertex, x_offset, y_offset, rotation, transform, color, blend_mode, style, shadow, texture, mirrored, visible))| FlatlanderGroupDrawData {
+                    cmd: DrawIndirectCmd {
+                        count: num_indices,
+                        prim_count: 1,
+                        first_index,
+                        base_vertex,
+                        base_instance: i as u32
+                    },
+                    blend_mode,
+                    style,
+                    x_offset,
+                    y_offset,
+                    rotation,
+                    transform,
+                    color,
+                    shadow,
+                    texture,
+                    mirrored,
+                    usage,
+                    visible
+                })
+        }
+
+        let unit_quad = self.shapes_alphabet.map(|slot| &self.alphabet_data[slot].entries[0]);
+        unpack(&self.group_data, &self.alphabet_data, unit_quad, usage)
+    }
+
+    /// Draw commands in group order, with neighbouring draws of the same texture and blend mode
+    /// merged into batches. Draws aren't reordered to merge more of them, so groups stay painted
+    /// in order, and `base_instance` keeps pointing at the group data uploaded in group order.
+    pub fn draw_commands(&self) -> (Vec<DrawIndirectCmd>, Vec<DrawBatch>) {
+        self.draw_commands_filtered(|_, _| true)
+    }
+
+    /// Draw commands for the groups matching `pred` only, still pointing at all uploaded group data.
+    ///
+    /// Shadows are moved in front of everything else with a stable sort, so they get batches of their own.
+    pub fn draw_commands_filtered(&self, pred: impl Fn(GroupSlot, &GroupData) -> bool) -> (Vec<DrawIndirectCmd>, Vec<DrawBatch>) {
+        // groups_draw_data walks the groups in the same order
+        let pred = &pred;
+        let included = [Usage::Static, Usage::Dynamic].iter()
+            .flat_map(|&usage| self.group_data.iter().filter(move |&(_, group)| group.usage == usage))
+            .flat_map(|(slot, group)| ::std::iter::repeat(pred(slot, group)).take(self.group_draw_len(group)));
+
+        let mut commands: Vec<((bool, Usage, u32, BlendMode, RenderStyle, bool), DrawIndirectCmd)> = self.groups_draw_data()
+            .zip(included)
+            .filter(|&(ref d, included)| d.visible && included)
+            .map(|(d, _)| ((!d.shadow, d.usage, d.texture, d.blend_mode, d.style, d.mirrored), d.cmd))
+            .collect();
+        commands.sort_by_key(|&((pass, ..), _)| pass);
+
+        let mut batches: Vec<DrawBatch> = Vec::new();
+        let mut previous_pass = None;
+        for (i, &((pass, usage, texture, blend_mode, style, mirrored), _)) in commands.iter().enumerate() {
+            let continues_batch = previous_pass == Some(pass) && batches.last()
+                .map(|b| b.usage == usage && b.texture == texture && b.blend_mode == blend_mode && b.style == style && b.mirrored == mirrored)
+                .unwrap_or(false);
+            previous_pass = Some(pass);
+
+            if continues_batch {
+                batches.last_mut().unwrap().command_count += 1;
+            } else {
+                batches.push(DrawBatch {
+                    usage,
+                    texture,
+                    blend_mode,
+                    style,
+                    mirrored,
+                    first_command: i,
+                    command_count: 1,
+                });
+            }
+        }
+
+        (commands.into_iter().map(|(_, cmd)| cmd).collect(), batches)
+    }
+
+    /// Layer called `name`, registered after the existing layers if there is none yet.
+    pub fn layer(&mut self, name: &str) -> LayerHandle {
+        if let Some(index) = self.layers.iter().position(|layer| layer.name == name) {
+            return LayerHandle { index };
+        }
+
+        self.layers.push(Layer { name: name.to_string(), enabled: true, groups: MetroHashSet::default() });
+        LayerHandle { index: self.layers.len() - 1 }
+    }
+
+    /// Moves the group to `layer`, out of the layer it was in.
+    pub fn add_to_layer(&mut self, layer: LayerHandle, slot: GroupSlot) {
+        if !self.group_data.contains_key(slot) {
+            return;
+        }
+        for other in &mut self.layers {
+            other.groups.remove(&slot);
+        }
+        self.layers[layer.index].groups.insert(slot);
+    }
 
+    /// Whether the group is in a layer that is enabled.
+    pub fn in_enabled_layer(&self, slot: GroupSlot) -> bool {
+        self.layers.iter().any(|layer| layer.enabled && layer.groups.contains(&slot))
+    }
+
+    /// Draw commands for the groups of one layer, like `draw_commands_filtered`.
+    pub fn layer_draw_commands(&self, layer: LayerHandle) -> (Vec<DrawIndirectCmd>, Vec<DrawBatch>) {
+        let groups = &self.layers[layer.index].groups;
+        self.draw_commands_filtered(|slot, _| groups.contains(&slot))
+    }
+
+    /// Groups that `draw_commands_filtered` keeps with `pred`, and those it leaves out.
+    pub fn cull_result(&self, pred: impl Fn(GroupSlot, &GroupData) -> bool) -> CullResult {
+        let visible = self.group_data.iter()
+            .filter(|&(slot, group)| self.alphabet_data[group.alphabet_slot].visible && !group.anchor_behind_camera && pred(slot, group))
+            .count();
+        CullResult { visible, culled: self.group_data.len() - visible }
+    }
+
+    pub fn create_flatland_group_with_items(&mut self, &transform: &na::Projective3<f32>, color: na::Vector4<u8>, alphabet_slot: AlphabetSlot, items: Vec<FlatlandItem>) -> GroupSlot {
+        self.debug_assert_items_in_alphabet(alphabet_slot, &items);
+
+        let slot = self.group_slots.insert(GroupSlotData { user_data: 0 });
+        self.group_data.insert(slot, GroupData {
+            transform,
+            pivot: na::Vector2::zeros(),
+            flip: (false, false),
+            flip_center: na::Vector2::zeros(),
+            blend_mode: BlendMode::Alpha,
+            style: RenderStyle::Fill,
+            alphabet_slot,
+            items,
+            item_colors: Vec::new(),
+            color,
+            decoration: Decoration::None,
+            decoration_baseline: DecorationBaseline::default(),
+            decoration_items: Vec::new(),
+            background: None,
+            background_item: None,
+            selection: None,
+            selection_items: Vec::new(),
+            shadow: None,
+            screen_anchor: None,
+            world_anchor: None,
+            anchor_behind_camera: false,
+            animation: None,
+            glyph_effect: None,
+            palette_cycle: None,
+            texture: None,
+            usage: Usage::Dynamic,
+            weight: 0.0,
+            base_alphabet_slot: alphabet_slot,
+            lod_levels: Vec::new(),
+            changed_generation: 0,
+        });
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
         self.draw_invalidated = true;
+
+        slot
     }
 
-    pub fn delete_flatland_group(&mut self, slot: GroupSlot) {
-        self.group_slots.remove(slot);
-        self.group_data.remove(slot);
+    pub fn update_items<'p>(&mut self, slot: GroupSlot, items: impl Iterator<Item = &'p FlatlandItem>) {
+        self.group_data[slot].items.clear();
+        self.group_data[slot].items.extend(items);
+        self.group_data[slot].item_colors.clear();
+        self.debug_assert_items_in_alphabet(self.group_data[slot].alphabet_slot, &self.group_data[slot].items);
+        self.touch_group(slot);
+
+        self.rebuild_generated_items(slot);
+        self.add_weighted_entries(slot);
 
         self.groups_invalidated = true;
         self.draw_invalidated = true;
     }
 
-    pub fn create_alphabet(&mut self) -> AlphabetSlot {
-        let slot = self.alphabet_slots.insert(AlphabetSlotData { count: 1 });
-        self.alphabet_data.insert(slot, AlphabetData::new());
-        slot
+    pub fn update_decoration(&mut self, slot: GroupSlot, decoration: Decoration) {
+        self.group_data[slot].decoration = decoration;
+        self.touch_group(slot);
+        self.rebuild_generated_items(slot);
     }
 
-    pub fn get_alphabet_entry_index(&self, slot: AlphabetSlot, id: u32) -> Option<usize> {
-        self.alphabet_data[slot].get_index(id)
+    pub fn update_decoration_baseline(&mut self, slot: GroupSlot, baseline: DecorationBaseline) {
+        self.group_data[slot].decoration_baseline = baseline;
+        self.touch_group(slot);
+        self.rebuild_generated_items(slot);
     }
 
-    pub fn add_alphabet_entry(&mut self, slot: AlphabetSlot, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
-        self.alphabets_invalidated = true;
-        self.alphabet_data_index_offsets_invalidated = true;
+    pub fn update_background(&mut self, slot: GroupSlot, background: Option<(na::Vector4<u8>, f32)>) {
+        self.group_data[slot].background = background;
+        self.touch_group(slot);
+        self.rebuild_generated_items(slot);
+    }
+
+    /// Highlights the items from `start` to `end`, in either order; an empty range removes the highlight.
+    pub fn update_selection(&mut self, slot: GroupSlot, start: usize, end: usize, color: na::Vector4<u8>) {
+        self.group_data[slot].selection = match (start.min(end), start.max(end)) {
+            (start, end) if start < end => Some((start, end, color)),
+            _ => None,
+        };
+        self.touch_group(slot);
+        self.rebuild_generated_items(slot);
+    }
+
+    /// Bounds of the group's glyphs and decorations, in the group's space.
+    pub fn group_bounds(&self, slot: GroupSlot) -> Option<GlyphBounds> {
+        let group = &self.group_data[slot];
+        let draws = group.items.iter().map(|i| GroupDraw::Item(i, None)).chain(group.decoration_items.iter().map(GroupDraw::Quad));
+        self.alphabet_data[group.alphabet_slot].draws_bounds(draws)
+    }
+
+    /// Whether a point in the transform's space is on a triangle of one of the group's glyphs.
+    ///
+    /// Only items whose bounds hold the point are tested triangle by triangle; entries
+    /// whose geometry was dropped after upload are never hit.
+    pub fn point_in_glyphs(&self, slot: GroupSlot, local: na::Vector2<f32>) -> bool {
+        let point = to_item_space(local);
+        let group = &self.group_data[slot];
+        let alphabet = &self.alphabet_data[group.alphabet_slot];
+
+        group.items.iter()
+            .filter(|item| alphabet.items_bounds(::std::iter::once(*item))
+                .map(|b| b.min.x <= point.x && point.x <= b.max.x && b.min.y <= point.y && point.y <= b.max.y)
+                .unwrap_or(false))
+            .any(|item| {
+                // into the entry's own space, undoing the offset and rotation
+                let (sin, cos) = item.rotation.sin_cos();
+                let d = point - item.position();
+                let p = na::Vector2::new(d.x * cos + d.y * sin, d.y * cos - d.x * sin);
+
+                alphabet.draw_entries(alphabet.weighted_entry(item.alphabet_entry_index, group.weight), group.color)
+                    .filter_map(|(entry, _)| alphabet.entries.get(entry))
+                    .any(|entry| {
+                        let position = |index: u16| {
+                            let pos = entry.vertices[index as usize].pos;
+                            na::Vector2::new(pos.d0.to_f32(), pos.d1.to_f32())
+                        };
+                        entry.indices.chunks(3)
+                            .filter(|t| t.len() == 3)
+                            .any(|t| point_in_triangle(p, position(t[0]), position(t[1]), position(t[2])))
+                    })
+            })
+    }
+
+    /// Pen position before the item at `char_index`, or after the last item past the end,
+    /// in the transform's space.
+    pub fn caret_position(&self, slot: GroupSlot, char_index: usize) -> na::Vector2<f32> {
+        let group = &self.group_data[slot];
+        match (group.items.get(char_index), group.items.last()) {
+            (Some(item), _) => to_item_space(item.position()),
+            (None, Some(last)) => {
+                let advance = self.alphabet_data[group.alphabet_slot].metrics.advance(last.alphabet_entry_index);
+                to_item_space(na::Vector2::new(last.x() + advance, last.y()))
+            },
+            (None, None) => na::Vector2::zeros(),
+        }
+    }
+
+    /// Index of the caret position closest to `local`, in the transform's space, on the line
+    /// with the baseline closest to it.
+    ///
+    /// Items sharing a `y_offset` form a line; the end of a line is the index of the next line's first item.
+    pub fn char_index_at(&self, slot: GroupSlot, local: na::Vector2<f32>) -> usize {
+        let local = to_item_space(local);
+        let group = &self.group_data[slot];
+        let metrics = &self.alphabet_data[group.alphabet_slot].metrics;
+        let closer = |a: f32, b: f32, to: f32| (a - to).abs().partial_cmp(&(b - to).abs()).unwrap_or(::std::cmp::Ordering::Equal);
+
+        let line_y = match group.items.iter().map(|i| i.y_offset).min_by(|&a, &b| closer(a as f32, b as f32, local.y * super::SUBPIXEL_SCALE as f32)) {
+            Some(y) => y,
+            None => return 0,
+        };
+
+        // caret boundaries before each item of the line, then after its last item
+        let line: Vec<(usize, &FlatlandItem)> = group.items.iter().enumerate().filter(|&(_, i)| i.y_offset == line_y).collect();
+        let &(last_index, last) = line.last().unwrap();
+        line.iter()
+            .map(|&(index, item)| (index, item.x()))
+            .chain(Some((last_index + 1, last.x() + metrics.advance(last.alphabet_entry_index))))
+            .min_by(|a, b| closer(a.1, b.1, local.x))
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    }
+
+    /// Screen size in pixels of the item's bounds, projected with the group transform and `vp`
+    /// onto a viewport of `viewport` pixels. Zero for unknown items or items without geometry.
+    pub fn group_item_pixel_size(&self, slot: GroupSlot, item_index: usize, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> na::Vector2<f32> {
+        let group = &self.group_data[slot];
+        match group.items.get(item_index).and_then(|item| self.alphabet_data[group.alphabet_slot].item_corners(item)) {
+            Some(corners) => projected_size(&group.placed_transform(), &corners, vp, viewport),
+            None => na::Vector2::zeros(),
+        }
+    }
+
+    /// Like `group_item_pixel_size`, for the bounds of the whole group.
+    pub fn group_pixel_size(&self, slot: GroupSlot, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> na::Vector2<f32> {
+        match self.group_bounds(slot) {
+            Some(b) => {
+                let corners = [b.min, na::Vector2::new(b.max.x, b.min.y), b.max, na::Vector2::new(b.min.x, b.max.y)];
+                projected_size(&self.group_data[slot].placed_transform(), &corners, vp, viewport)
+            },
+            None => na::Vector2::zeros(),
+        }
+    }
+
+    /// Replaces the group's levels of detail, keeping their alphabets alive while they are used.
+    ///
+    /// With no levels, the group goes back to the alphabet it was created with.
+    pub fn set_group_lod_alphabets(&mut self, slot: GroupSlot, mut levels: Vec<(f32, AlphabetSlot)>) {
+        levels.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+        for &(_, alphabet) in &levels {
+            self.inc_alphabet(alphabet);
+        }
+
+        let previous = ::std::mem::replace(&mut self.group_data[slot].lod_levels, levels);
+
+        // stop drawing from a previous level before its alphabet may be released
+        let group = &self.group_data[slot];
+        let current = group.alphabet_slot;
+        if current != group.base_alphabet_slot && !group.lod_levels.iter().any(|&(_, a)| a == current) {
+            let fallback = group.lod_levels.first().map(|&(_, a)| a).unwrap_or(group.base_alphabet_slot);
+            self.switch_group_alphabet(slot, fallback);
+        }
+        for (_, alphabet) in previous {
+            self.dec_alphabet(alphabet);
+        }
+    }
+
+    pub fn has_lod_groups(&self) -> bool {
+        self.group_data.values().any(|g| !g.lod_levels.is_empty())
+    }
+
+    /// Switches groups with levels of detail to the alphabet of the highest level
+    /// their apparent height reaches, or to the lowest level.
+    pub fn select_lod_alphabets(&mut self, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) {
+        let switches: Vec<(GroupSlot, AlphabetSlot)> = self.group_data.iter()
+            .filter(|&(_, group)| !group.lod_levels.is_empty())
+            .filter_map(|(slot, group)| {
+                let height = self.group_pixel_size(slot, vp, viewport).y;
+                let level = group.lod_levels.iter()
+                    .rev()
+                    .find(|&&(min_height, _)| height >= min_height)
+                    .unwrap_or(&group.lod_levels[0])
+                    .1;
+
+                if level != group.alphabet_slot { Some((slot, level)) } else { None }
+            })
+            .collect();
+
+        for (slot, alphabet) in switches {
+            self.switch_group_alphabet(slot, alphabet);
+        }
+    }
+
+    /// Draws the group's items from another alphabet with the same entry indices.
+    fn switch_group_alphabet(&mut self, slot: GroupSlot, alphabet: AlphabetSlot) {
+        if self.group_data[slot].alphabet_slot == alphabet {
+            return;
+        }
+
+        // generated rectangles follow the metrics of the new alphabet
+        self.group_data[slot].alphabet_slot = alphabet;
+        self.touch_group(slot);
+        self.rebuild_generated_items(slot);
+
         self.groups_invalidated = true;
         self.draw_invalidated = true;
+    }
+
+    fn rebuild_generated_items(&mut self, slot: GroupSlot) {
+        let group = &self.group_data[slot];
+        let has_decoration = group.decoration != Decoration::None || !group.decoration_items.is_empty();
+        let has_background = group.background.is_some() || group.background_item.is_some();
+        let has_selection = group.selection.is_some() || !group.selection_items.is_empty();
 
-        self.total_alphabet_vertices += vertices.len();
-        self.total_alphabet_indices += indices.len();
-        self.alphabet_data[slot].add(id, vertices, indices)
+        if has_decoration {
+            self.rebuild_decoration_items(slot);
+        }
+        if has_selection {
+            self.rebuild_selection_items(slot);
+        }
+        if has_background {
+            self.rebuild_background_item(slot);
+        }
+        if self.group_data[slot].flip != (false, false) {
+            self.update_flip_center(slot);
+        }
     }
 
-    pub fn inc_alphabet(&mut self, slot: AlphabetSlot) {
-        self.alphabet_slots[slot].count += 1;
+    fn update_flip_center(&mut self, slot: GroupSlot) {
+        self.group_data[slot].flip_center = self.pivot_point(slot, Pivot::Center);
     }
 
-    pub fn dec_alphabet(&mut self, slot: AlphabetSlot) {
-        self.alphabet_slots[slot].count -= 1;
+    fn rebuild_background_item(&mut self, slot: GroupSlot) {
+        let background = self.group_data[slot].background;
 
-        if self.alphabet_slots[slot].count <= 0 {
-            self.delete_alphabet(slot);
+        let background_item = match (background, self.group_bounds(slot)) {
+            (Some((_, padding)), Some(bounds)) => {
+                let padding = na::Vector2::new(padding, padding);
+                QuadItem::new(bounds.min - padding, bounds.max + padding)
+            },
+            _ => None,
+        };
+        if background_item.is_some() {
+            self.ensure_unit_quad();
         }
+
+        self.group_data[slot].background_item = background_item;
+
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
     }
 
-    pub fn delete_alphabet(&mut self, slot: AlphabetSlot) {
-        self.alphabet_slots.remove(slot);
-        let data = self.alphabet_data.remove(slot).expect("expected to remove data when removing the alphabet");
-        self.total_alphabet_vertices -= data.total_vertices;
-        self.total_alphabet_indices -= data.total_indices;
+    /// Replaces the selection items with a rectangle over the selected items of each line,
+    /// as high as the tallest glyph of the group.
+    fn rebuild_selection_items(&mut self, slot: GroupSlot) {
+        let (lines, extent) = {
+            let group = &self.group_data[slot];
+            let metrics = &self.alphabet_data[group.alphabet_slot].metrics;
+            let extent = group.items.iter()
+                .filter_map(|i| metrics.bounds(i.alphabet_entry_index))
+                .fold(None, |extent, b| match extent {
+                    None => Some((b.min.y, b.max.y)),
+                    Some((bottom, top)) => Some((b.min.y.min(bottom), b.max.y.max(top))),
+                });
+
+            // start, width and baseline of each line's part of the selection
+            let mut lines = Vec::new();
+            if let Some((start, end, _)) = group.selection {
+                let end = end.min(group.items.len());
+                let mut index = start;
+                while index < end {
+                    let first = &group.items[index];
+                    let line_end = (index..end).find(|&i| group.items[i].y_offset != first.y_offset).unwrap_or(end);
+                    let last = &group.items[line_end - 1];
+                    let right = last.x() + metrics.advance(last.alphabet_entry_index);
+                    lines.push((first.x(), right - first.x(), first.y()));
+                    index = line_end;
+                }
+            }
+
+            (lines, extent)
+        };
+
+        let selection_items: Vec<QuadItem> = match extent {
+            Some((bottom, top)) => lines.into_iter()
+                .filter_map(|(x, width, y)| QuadItem::new(na::Vector2::new(x, y + bottom), na::Vector2::new(x + width, y + top)))
+                .collect(),
+            None => Vec::new(),
+        };
+        if !selection_items.is_empty() {
+            self.ensure_unit_quad();
+        }
+
+        self.group_data[slot].selection_items = selection_items;
+
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    /// Replaces the decoration items with lines spanning the group's current items.
+    fn rebuild_decoration_items(&mut self, slot: GroupSlot) {
+        let (decoration, baseline, span) = {
+            let group = &self.group_data[slot];
+            let span = self.alphabet_data[group.alphabet_slot].items_span(&group.items);
+            (group.decoration, group.decoration_baseline, span)
+        };
+
+        let lines = match decoration {
+            Decoration::None => vec![],
+            Decoration::Underline { thickness } => vec![(baseline.underline, thickness)],
+            Decoration::Strikethrough { thickness } => vec![(baseline.strikethrough, thickness)],
+            Decoration::UnderlineAndStrikethrough { thickness } => vec![
+                (baseline.underline, thickness),
+                (baseline.strikethrough, thickness),
+            ],
+        };
+
+        let decoration_items: Vec<QuadItem> = match span {
+            Some((start, end)) => lines.into_iter()
+                .filter_map(|(y, thickness)| QuadItem::new(
+                    na::Vector2::new(start, y - thickness * 0.5),
+                    na::Vector2::new(end, y + thickness * 0.5),
+                ))
+                .collect(),
+            None => Vec::new(),
+        };
+        if !decoration_items.is_empty() {
+            self.ensure_unit_quad();
+        }
+
+        self.group_data[slot].decoration_items = decoration_items;
+
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    pub fn update_transform(&mut self, slot: GroupSlot, &transform: &na::Projective3<f32>) {
+        self.group_data[slot].transform = transform;
+        self.touch_group(slot);
+
+        // the transform is part of the uploaded group data, not of the draw commands
+        self.groups_invalidated = true;
+    }
+
+    pub fn update_screen_anchor(&mut self, slot: GroupSlot, anchor: Option<(Anchor, na::Vector2<f32>)>) {
+        self.group_data[slot].screen_anchor = anchor;
+        self.place_on_screen(slot);
+    }
+
+    /// Moves every anchored group to its anchor on a screen of `size`.
+    pub fn set_viewport_size(&mut self, size: na::Vector2<f32>) {
+        if self.viewport_size == size {
+            return;
+        }
+
+        self.viewport_size = size;
+        let anchored: Vec<GroupSlot> = self.group_data.iter()
+            .filter(|&(_, group)| group.screen_anchor.is_some())
+            .map(|(slot, _)| slot)
+            .collect();
+        for slot in anchored {
+            self.place_on_screen(slot);
+        }
+    }
+
+    fn place_on_screen(&mut self, slot: GroupSlot) {
+        let (anchor, offset) = match self.group_data[slot].screen_anchor {
+            Some(anchor) => anchor,
+            None => return,
+        };
+
+        let position = anchor.position(self.viewport_size) + offset;
+        {
+            let matrix = self.group_data[slot].transform.matrix_mut_unchecked();
+            matrix[(0, 3)] = position.x;
+            matrix[(1, 3)] = position.y;
+        }
+        self.touch_group(slot);
 
-        self.alphabets_invalidated = true;
-        self.alphabet_data_index_offsets_invalidated = true;
         self.groups_invalidated = true;
+    }
+
+    pub fn update_world_anchor(&mut self, slot: GroupSlot, anchor: Option<na::Point3<f32>>) {
+        let group = &mut self.group_data[slot];
+        group.world_anchor = anchor;
+        if anchor.is_none() && group.anchor_behind_camera {
+            group.anchor_behind_camera = false;
+            self.draw_invalidated = true;
+        }
+    }
+
+    /// Moves every group with a world anchor to where `vp` projects it on a `viewport` sized
+    /// screen, in pixels from the top-left corner, and hides those whose anchor is behind the camera.
+    ///
+    /// Groups that didn't move aren't uploaded again.
+    pub fn update_anchored_groups(&mut self, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) {
+        let mut visibility_changed = false;
+        let mut moved = Vec::new();
+
+        for (slot, group) in self.group_data.iter_mut() {
+            let anchor = match group.world_anchor {
+                Some(anchor) => anchor,
+                None => continue,
+            };
+
+            let clip = vp * na::Vector4::new(anchor.x, anchor.y, anchor.z, 1.0);
+            let behind_camera = clip.w <= 0.0;
+            if behind_camera != group.anchor_behind_camera {
+                group.anchor_behind_camera = behind_camera;
+                visibility_changed = true;
+            }
+            if behind_camera {
+                continue;
+            }
+
+            let position = na::Vector2::new(
+                (clip.x / clip.w * 0.5 + 0.5) * viewport.x,
+                (0.5 - clip.y / clip.w * 0.5) * viewport.y,
+            );
+            let matrix = group.transform.matrix_mut_unchecked();
+            if (matrix[(0, 3)], matrix[(1, 3)]) != (position.x, position.y) {
+                matrix[(0, 3)] = position.x;
+                matrix[(1, 3)] = position.y;
+                moved.push(slot);
+            }
+        }
+
+        if !moved.is_empty() {
+            for slot in moved {
+                self.touch_group(slot);
+            }
+            self.groups_invalidated = true;
+        }
+        if visibility_changed {
+            self.draw_invalidated = true;
+        }
+    }
+
+    /// Applies all transforms at once, skipping groups that no longer exist.
+    pub fn update_transforms(&mut self, updates: &[(GroupSlot, na::Projective3<f32>)]) {
+        for &(slot, transform) in updates {
+            if let Some(group) = self.group_data.get_mut(slot) {
+                self.generation += 1;
+                group.transform = transform;
+                group.changed_generation = self.generation;
+            }
+        }
+
+        self.groups_invalidated = true;
+    }
+
+    /// Moves the group's instance data to the buffer for `usage`.
+    pub fn update_usage(&mut self, slot: GroupSlot, usage: Usage) {
+        if self.group_data[slot].usage == usage {
+            return;
+        }
+
+        self.group_data[slot].usage = usage;
+        self.touch_group(slot);
+        self.static_groups_changed = true;
+
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    pub fn update_texture(&mut self, slot: GroupSlot, texture: Option<Texture>) {
+        self.group_data[slot].texture = texture;
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    pub fn update_pivot(&mut self, slot: GroupSlot, pivot: na::Vector2<f32>) {
+        self.group_data[slot].pivot = pivot;
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+    }
+
+    /// The pivot of the group's current contents, in the transform's space.
+    pub fn pivot_point(&self, slot: GroupSlot, pivot: Pivot) -> na::Vector2<f32> {
+        let point = match pivot {
+            Pivot::Origin => na::Vector2::zeros(),
+            Pivot::Center => self.group_bounds(slot).map(|b| (b.min + b.max) * 0.5).unwrap_or(na::Vector2::zeros()),
+            Pivot::BaselineStart => self.group_data[slot].items.first()
+                .map(|i| i.position())
+                .unwrap_or(na::Vector2::zeros()),
+        };
+        to_item_space(point)
+    }
+
+    /// Replaces the scale of the group's transform, about the center of its bounds,
+    /// which stays where it is drawn.
+    pub fn update_scale(&mut self, slot: GroupSlot, sx: f32, sy: f32) {
+        let center = self.pivot_point(slot, Pivot::Center);
+        let group = &mut self.group_data[slot];
+        // placed_transform applies the transform about the pivot
+        let point = na::Point3::new(center.x - group.pivot.x, center.y - group.pivot.y, 0.0);
+        let scaled = with_scale(&group.transform, sx, sy);
+        let shift = group.transform * point - scaled * point;
+        group.transform = na::Projective3::from_matrix_unchecked(na::Matrix4::new_translation(&shift) * scaled.matrix());
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+    }
+
+    pub fn update_color(&mut self, slot: GroupSlot, color: na::Vector4<u8>) {
+        self.group_data[slot].color = color;
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+    }
+
+    pub fn update_item_colors(&mut self, slot: GroupSlot, colors: Vec<na::Vector4<u8>>) {
+        self.group_data[slot].item_colors = colors;
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+    }
+
+    /// Colors each item with `f` of its offset, in the transform's space like `caret_position`.
+    pub fn apply_color_gradient(&mut self, slot: GroupSlot, f: impl Fn(na::Vector2<f32>) -> na::Vector4<u8>) {
+        let colors = self.group_data[slot].items.iter()
+            .map(|i| f(to_item_space(i.position())))
+            .collect();
+        self.update_item_colors(slot, colors);
+    }
+
+    /// Mirrors the group about the center of its bounds; mirrored draws are batched apart
+    /// and drawn with the other front face, so they still pass face culling.
+    pub fn update_flip(&mut self, slot: GroupSlot, flip_x: bool, flip_y: bool) {
+        self.group_data[slot].flip = (flip_x, flip_y);
+        self.update_flip_center(slot);
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    pub fn update_blend_mode(&mut self, slot: GroupSlot, blend_mode: BlendMode) {
+        self.group_data[slot].blend_mode = blend_mode;
+        self.touch_group(slot);
+
         self.draw_invalidated = true;
     }
+
+    pub fn update_animation(&mut self, slot: GroupSlot, animation: Option<GroupAnimation>) {
+        self.group_data[slot].animation = animation.map(|a| (a, 0.0));
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+    }
+
+    pub fn update_weight(&mut self, slot: GroupSlot, weight: f32) {
+        self.group_data[slot].weight = weight.max(0.0).min(1.0);
+        self.touch_group(slot);
+        self.add_weighted_entries(slot);
+
+        // the weight picks the entries the draw commands point at
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    /// Generates the entries of the group's weight step for its interpolable items that have none yet.
+    fn add_weighted_entries(&mut self, slot: GroupSlot) {
+        let group = &self.group_data[slot];
+        let (alphabet_slot, step) = (group.alphabet_slot, weight_step(group.weight));
+        if step == 0 || self.alphabet_data[alphabet_slot].interpolable.is_empty() {
+            return;
+        }
+
+        let alphabet = &self.alphabet_data[alphabet_slot];
+        let mut missing: Vec<usize> = group.items.iter()
+            .map(|i| i.alphabet_entry_index)
+            .filter(|index| alphabet.interpolable.contains_key(index) && !alphabet.weighted.contains_key(&(*index, step)))
+            .collect();
+        missing.sort();
+        missing.dedup();
+
+        for index in missing {
+            self.add_weighted_entry(alphabet_slot, index, step);
+        }
+    }
+
+    fn add_weighted_entry(&mut self, slot: AlphabetSlot, index: usize, step: usize) {
+        let (vertices, indices) = {
+            let (ref regular, ref bold, ref indices) = self.alphabet_data[slot].interpolable[&index];
+            (lerp_vertices(regular, bold, step as f32 / WEIGHT_STEPS as f32), indices.clone())
+        };
+        let weighted = self.add_alphabet_shape(slot, vertices, indices);
+        self.alphabet_data[slot].weighted.insert((index, step), weighted);
+    }
+
+    pub fn update_glyph_effect(&mut self, slot: GroupSlot, effect: Option<GlyphEffect>) {
+        self.group_data[slot].glyph_effect = effect.map(|e| (e, 0.0));
+        self.touch_group(slot);
+
+        // typewriter visibility is the index count of each draw command
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    /// Sets the group color to the first color of `palette` and cycles it through the others
+    /// in `advance_animations`. An empty palette changes nothing.
+    pub fn update_palette_cycle(&mut self, slot: GroupSlot, palette: Vec<na::Vector4<u8>>, period: f32) {
+        if palette.is_empty() {
+            return;
+        }
+
+        self.update_color(slot, palette[0]);
+        self.group_data[slot].palette_cycle = Some((palette, period, 0.0));
+    }
+
+    /// Stops cycling, keeping the current color.
+    pub fn clear_palette_cycle(&mut self, slot: GroupSlot) {
+        self.group_data[slot].palette_cycle = None;
+    }
+
+    pub fn is_group_animating(&self, slot: GroupSlot) -> bool {
+        self.group_data[slot].is_animating()
+    }
+
+    /// Moves running animations forward, leaving groups whose animation ended untouched.
+    pub fn advance_animations(&mut self, dt: f32) {
+        let mut changed = false;
+        let mut commands_changed = false;
+        for group in self.group_data.values_mut() {
+            let animating = group.is_animating();
+            let glyph_effect_playing = group.is_glyph_effect_playing();
+            if animating {
+                if let Some((_, ref mut elapsed)) = group.animation {
+                    *elapsed += dt;
+                }
+            }
+            if glyph_effect_playing {
+                if let Some((_, ref mut elapsed)) = group.glyph_effect {
+                    *elapsed += dt;
+                }
+                commands_changed = true;
+            }
+            // only uploaded again when the cycle steps to another color
+            let mut palette_stepped = false;
+            if let Some((ref palette, period, ref mut elapsed)) = group.palette_cycle {
+                if palette.len() > 1 && period > 0.0 {
+                    *elapsed = (*elapsed + dt) % period;
+                    let step = ((*elapsed / period * palette.len() as f32) as usize).min(palette.len() - 1);
+                    if group.color != palette[step] {
+                        group.color = palette[step];
+                        palette_stepped = true;
+                    }
+                }
+            }
+            if animating || glyph_effect_playing || palette_stepped {
+                self.generation += 1;
+                group.changed_generation = self.generation;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.groups_invalidated = true;
+        }
+        // glyphs the typewriter reaches get their index counts back
+        if commands_changed {
+            self.draw_invalidated = true;
+        }
+    }
+
+    pub fn update_shadow(&mut self, slot: GroupSlot, shadow: Option<(na::Vector2<f32>, na::Vector4<u8>)>) {
+        self.group_data[slot].shadow = shadow;
+        self.touch_group(slot);
+
+        self.groups_invalidated = true;
+        self.draw_invalidated = true;
+    }
+
+    pub fn update_style(&mut self, slot: GroupSlot, style: RenderStyle) {
+        self.group_data[slot].style = style;
+        self.touch_group(slot);
+
+        self.draw_invalidated = true;
+    }
+
+    fn touch_group(&mut self, slot: GroupSlot) {
+        self.generation += 1;
+        self.group_data[slot].changed_generation = self.generation;
+    }
+
+    /// Groups changed after `generation`, and the current generation to pass next time.
+    ///
+    /// Deleted groups aren't reported.
+    pub fn groups_changed_since(&self, generation: u64) -> (Vec<GroupSlot>, u64) {
+        let changed = self.group_data.iter()
+            .filter(|&(_, group)| group.changed_generation > generation)
+            .map(|(slot, _)| slot)
+            .collect();
+        (changed, self.generation)
+    }
+
+    /// Screen rectangle, as x, y, width and height in pixels from the bottom-left corner, covering
+    /// where groups changed since the last call were drawn before and are drawn now, clamped to
+    /// the viewport. The whole viewport if the camera changed, `None` if nothing changed.
+    pub fn take_dirty_screen_rect(&mut self, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> Option<na::Vector4<f32>> {
+        let camera_changed = self.dirty_camera != Some((*vp, viewport));
+        let (changed, generation) = match camera_changed {
+            true => (self.group_data.keys().collect(), self.generation),
+            false => self.groups_changed_since(self.dirty_generation),
+        };
+
+        // deleted groups leave the rectangle they were drawn in behind
+        let mut dirty = None;
+        let removed: Vec<GroupSlot> = self.screen_rects.keys()
+            .filter(|&&slot| !self.group_data.contains_key(slot))
+            .cloned()
+            .collect();
+        for slot in removed {
+            dirty = union_rects(dirty, self.screen_rects.remove(&slot));
+        }
+
+        for slot in changed {
+            let rect = self.group_screen_rect(slot, vp, viewport);
+            dirty = union_rects(dirty, union_rects(self.screen_rects.get(&slot).cloned(), rect));
+            match rect {
+                Some(rect) => self.screen_rects.insert(slot, rect),
+                None => self.screen_rects.remove(&slot),
+            };
+        }
+
+        self.dirty_generation = generation;
+        self.dirty_camera = Some((*vp, viewport));
+
+        let (min, max) = match (camera_changed, dirty) {
+            (true, _) => (na::Vector2::zeros(), viewport),
+            (false, Some((min, max))) => (
+                na::Vector2::new(min.x.floor().max(0.0), min.y.floor().max(0.0)),
+                na::Vector2::new(max.x.ceil().min(viewport.x), max.y.ceil().min(viewport.y)),
+            ),
+            (false, None) => return None,
+        };
+        if max.x <= min.x || max.y <= min.y {
+            return None;
+        }
+        Some(na::Vector4::new(min.x, min.y, max.x - min.x, max.y - min.y))
+    }
+
+    /// Screen rectangle of everything drawn for the group, with its animation, wave and shadow.
+    fn group_screen_rect(&self, slot: GroupSlot, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> Option<(na::Vector2<f32>, na::Vector2<f32>)> {
+        let group = &self.group_data[slot];
+        let mut bounds = self.alphabet_data[group.alphabet_slot].draws_bounds(group.draw_items().map(|(draw, _)| draw))?;
+        if let Some((GlyphEffect::Wave { amplitude, .. }, _)) = group.glyph_effect {
+            bounds.min.y -= amplitude.abs();
+            bounds.max.y += amplitude.abs();
+        }
+
+        let corners = [bounds.min, na::Vector2::new(bounds.max.x, bounds.min.y), bounds.max, na::Vector2::new(bounds.min.x, bounds.max.y)];
+        let rect = |transform| projected_rect(&group.animated(group.color, transform).1, &corners, vp, viewport);
+        let shadow = group.shadow.map(|_| rect(group.shadow_transform()));
+        union_rects(Some(rect(group.placed_transform())), shadow)
+    }
+
+    pub fn set_group_user_data(&mut self, slot: GroupSlot, user_data: u64) {
+        self.group_slots[slot].user_data = user_data;
+    }
+
+    pub fn group_user_data(&self, slot: GroupSlot) -> u64 {
+        self.group_slots[slot].user_data
+    }
+
+    /// New group with the items, transform and color of `slot` and the alphabet it was created with.
+    ///
+    /// The caller holds a reference to the alphabet for the new group.
+    pub fn duplicate_flatland_group(&mut self, slot: GroupSlot) -> GroupSlot {
+        let (transform, color, alphabet_slot, items) = {
+            let group = &self.group_data[slot];
+            (group.transform, group.color, group.base_alphabet_slot, group.items.clone())
+        };
+        self.create_flatland_group_with_items(&transform, color, alphabet_slot, items)
+    }
+
+    /// Does nothing for groups already deleted by id, see `delete_flatland_groups`.
+    pub fn delete_flatland_group(&mut self, slot: GroupSlot) {
+        if self.remove_group(slot) {
+            self.groups_invalidated = true;
+            self.draw_invalidated = true;
+        }
+    }
+
+    /// Deletes all listed groups at once; unknown or already deleted ids are skipped.
+    pub fn delete_flatland_groups(&mut self, slots: &[GroupSlot]) {
+        let mut removed = false;
+        for &slot in slots {
+            removed |= self.remove_group(slot);
+        }
+
+        if removed {
+            self.groups_invalidated = true;
+            self.draw_invalidated = true;
+        }
+    }
+
+    /// Replaces the groups with one group of their items and decorations, placed by their transforms.
+    ///
+    /// The new group has the identity transform and the color, blend mode and style of the
+    /// first group. All groups must exist and share an alphabet, which the decorations are
+    /// added to as shapes, since the frozen group has no decoration of its own.
+    pub fn freeze_groups(&mut self, slots: &[GroupSlot]) -> GroupSlot {
+        let first = &self.group_data[slots[0]];
+        let (color, blend_mode, style, alphabet_slot) = (first.color, first.blend_mode, first.style, first.base_alphabet_slot);
+
+        let mut items = Vec::new();
+        for &slot in slots {
+            let (transform, decorations) = {
+                let group = &self.group_data[slot];
+                assert_eq!(alphabet_slot, group.base_alphabet_slot, "frozen groups must share an alphabet");
+                let transform = group.placed_transform();
+                items.extend(group.items.iter().map(|item| frozen_item(&transform, item)));
+                (transform, group.decoration_items.clone())
+            };
+
+            for decoration in decorations {
+                let (vertices, indices) = quad(na::Vector2::zeros(), decoration.size());
+                let entry = self.add_alphabet_shape(alphabet_slot, vertices, indices);
+                let item = FlatlandItem::new(entry, decoration.min.x, decoration.min.y);
+                items.push(frozen_item(&transform, &item));
+            }
+        }
+
+        self.delete_flatland_groups(slots);
+
+        let frozen = self.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet_slot, items);
+        self.group_data[frozen].blend_mode = blend_mode;
+        self.group_data[frozen].style = style;
+        frozen
+    }
+
+    fn remove_group(&mut self, slot: GroupSlot) -> bool {
+        if self.group_slots.remove(slot).is_none() {
+            return false;
+        }
+        for layer in &mut self.layers {
+            layer.groups.remove(&slot);
+        }
+        if let Some(group) = self.group_data.remove(slot) {
+            if group.usage == Usage::Static {
+                self.static_groups_changed = true;
+            }
+            for (_, alphabet) in group.lod_levels {
+                self.dec_alphabet(alphabet);
+            }
+        }
+        true
+    }
+
+    pub fn create_alphabet(&mut self) -> AlphabetSlot {
+        let slot = self.alphabet_slots.insert(AlphabetSlotData { count: 1 });
+        self.alphabet_data.insert(slot, AlphabetData::new());
+        slot
+    }
+
+    /// Makes sure the unit quad generated rectangles are drawn with exists.
+    fn ensure_unit_quad(&mut self) {
+        if self.shapes_alphabet.is_none() {
+            let slot = self.create_alphabet();
+            let (vertices, indices) = quad(na::Vector2::zeros(), na::Vector2::new(1.0, 1.0));
+            self.place_alphabet_entry(slot, vertices, indices, 0.0);
+            self.shapes_alphabet = Some(slot);
+        }
+    }
+
+    pub fn get_alphabet_entry_index(&self, slot: AlphabetSlot, id: u32) -> Option<usize> {
+        self.alphabet_data[slot].get_index(id)
+    }
+
+    /// Ids and their entry indices, sorted by entry index and then id.
+    pub fn alphabet_entry_ids(&self, slot: AlphabetSlot) -> Vec<(u32, usize)> {
+        let mut ids: Vec<(u32, usize)> = self.alphabet_data[slot].map.iter().map(|(&id, &index)| (id, index)).collect();
+        ids.sort_by_key(|&(id, index)| (index, id));
+        ids
+    }
+
+    pub fn add_alphabet_entry(&mut self, slot: AlphabetSlot, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
+        self.add_alphabet_entry_with_advance(slot, id, vertices, indices, 0.0)
+    }
+
+    pub fn add_alphabet_entry_with_advance(&mut self, slot: AlphabetSlot, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>, advance: f32) -> usize {
+        self.assert_not_frozen(slot);
+
+        let index = self.place_alphabet_entry(slot, vertices, indices, advance);
+        self.alphabet_data[slot].map.insert(id, index);
+        index
+    }
+
+    /// Adds an entry drawn as colored layers, in order. Each layer color is multiplied by the group color.
+    pub fn add_layered_alphabet_entry(&mut self, slot: AlphabetSlot, id: u32, layers: Vec<(na::Vector4<u8>, Vec<FlatlanderVertex>, Vec<u16>)>, advance: f32) -> usize {
+        self.assert_not_frozen(slot);
+
+        let all_vertices: Vec<FlatlanderVertex> = layers.iter()
+            .flat_map(|&(_, ref vertices, _)| vertices.iter().cloned())
+            .collect();
+        let entry_layers: Vec<(na::Vector4<u8>, usize)> = layers.into_iter()
+            .map(|(color, vertices, indices)| (color, self.add_alphabet_shape(slot, vertices, indices)))
+            .collect();
+
+        // the entry itself has no geometry, only the metrics of all its layers
+        let index = self.place_alphabet_entry(slot, Vec::new(), Vec::new(), advance);
+        let data = &mut self.alphabet_data[slot];
+        Rc::make_mut(&mut data.metrics).bounds[index] = geometry_bounds(&all_vertices);
+        data.layers.insert(index, entry_layers);
+        data.map.insert(id, index);
+        index
+    }
+
+    /// Adds the regular geometry as the entry for `id`, keeping the bold vertices to generate
+    /// geometry for other weights. Both must have the same number of vertices.
+    pub fn add_interpolable_alphabet_entry(&mut self, slot: AlphabetSlot, id: u32, regular: (Vec<FlatlanderVertex>, Vec<u16>), bold: Vec<FlatlanderVertex>, advance: f32) -> usize {
+        assert_eq!(regular.0.len(), bold.len(), "regular and bold vertices of an interpolable entry must match");
+
+        let (vertices, indices) = regular;
+        let index = self.add_alphabet_entry_with_advance(slot, id, vertices.clone(), indices.clone(), advance);
+        self.alphabet_data[slot].interpolable.insert(index, (vertices, bold, indices));
+        index
+    }
+
+    /// Adds geometry not looked up by id, reusing an entry with identical geometry if there is one.
+    pub fn add_alphabet_shape(&mut self, slot: AlphabetSlot, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
+        let key = geometry_hash(&vertices, &indices);
+        if let Some(index) = self.alphabet_data[slot].get_deduped_index(key, &vertices, &indices) {
+            return index;
+        }
+
+        let index = self.place_alphabet_entry(slot, vertices, indices, 0.0);
+        self.alphabet_data[slot].dedup.insert(key, index);
+        index
+    }
+
+    fn place_alphabet_entry(&mut self, slot: AlphabetSlot, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>, advance: f32) -> usize {
+        self.assert_not_frozen(slot);
+        self.alphabets_invalidated = true;
+        self.draw_invalidated = true;
+
+        let first_vertex = self.free_alphabet_vertices.take(vertices.len()).unwrap_or_else(|| {
+            self.alphabet_vertices_end += vertices.len();
+            self.alphabet_vertices_end - vertices.len()
+        });
+        let first_index = self.free_alphabet_indices.take(indices.len()).unwrap_or_else(|| {
+            self.alphabet_indices_end += indices.len();
+            self.alphabet_indices_end - indices.len()
+        });
+        self.alphabet_data[slot].add(vertices, indices, advance, first_vertex, first_index)
+    }
+
+    pub fn add_alphabet_entry_deduped(&mut self, slot: AlphabetSlot, key: u64, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
+        self.assert_not_frozen(slot);
+
+        if let Some(index) = self.alphabet_data[slot].get_deduped_index(key, &vertices, &indices) {
+            self.alphabet_data[slot].map.insert(id, index);
+            return index;
+        }
+
+        let index = self.add_alphabet_entry(slot, id, vertices, indices);
+        self.alphabet_data[slot].dedup.insert(key, index);
+        index
+    }
+
+    /// Adds the geometry as an entry and makes it the alphabet's fallback entry. Its advance is
+    /// the right edge of the geometry plus the gap left of it, so the glyph sits centered.
+    pub fn set_alphabet_fallback_glyph(&mut self, slot: AlphabetSlot, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
+        self.assert_not_frozen(slot);
+
+        let advance = geometry_bounds(&vertices).map(|b| b.max.x + b.min.x).unwrap_or(0.0);
+        let index = self.place_alphabet_entry(slot, vertices, indices, advance);
+        self.alphabet_data[slot].fallback_entry = Some(index);
+        index
+    }
+
+    pub fn alphabet_vertex_count(&self, slot: AlphabetSlot) -> usize {
+        self.alphabet_data[slot].total_vertices
+    }
+
+    /// Copies the entries looked up by id into new alphabets of at most `max_vertices` each,
+    /// in entry order, keeping their ids, advances, layers and the kerning between entries
+    /// that end up in the same alphabet. Shapes only used for decorations are left out.
+    ///
+    /// Panics if the geometry of an entry was dropped after upload, or doesn't fit on its own.
+    pub fn split_alphabet(&mut self, slot: AlphabetSlot, max_vertices: usize) -> Vec<AlphabetSlot> {
+        let ids = self.alphabet_entry_ids(slot);
+        let mut parts: Vec<(AlphabetSlot, usize, MetroHashMap<usize, usize>)> = Vec::new();
+
+        let mut start = 0;
+        while start < ids.len() {
+            let index = ids[start].1;
+            let aliases: Vec<u32> = ids[start..].iter()
+                .take_while(|&&(_, i)| i == index)
+                .map(|&(id, _)| id)
+                .collect();
+            start += aliases.len();
+
+            let copy = self.copy_alphabet_entry(slot, index);
+            let vertex_count = copy.vertex_count();
+            assert!(vertex_count <= max_vertices, "entry {} has more than {} vertices", index, max_vertices);
+
+            let starts_part = parts.last().map(|&(_, count, _)| count + vertex_count > max_vertices).unwrap_or(true);
+            if starts_part {
+                parts.push((self.create_alphabet(), 0, MetroHashMap::default()));
+            }
+            let part = parts.last_mut().unwrap();
+
+            let advance = self.alphabet_data[slot].metrics.advance(index);
+            let new_index = match copy {
+                EntryCopy::Plain(vertices, indices) => self.add_alphabet_entry_with_advance(part.0, aliases[0], vertices, indices, advance),
+                EntryCopy::Layered(layers) => self.add_layered_alphabet_entry(part.0, aliases[0], layers, advance),
+            };
+            for &id in &aliases[1..] {
+                self.alphabet_data[part.0].map.insert(id, new_index);
+            }
+            part.1 += vertex_count;
+            part.2.insert(index, new_index);
+        }
+
+        let kerning: Vec<((usize, usize), f32)> = self.alphabet_data[slot].metrics.kerning.iter()
+            .map(|(&pair, &adjustment)| (pair, adjustment))
+            .collect();
+        for &(part, _, ref indices) in &parts {
+            for &((left, right), adjustment) in &kerning {
+                if let (Some(&left), Some(&right)) = (indices.get(&left), indices.get(&right)) {
+                    self.set_alphabet_kerning(part, left, right, adjustment);
+                }
+            }
+        }
+
+        parts.into_iter().map(|(part, _, _)| part).collect()
+    }
+
+    fn copy_alphabet_entry(&self, slot: AlphabetSlot, index: usize) -> EntryCopy {
+        let data = &self.alphabet_data[slot];
+        let geometry = |index: usize| {
+            let entry = &data.entries[index];
+            assert!(entry.is_retained(), "geometry of entry {} was dropped after upload", index);
+            (entry.vertices.clone(), entry.indices.clone())
+        };
+
+        match data.layers.get(&index) {
+            None => {
+                let (vertices, indices) = geometry(index);
+                EntryCopy::Plain(vertices, indices)
+            },
+            Some(layers) => EntryCopy::Layered(layers.iter()
+                .map(|&(color, layer)| {
+                    let (vertices, indices) = geometry(layer);
+                    (color, vertices, indices)
+                })
+                .collect()),
+        }
+    }
+
+    pub fn set_alphabet_kerning(&mut self, slot: AlphabetSlot, left: usize, right: usize, adjustment: f32) {
+        self.assert_not_frozen(slot);
+
+        Rc::make_mut(&mut self.alphabet_data[slot].metrics).kerning.insert((left, right), adjustment);
+    }
+
+    pub fn alphabet_metrics(&self, slot: AlphabetSlot) -> &Rc<GlyphMetrics> {
+        &self.alphabet_data[slot].metrics
+    }
+
+    /// Items of `text` on a single line of the alphabet's glyphs, with the characters missing
+    /// from `char_to_entry`, see `Flatlander::draw_text`.
+    pub fn text_items<S: BuildHasher>(&self, context: &LayoutContext, slot: AlphabetSlot, char_to_entry: &HashMap<char, usize, S>, text: &str) -> (Vec<FlatlandItem>, Vec<char>) {
+        let data = &self.alphabet_data[slot];
+        let (entry_indices, missing) = layout::text_entries(char_to_entry, text, data.fallback_entry);
+        (layout::layout_text(context, &data.metrics, &entry_indices), missing)
+    }
+
+    /// Stops accepting entries for the alphabet and trims its storage.
+    ///
+    /// Every weight step of the interpolable entries is generated now, so nothing is added to
+    /// the alphabet afterward: once its pending entries are uploaded, groups using it never
+    /// invalidate or upload alphabet data again. The outlines kept to generate entries are dropped.
+    pub fn freeze_alphabet(&mut self, slot: AlphabetSlot) {
+        let mut interpolable: Vec<usize> = self.alphabet_data[slot].interpolable.keys().cloned().collect();
+        interpolable.sort();
+        for index in interpolable {
+            for step in 1..=WEIGHT_STEPS {
+                if !self.alphabet_data[slot].weighted.contains_key(&(index, step)) {
+                    self.add_weighted_entry(slot, index, step);
+                }
+            }
+        }
+
+        let data = &mut self.alphabet_data[slot];
+        data.frozen = true;
+        data.interpolable = MetroHashMap::default();
+        data.dedup = MetroHashMap::default();
+        data.entries.shrink_to_fit();
+        data.map.shrink_to_fit();
+        data.weighted.shrink_to_fit();
+    }
+
+    pub fn set_alphabet_visible(&mut self, slot: AlphabetSlot, visible: bool) {
+        let data = &mut self.alphabet_data[slot];
+        if data.visible != visible {
+            data.visible = visible;
+            self.draw_invalidated = true;
+        }
+    }
+
+    fn assert_not_frozen(&self, slot: AlphabetSlot) {
+        assert!(!self.alphabet_data[slot].frozen, "alphabet is frozen");
+    }
+
+    /// In debug builds, panics listing the items that point past the alphabet's entries,
+    /// rather than letting them silently draw nothing.
+    fn debug_assert_items_in_alphabet(&self, slot: AlphabetSlot, items: &[FlatlandItem]) {
+        if cfg!(debug_assertions) {
+            let len = self.alphabet_data[slot].entries.len();
+            let invalid: Vec<(usize, usize)> = items.iter()
+                .enumerate()
+                .filter(|&(_, item)| item.alphabet_entry_index >= len)
+                .map(|(i, item)| (i, item.alphabet_entry_index))
+                .collect();
+
+            assert!(invalid.is_empty(), "items (position, entry index) {:?} are out of range for an alphabet with {} entries", invalid, len);
+        }
+    }
+
+    /// Pairs of alphabets that have the same entries, compared by content hash.
+    pub fn find_duplicate_alphabets(&self) -> Vec<(AlphabetSlot, AlphabetSlot)> {
+        let mut by_content: MetroHashMap<(usize, u64), Vec<AlphabetSlot>> = MetroHashMap::default();
+
+        for (slot, data) in self.alphabet_data.iter().filter(|&(slot, _)| Some(slot) != self.shapes_alphabet) {
+            if !data.entries.is_empty() {
+                by_content.entry((data.entries.len(), data.content_hash()))
+                    .or_insert_with(Vec::new)
+                    .push(slot);
+            }
+        }
+
+        let mut duplicates = Vec::new();
+        for slots in by_content.values() {
+            for (i, a) in slots.iter().enumerate() {
+                for b in &slots[i + 1..] {
+                    duplicates.push((*a, *b));
+                }
+            }
+        }
+        duplicates
+    }
+
+    pub fn clear_alphabet(&mut self, slot: AlphabetSlot) {
+        self.assert_not_frozen(slot);
+
+        let data = &mut self.alphabet_data[slot];
+        let entries = ::std::mem::replace(&mut data.entries, Vec::new());
+        data.clear();
+
+        self.free_alphabet_entries(&entries);
+        self.invalidate_removed_alphabet_entries();
+    }
+
+    pub fn inc_alphabet(&mut self, slot: AlphabetSlot) {
+        self.alphabet_slots[slot].count += 1;
+    }
+
+    pub fn dec_alphabet(&mut self, slot: AlphabetSlot) {
+        self.alphabet_slots[slot].count -= 1;
+
+        if self.alphabet_slots[slot].count <= 0 {
+            self.delete_alphabet(slot);
+        }
+    }
+
+    pub fn delete_alphabet(&mut self, slot: AlphabetSlot) {
+        self.alphabet_slots.remove(slot);
+        let data = self.alphabet_data.remove(slot).expect("expected to remove data when removing the alphabet");
+
+        self.free_alphabet_entries(&data.entries);
+        self.invalidate_removed_alphabet_entries();
+    }
+
+    /// Drops the entries of the alphabet that nothing refers to, neither an id, a group using the
+    /// alphabet, nor the fallback, and renumbers the others. Groups using the alphabet are updated;
+    /// the returned table gives the new index of each old entry, `None` for dropped ones, to update
+    /// entry indices kept elsewhere.
+    ///
+    /// Panics if the alphabet is frozen or a level of detail, since levels share entry indices.
+    pub fn compact_alphabet(&mut self, slot: AlphabetSlot) -> Vec<Option<usize>> {
+        self.assert_not_frozen(slot);
+        assert!(
+            !self.group_data.values().any(|g| !g.lod_levels.is_empty()
+                && (g.base_alphabet_slot == slot || g.lod_levels.iter().any(|&(_, a)| a == slot))),
+            "can't compact an alphabet used as a level of detail"
+        );
+
+        let data = &self.alphabet_data[slot];
+        let mut keep = vec![false; data.entries.len()];
+        let mut mark = |index: usize| if let Some(k) = keep.get_mut(index) { *k = true };
+
+        data.map.values().for_each(|&index| mark(index));
+        if let Some(index) = data.fallback_entry {
+            mark(index);
+        }
+        for group in self.group_data.values().filter(|g| g.alphabet_slot == slot) {
+            group.items.iter().for_each(|item| mark(item.alphabet_entry_index));
+        }
+        drop(mark);
+
+        // whatever the kept entries are drawn with at other weights or as layers
+        for (&(index, _), &entry) in data.weighted.iter() {
+            if keep[index] {
+                keep[entry] = true;
+            }
+        }
+        for (&index, layers) in data.layers.iter() {
+            if keep[index] {
+                for &(_, layer) in layers {
+                    keep[layer] = true;
+                }
+            }
+        }
+
+        let dropped: Vec<(Range<usize>, Range<usize>)> = data.entries.iter()
+            .zip(&keep)
+            .filter(|&(_, &kept)| !kept)
+            .map(|(entry, _)| entry.space())
+            .collect();
+        let remap = self.alphabet_data[slot].retain_entries(&keep);
+        for (vertices, indices) in dropped {
+            self.free_alphabet_vertices.free(vertices);
+            self.free_alphabet_indices.free(indices);
+        }
+
+        let groups: Vec<GroupSlot> = self.group_data.iter()
+            .filter(|&(_, g)| g.alphabet_slot == slot)
+            .map(|(group, _)| group)
+            .collect();
+        for group in groups {
+            let data = &mut self.group_data[group];
+            for item in data.items.iter_mut() {
+                item.alphabet_entry_index = remap[item.alphabet_entry_index].expect("entries used by groups are kept");
+            }
+            self.touch_group(group);
+        }
+
+        self.groups_invalidated = true;
+        self.invalidate_removed_alphabet_entries();
+
+        remap
+    }
+
+    fn free_alphabet_entries(&mut self, entries: &[AlphabetEntry]) {
+        for (vertices, indices) in entries.iter().map(|entry| entry.space()) {
+            self.free_alphabet_vertices.free(vertices);
+            self.free_alphabet_indices.free(indices);
+        }
+    }
+
+    fn invalidate_removed_alphabet_entries(&mut self) {
+        // with retained data the holes are compacted away on the next upload
+        if self.retain_cpu_data {
+            self.alphabets_invalidated = true;
+        }
+        self.draw_invalidated = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data;
+
+    fn triangle(size: f32) -> (Vec<FlatlanderVertex>, Vec<u16>) {
+        let vertex = |x: f32, y: f32| FlatlanderVertex {
+            pos: data::f16_f16::from((x, y)),
+            normal: data::f16_f16::from((0.0, 0.0)),
+        };
+        (vec![vertex(0.0, 0.0), vertex(size, 0.0), vertex(0.0, size)], vec![0, 1, 2])
+    }
+
+    fn item(alphabet_entry_index: usize, x: f32, y: f32) -> FlatlandItem {
+        FlatlandItem::new(alphabet_entry_index, x, y)
+    }
+
+    /// Black group of `items` with the identity transform.
+    fn group(flatland: &mut Flatland, alphabet: AlphabetSlot, items: Vec<FlatlandItem>) -> GroupSlot {
+        flatland.create_flatland_group_with_items(&na::Projective3::identity(), na::Vector4::new(0, 0, 0, 255), alphabet, items)
+    }
+
+    /// Start and length of each run of vertices waiting for upload.
+    fn pending_vertex_runs(flatland: &Flatland) -> Vec<(usize, usize)> {
+        flatland.pending_alphabet_vertices().iter().map(|&(start, ref vertices)| (start, vertices.len())).collect()
+    }
+
+    #[test]
+    fn finds_alphabets_with_identical_entries() {
+        let mut flatland = Flatland::new();
+        let a = flatland.create_alphabet();
+        let b = flatland.create_alphabet();
+        let c = flatland.create_alphabet();
+
+        for &slot in &[a, b] {
+            let (vertices, indices) = triangle(1.0);
+            flatland.add_alphabet_entry(slot, 1, vertices, indices);
+        }
+        let (vertices, indices) = triangle(2.0);
+        flatland.add_alphabet_entry(c, 1, vertices, indices);
+
+        assert_eq!(vec![(a, b)], flatland.find_duplicate_alphabets());
+    }
+
+    #[test]
+    fn only_entries_added_after_upload_are_pending() {
+        let mut flatland = Flatland::new();
+        let slot = flatland.create_alphabet();
+
+        let (vertices, indices) = triangle(1.0);
+        let first = flatland.add_alphabet_entry(slot, 1, vertices, indices);
+        flatland.mark_alphabets_uploaded();
+
+        let (vertices, indices) = triangle(2.0);
+        flatland.add_alphabet_entry(slot, 2, vertices, indices);
+
+        assert_eq!(3, flatland.uploaded_alphabet_vertices_len());
+        assert_eq!(vec![(3, 3)], pending_vertex_runs(&flatland));
+        assert_eq!(vec![(3, vec![0, 1, 2])], flatland.pending_alphabet_indices());
+        assert!(flatland.alphabet_entry_geometry(slot, first).is_none());
+    }
+
+    #[test]
+    fn draws_offset_entry_local_indices_by_the_entry_place() {
+        let mut flatland = Flatland::new();
+        let big = flatland.create_alphabet();
+        let vertex = triangle(1.0).0[0];
+        flatland.add_alphabet_entry(big, 0, vec![vertex; MAX_ALPHABET_VERTICES], (0..3).collect());
+        let slot = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(slot, 1, vertices, indices);
+        group(&mut flatland, slot, vec![item(entry, 0.0, 0.0)]);
+
+        assert_eq!(vec![(0, vec![0, 1, 2, 0, 1, 2])], flatland.pending_alphabet_indices());
+        let (commands, _) = flatland.draw_commands();
+        let (first_index, base_vertex) = (commands[0].first_index, commands[0].base_vertex);
+        assert_eq!((3, MAX_ALPHABET_VERTICES as u32), (first_index, base_vertex));
+    }
+
+    #[test]
+    fn retained_alphabets_compact_after_clear() {
+        let mut flatland = Flatland::new();
+        flatland.set_retain_cpu_data(true);
+        let a = flatland.create_alphabet();
+        let b = flatland.create_alphabet();
+
+        let (vertices, indices) = triangle(1.0);
+        flatland.add_alphabet_entry(a, 1, vertices, indices);
+        let (vertices, indices) = triangle(2.0);
+        let entry = flatland.add_alphabet_entry(b, 1, vertices, indices);
+        flatland.mark_alphabets_uploaded();
+
+        flatland.clear_alphabet(a);
+        assert!(flatland.compact_alphabets());
+
+        assert_eq!(0, flatland.uploaded_alphabet_vertices_len());
+        assert_eq!(vec![(0, vec![0, 1, 2])], flatland.pending_alphabet_indices());
+        assert_eq!(Some(triangle(2.0).1), flatland.alphabet_entry_geometry(b, entry).map(|g| g.1));
+    }
+
+    #[test]
+    fn invalidated_gpu_data_is_uploaded_again_only_when_retained() {
+        let mut flatland = Flatland::new();
+        let slot = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        flatland.add_alphabet_entry(slot, 1, vertices, indices);
+        flatland.mark_alphabets_uploaded();
+        assert!(!flatland.invalidate_gpu_data());
+
+        flatland.set_retain_cpu_data(true);
+        let (vertices, indices) = triangle(2.0);
+        flatland.add_alphabet_entry(slot, 2, vertices, indices);
+        flatland.clear_alphabet(slot);
+        let (vertices, indices) = triangle(2.0);
+        flatland.add_alphabet_entry(slot, 2, vertices, indices);
+        flatland.mark_alphabets_uploaded();
+
+        assert!(flatland.invalidate_gpu_data());
+        assert!(flatland.alphabets_invalidated && flatland.groups_invalidated);
+        assert_eq!(0, flatland.uploaded_alphabet_vertices_len());
+        assert_eq!(vec![(0, vec![0, 1, 2])], flatland.pending_alphabet_indices());
+    }
+
+    #[test]
+    fn freed_alphabet_space_is_reused_without_retained_data() {
+        let mut flatland = Flatland::new();
+        let font = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        flatland.add_alphabet_entry(font, 0, vertices, indices);
+
+        // a million vertices placed in total, far past what u16 indices reach without reuse
+        for round in 0..100 {
+            let rebuilt = flatland.create_alphabet();
+            for size in 1..5 {
+                let len = size * 1000;
+                let vertex = triangle(1.0).0[0];
+                flatland.add_alphabet_entry(rebuilt, size as u32, vec![vertex; len], (0..len as u16).collect());
+            }
+            // persistent entries placed in between keep the holes from merging with the end
+            let (vertices, indices) = triangle(1.0);
+            flatland.add_alphabet_entry(font, round + 1, vertices, indices);
+
+            for &(_, ref indices) in &flatland.pending_alphabet_indices() {
+                assert!(indices.iter().all(|&index| (index as usize) < flatland.alphabet_vertices_len()));
+            }
+            flatland.mark_alphabets_uploaded();
+            flatland.delete_alphabet(rebuilt);
+        }
+
+        assert_eq!(3 + 10_000 + 100 * 3, flatland.alphabet_vertices_len());
+        assert_eq!(10_000, flatland.free_alphabet_vertices.len());
+    }
+
+    #[test]
+    fn edits_held_back_by_a_batch_are_uploaded_as_one_run() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 0, vertices, indices);
+        group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        flatland.mark_alphabets_uploaded();
+
+        // what Flatlander::end_batch uploads after bulk edits
+        for id in 1..100 {
+            let (vertices, indices) = triangle(1.0);
+            let entry = flatland.add_alphabet_entry(alphabet, id, vertices, indices);
+            group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        }
+
+        assert!(flatland.alphabets_invalidated && flatland.groups_invalidated && flatland.draw_invalidated);
+        assert_eq!(vec![(3, 99 * 3)], pending_vertex_runs(&flatland));
+        assert_eq!(1, flatland.pending_alphabet_indices().len());
+        let (commands, batches) = flatland.draw_commands();
+        assert_eq!((100, 1), (commands.len(), batches.len()));
+
+        flatland.mark_alphabets_uploaded();
+        assert!(pending_vertex_runs(&flatland).is_empty());
+    }
+
+    #[test]
+    fn draw_hooks_run_around_the_draw_calls() {
+        let log = Rc::new(::std::cell::RefCell::new(Vec::new()));
+        let logged = |name: &'static str| -> Box<dyn Fn(&gl::Gl)> {
+            let log = log.clone();
+            Box::new(move |_| log.borrow_mut().push(name))
+        };
+        let gl = gl::Gl::load_with(|_| ::std::ptr::null());
+
+        let mut hooks = super::super::DrawHooks::default();
+        hooks.around(&gl, || log.borrow_mut().push("draw"));
+        hooks.pre = Some(logged("pre"));
+        hooks.post = Some(logged("post"));
+        hooks.around(&gl, || log.borrow_mut().push("draw"));
+
+        assert_eq!(vec!["draw", "pre", "draw", "post"], *log.borrow());
+    }
+
+    #[test]
+    fn entries_filling_holes_are_uploaded_in_place() {
+        let mut flatland = Flatland::new();
+        let a = flatland.create_alphabet();
+        let b = flatland.create_alphabet();
+        for &slot in &[a, b] {
+            let (vertices, indices) = triangle(1.0);
+            flatland.add_alphabet_entry(slot, 1, vertices, indices);
+        }
+        flatland.mark_alphabets_uploaded();
+
+        flatland.delete_alphabet(a);
+        let (vertices, indices) = triangle(2.0);
+        flatland.add_alphabet_entry(b, 2, vertices, indices);
+
+        assert_eq!(6, flatland.alphabet_vertices_len());
+        assert_eq!(vec![(0, 3)], pending_vertex_runs(&flatland));
+        assert_eq!(vec![(0, vec![0, 1, 2])], flatland.pending_alphabet_indices());
+        assert!(!flatland.has_wasted_alphabet_space());
+    }
+
+    #[test]
+    fn wasted_fraction_follows_cleared_entries() {
+        let mut flatland = Flatland::new();
+        let a = flatland.create_alphabet();
+        let b = flatland.create_alphabet();
+        assert_eq!(0.0, flatland.wasted_alphabet_fraction());
+
+        let (vertices, indices) = triangle(1.0);
+        flatland.add_alphabet_entry(a, 1, vertices, indices);
+        let (vertices, indices) = triangle(1.0);
+        flatland.add_alphabet_entry(b, 1, vertices, indices);
+        flatland.clear_alphabet(a);
+
+        assert!(flatland.has_wasted_alphabet_space());
+        assert_eq!(0.5, flatland.wasted_alphabet_fraction());
+    }
+
+    #[test]
+    fn group_bounds_cover_offset_items() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(4.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let group = flatland.create_flatland_group_with_items(
+            &na::Projective3::identity(), na::Vector4::new(255, 255, 255, 255), alphabet, vec![item(entry, 0.0, 0.0), item(entry, 10.0, -2.0)]
+        );
+
+        let bounds = flatland.group_bounds(group).unwrap();
+        assert_eq!((0.0, -2.0), (bounds.min.x, bounds.min.y));
+        assert_eq!((14.0, 4.0), (bounds.max.x, bounds.max.y));
+    }
+
+    #[test]
+    fn item_pixel_size_follows_transform_and_projection() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(4.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let transform = na::Projective3::from_matrix_unchecked(na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(2.0, 3.0, 1.0)));
+        let group = flatland.create_flatland_group_with_items(&transform, na::Vector4::new(0, 0, 0, 255), alphabet, vec![item(entry, 3.0, 0.0)]);
+
+        // one unit per pixel on a 100x50 viewport
+        let vp = na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(2.0 / 100.0, 2.0 / 50.0, 1.0));
+        let size = flatland.group_item_pixel_size(group, 0, &vp, na::Vector2::new(100.0, 50.0));
+
+        assert!((size - na::Vector2::new(8.0, 12.0)).norm() < 0.001);
+        assert_eq!(na::Vector2::zeros(), flatland.group_item_pixel_size(group, 1, &vp, na::Vector2::new(100.0, 50.0)));
+    }
+
+    #[test]
+    fn lod_picks_the_highest_level_reached_and_holds_its_alphabets() {
+        let mut flatland = Flatland::new();
+        let coarse = flatland.create_alphabet();
+        let fine = flatland.create_alphabet();
+        for &alphabet in &[coarse, fine] {
+            let (vertices, indices) = triangle(10.0);
+            flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        }
+
+        let group = group(&mut flatland, coarse, vec![item(0, 0.0, 0.0)]);
+        flatland.set_group_lod_alphabets(group, vec![(20.0, fine), (0.0, coarse)]);
+
+        let viewport = na::Vector2::new(100.0, 100.0);
+        let pixels = |scale: f32| na::Matrix4::new_scaling(scale * 2.0 / 100.0);
+
+        flatland.select_lod_alphabets(&pixels(1.0), viewport);
+        assert!(flatland.group_data[group].alphabet_slot == coarse);
+        flatland.select_lod_alphabets(&pixels(4.0), viewport);
+        assert!(flatland.group_data[group].alphabet_slot == fine);
+
+        flatland.dec_alphabet(fine);
+        assert!(flatland.alphabet_data.contains_key(fine));
+        flatland.set_group_lod_alphabets(group, vec![]);
+        assert!(flatland.group_data[group].alphabet_slot == coarse);
+        assert!(!flatland.alphabet_data.contains_key(fine));
+        flatland.delete_flatland_group(group);
+        assert!(!flatland.alphabet_data.contains_key(fine));
+    }
+
+    #[test]
+    fn changed_groups_are_reported_once_per_generation() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        group(&mut flatland, alphabet, vec![]);
+        let b = group(&mut flatland, alphabet, vec![]);
+
+        let (changed, generation) = flatland.groups_changed_since(0);
+        assert_eq!(2, changed.len());
+        assert!(flatland.groups_changed_since(generation).0.is_empty());
+
+        flatland.update_color(b, na::Vector4::new(255, 0, 0, 255));
+        flatland.update_transforms(&[(b, na::Projective3::identity())]);
+        let (changed, next) = flatland.groups_changed_since(generation);
+        assert!(changed == vec![b]);
+        assert!(next > generation);
+    }
+
+    #[test]
+    fn transform_and_color_changes_upload_the_group_data() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        let moved = na::Projective3::from_matrix_unchecked(na::Matrix4::new_translation(&na::Vector3::new(3.0, 0.0, 0.0)));
+
+        flatland.groups_invalidated = false;
+        flatland.update_transform(group, &moved);
+        assert!(flatland.groups_invalidated);
+        assert_eq!(3.0, flatland.groups_draw_data().next().unwrap().transform.matrix()[(0, 3)]);
+
+        flatland.groups_invalidated = false;
+        flatland.update_color(group, na::Vector4::new(255, 0, 0, 255));
+        assert!(flatland.groups_invalidated);
+        assert_eq!(na::Vector4::new(255, 0, 0, 255), flatland.groups_draw_data().next().unwrap().color);
+    }
+
+    #[test]
+    fn groups_deleted_by_id_can_be_deleted_again() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let groups: Vec<_> = (0..3)
+            .map(|_| group(&mut flatland, alphabet, vec![]))
+            .collect();
+
+        flatland.delete_flatland_groups(&groups[..2]);
+        assert_eq!(1, flatland.group_data.len());
+
+        // what the dropped handles do afterwards
+        flatland.draw_invalidated = false;
+        flatland.delete_flatland_group(groups[0]);
+        assert!(!flatland.draw_invalidated);
+        assert!(flatland.group_data.contains_key(groups[2]));
+    }
+
+    #[test]
+    fn scale_is_replaced_without_moving_the_group() {
+        let rotation = na::Matrix4::new_rotation(na::Vector3::new(0.0, 0.0, 0.5));
+        let transform = na::Projective3::from_matrix_unchecked(
+            na::Matrix4::new_translation(&na::Vector3::new(10.0, 20.0, 0.0)) * rotation * na::Matrix4::new_scaling(3.0)
+        );
+
+        let scaled = with_scale(&transform, 2.0, 2.0);
+
+        let expected = na::Matrix4::new_translation(&na::Vector3::new(10.0, 20.0, 0.0))
+            * rotation
+            * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(2.0, 2.0, 3.0));
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!((scaled.matrix()[(row, column)] - expected[(row, column)]).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn scaling_a_group_keeps_its_center_in_place() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(2.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let transform = na::Projective3::from_matrix_unchecked(
+            na::Matrix4::new_translation(&na::Vector3::new(10.0, 20.0, 0.0)) * na::Matrix4::new_rotation(na::Vector3::new(0.0, 0.0, 0.5))
+        );
+        let group = flatland.create_flatland_group_with_items(&transform, na::Vector4::new(0, 0, 0, 255), alphabet, vec![item(entry, 4.0, 2.0)]);
+        flatland.update_pivot(group, na::Vector2::new(1.0, 1.0));
+
+        let center = flatland.pivot_point(group, Pivot::Center);
+        let drawn_center = |flatland: &Flatland| flatland.group_data[group].placed_transform() * na::Point3::new(center.x, center.y, 0.0);
+        let before = drawn_center(&flatland);
+
+        flatland.update_scale(group, 3.0, 0.5);
+
+        let after = drawn_center(&flatland);
+        assert!((after - before).norm() < 0.0001);
+        let scaled = flatland.group_data[group].transform;
+        assert!(((scaled * na::Vector3::x()).norm() - 3.0).abs() < 0.0001);
+        assert!(((scaled * na::Vector3::y()).norm() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn empty_alphabets_are_not_duplicates() {
+        let mut flatland = Flatland::new();
+        flatland.create_alphabet();
+        flatland.create_alphabet();
+
+        assert!(flatland.find_duplicate_alphabets().is_empty());
+    }
+
+    #[test]
+    fn layered_entries_draw_each_layer_tinted_by_the_group() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (small_vertices, small_indices) = triangle(1.0);
+        let (large_vertices, large_indices) = triangle(2.0);
+        let entry = flatland.add_layered_alphabet_entry(alphabet, 1, vec![
+            (na::Vector4::new(255, 0, 0, 255), large_vertices, large_indices),
+            (na::Vector4::new(0, 0, 255, 255), small_vertices, small_indices),
+        ], 0.0);
+
+        flatland.create_flatland_group_with_items(
+            &na::Projective3::identity(), na::Vector4::new(255, 255, 255, 128), alphabet, vec![item(entry, 0.0, 0.0)]
+        );
+
+        let draw_data: Vec<_> = flatland.groups_draw_data().collect();
+        assert_eq!(2, flatland.groups_len_with_usage(Usage::Dynamic));
+        assert_eq!(2, draw_data.len());
+        assert_eq!(na::Vector4::new(255, 0, 0, 128), draw_data[0].color);
+        assert_eq!(na::Vector4::new(0, 0, 255, 128), draw_data[1].color);
+        let first_index = draw_data[1].cmd.first_index;
+        assert_eq!(3, first_index);
+        assert_eq!(Some(2.0), flatland.alphabet_metrics(alphabet).bounds(entry).map(|b| b.max.x));
+    }
+
+    #[test]
+    fn hidden_alphabets_are_left_out_of_draw_commands() {
+        let mut flatland = Flatland::new();
+        let hidden = flatland.create_alphabet();
+        let shown = flatland.create_alphabet();
+        for &alphabet in &[hidden, shown] {
+            let (vertices, indices) = triangle(1.0);
+            let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+            group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        }
+
+        flatland.set_alphabet_visible(hidden, false);
+        let (commands, _) = flatland.draw_commands();
+        let base_instance = commands[0].base_instance;
+        assert_eq!(1, commands.len());
+        assert_eq!(1, base_instance);
+        assert_eq!(CullResult { visible: 1, culled: 1 }, flatland.cull_result(|_, _| true));
+
+        flatland.set_alphabet_visible(hidden, true);
+        assert_eq!(2, flatland.draw_commands().0.len());
+        assert_eq!(CullResult { visible: 2, culled: 0 }, flatland.cull_result(|_, _| true));
+    }
+
+    #[test]
+    fn shadows_are_drawn_first_with_an_offset_transform() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        let shadowed = flatland.create_flatland_group_with_items(
+            &na::Projective3::identity(), na::Vector4::new(255, 255, 255, 255), alphabet, vec![item(entry, 0.0, 0.0)]
+        );
+        flatland.update_shadow(shadowed, Some((na::Vector2::new(2.0, 3.0), na::Vector4::new(0, 0, 0, 128))));
+
+        let draw_data: Vec<_> = flatland.groups_draw_data().collect();
+        assert_eq!(3, flatland.groups_len_with_usage(Usage::Dynamic));
+        assert_eq!(3, draw_data.len());
+        assert!(draw_data[1].shadow);
+        assert_eq!(na::Vector4::new(0, 0, 0, 128), draw_data[1].color);
+        let translation = draw_data[1].transform.matrix();
+        assert_eq!((2.0, 3.0), (translation[(0, 3)], translation[(1, 3)]));
+
+        let (commands, batches) = flatland.draw_commands();
+        let first_instance = commands[0].base_instance;
+        assert_eq!(1, first_instance);
+        assert_eq!(2, batches.len());
+        assert_eq!((0, 1), (batches[0].first_command, batches[0].command_count));
+        assert_eq!((1, 2), (batches[1].first_command, batches[1].command_count));
+
+        flatland.update_shadow(shadowed, None);
+        assert_eq!(2, flatland.groups_len_with_usage(Usage::Dynamic));
+        assert_eq!(1, flatland.draw_commands().1.len());
+    }
+
+    #[test]
+    fn filtered_draw_commands_keep_their_instances() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let colors = [na::Vector4::new(255, 0, 0, 255), na::Vector4::new(0, 255, 0, 255)];
+        for &color in &colors {
+            flatland.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet, vec![item(entry, 0.0, 0.0), item(entry, 0.0, 0.0)]);
+        }
+
+        let (commands, batches) = flatland.draw_commands_filtered(|_, group| group.color == colors[1]);
+        let base_instances: Vec<u32> = commands.iter().map(|c| c.base_instance).collect();
+        assert_eq!(vec![2, 3], base_instances);
+        assert_eq!(2, batches[0].command_count);
+    }
+
+    #[test]
+    fn outline_groups_get_their_own_batch() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let groups: Vec<_> = (0..3)
+            .map(|_| group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]))
+            .collect();
+        flatland.update_style(groups[1], RenderStyle::Outline);
+
+        let (_, batches) = flatland.draw_commands();
+        let batches: Vec<_> = batches.iter().map(|b| (b.style, b.command_count)).collect();
+        assert_eq!(vec![(RenderStyle::Fill, 1), (RenderStyle::Outline, 1), (RenderStyle::Fill, 1)], batches);
+    }
+
+    #[test]
+    fn blend_mode_batches_keep_the_group_order() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let modes = [BlendMode::Alpha, BlendMode::Additive, BlendMode::Additive, BlendMode::Alpha, BlendMode::Multiply];
+        for &mode in &modes {
+            let slot = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+            flatland.update_blend_mode(slot, mode);
+        }
+
+        let (commands, batches) = flatland.draw_commands();
+        let instances: Vec<_> = commands.iter().map(|c| c.base_instance).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4], instances);
+        let batches: Vec<_> = batches.iter().map(|b| (b.blend_mode, b.first_command, b.command_count)).collect();
+        assert_eq!(vec![
+            (BlendMode::Alpha, 0, 1),
+            (BlendMode::Additive, 1, 2),
+            (BlendMode::Alpha, 3, 1),
+            (BlendMode::Multiply, 4, 1),
+        ], batches);
+    }
+
+    #[test]
+    fn alphabet_entry_ids_are_listed_by_entry_index() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        flatland.add_alphabet_entry(alphabet, 7, vertices, indices);
+        for &id in &[3, 9] {
+            let (vertices, indices) = triangle(2.0);
+            let key = geometry_hash(&vertices, &indices);
+            flatland.add_alphabet_entry_deduped(alphabet, key, id, vertices, indices);
+        }
+
+        assert_eq!(vec![(7, 0), (3, 1), (9, 1)], flatland.alphabet_entry_ids(alphabet));
+    }
+
+    #[test]
+    fn deduplicated_entries_share_matching_geometry_only() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+
+        let (vertices, indices) = triangle(1.0);
+        let first = flatland.add_alphabet_entry_deduped(alphabet, 42, 1, vertices, indices);
+        let (vertices, indices) = triangle(1.0);
+        let repeated = flatland.add_alphabet_entry_deduped(alphabet, 42, 2, vertices, indices);
+        assert_eq!(first, repeated);
+        assert_eq!(vec![(0, 3)], pending_vertex_runs(&flatland));
+        assert_eq!(Some(first), flatland.get_alphabet_entry_index(alphabet, 2));
+
+        let (vertices, indices) = triangle(2.0);
+        let colliding = flatland.add_alphabet_entry_deduped(alphabet, 42, 3, vertices, indices);
+        assert_ne!(first, colliding);
+        assert_eq!(vec![(0, 6)], pending_vertex_runs(&flatland));
+    }
+
+    #[test]
+    fn text_items_follow_advances_kerning_and_the_fallback_glyph() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let mut char_to_entry = HashMap::new();
+        for &(c, advance) in &[('a', 10.0), ('b', 6.0)] {
+            let (vertices, indices) = triangle(1.0);
+            char_to_entry.insert(c, flatland.add_alphabet_entry_with_advance(alphabet, c as u32, vertices, indices, advance));
+        }
+        flatland.set_alphabet_kerning(alphabet, char_to_entry[&'a'], char_to_entry[&'b'], -2.0);
+        let (vertices, indices) = triangle(4.0);
+        let fallback = flatland.set_alphabet_fallback_glyph(alphabet, vertices, indices);
+
+        let context = LayoutContext { pixels_per_unit: 2.0 };
+        let (items, missing) = flatland.text_items(&context, alphabet, &char_to_entry, "abxax");
+
+        assert_eq!(vec!['x'], missing);
+        let entries: Vec<_> = items.iter().map(|i| i.alphabet_entry_index).collect();
+        assert_eq!(vec![char_to_entry[&'a'], char_to_entry[&'b'], fallback, char_to_entry[&'a'], fallback], entries);
+        let offsets: Vec<_> = items.iter().map(|i| i.x()).collect();
+        assert_eq!(vec![0.0, 4.0, 7.0, 9.0, 14.0], offsets);
+    }
+
+    #[test]
+    fn animations_change_the_drawn_color_and_transform_until_they_end() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let fading = flatland.create_flatland_group_with_items(
+            &na::Projective3::identity(), na::Vector4::new(0, 0, 0, 200), alphabet, vec![item(entry, 0.0, 0.0)]
+        );
+        let sliding = flatland.create_flatland_group_with_items(
+            &na::Projective3::identity(), na::Vector4::new(0, 0, 0, 200), alphabet, vec![item(entry, 0.0, 0.0)]
+        );
+        flatland.update_animation(fading, Some(GroupAnimation::FadeIn { secs: 2.0 }));
+        flatland.update_animation(sliding, Some(GroupAnimation::SlideFrom { offset: na::Vector2::new(10.0, 0.0), secs: 2.0 }));
+
+        flatland.advance_animations(1.0);
+        let draw_data: Vec<_> = flatland.groups_draw_data().collect();
+        assert_eq!(100, draw_data[0].color.w);
+        assert_eq!(5.0, draw_data[1].transform.matrix()[(0, 3)]);
+        assert_eq!(na::Vector4::new(0, 0, 0, 200), flatland.group_data[fading].color);
+
+        flatland.advance_animations(1.5);
+        assert!(!flatland.is_group_animating(fading));
+        let draw_data: Vec<_> = flatland.groups_draw_data().collect();
+        assert_eq!(200, draw_data[0].color.w);
+        assert_eq!(0.0, draw_data[1].transform.matrix()[(0, 3)]);
+
+        flatland.groups_invalidated = false;
+        flatland.advance_animations(1.0);
+        assert!(!flatland.groups_invalidated);
+    }
+
+    #[test]
+    fn winding_is_normalized_per_triangle() {
+        let vertex = |x: f32, y: f32| FlatlanderVertex {
+            pos: data::f16_f16::from((x, y)),
+            normal: data::f16_f16::from((0.0, 0.0)),
+        };
+        let vertices = vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0), vertex(2.0, 0.0)];
+        let mut indices = vec![0, 1, 2, 0, 2, 1, 0, 1, 3];
+
+        normalize_winding(&vertices, &mut indices, Winding::CounterClockwise);
+        assert_eq!(vec![0, 1, 2, 0, 1, 2, 0, 1, 3], indices);
+
+        normalize_winding(&vertices, &mut indices, Winding::Clockwise);
+        assert_eq!(vec![0, 2, 1, 0, 2, 1, 0, 1, 3], indices);
+    }
+
+    #[test]
+    #[should_panic(expected = "alphabet is frozen")]
+    fn frozen_alphabets_reject_new_entries() {
+        let mut flatland = Flatland::new();
+        let slot = flatland.create_alphabet();
+        flatland.freeze_alphabet(slot);
+
+        let (vertices, indices) = triangle(1.0);
+        flatland.add_alphabet_entry(slot, 1, vertices, indices);
+    }
+
+    #[test]
+    fn groups_of_frozen_alphabets_change_without_alphabet_uploads() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (regular, indices) = triangle(2.0);
+        let (bold, _) = triangle(4.0);
+        let entry = flatland.add_interpolable_alphabet_entry(alphabet, 1, (regular, indices), bold, 5.0);
+        flatland.freeze_alphabet(alphabet);
+        let entries = flatland.alphabet_data[alphabet].entries.len();
+        assert_eq!(1 + WEIGHT_STEPS, entries);
+        flatland.mark_alphabets_uploaded();
+
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        flatland.update_weight(group, 0.5);
+        flatland.update_decoration(group, Decoration::Underline { thickness: 1.0 });
+        flatland.update_background(group, Some((na::Vector4::new(255, 0, 0, 255), 1.0)));
+        flatland.update_selection(group, 0, 1, na::Vector4::new(0, 0, 255, 255));
+
+        assert_eq!(entries, flatland.alphabet_data[alphabet].entries.len());
+        assert!(!flatland.alphabet_data[alphabet].has_pending);
+        assert_ne!(entry, flatland.alphabet_data[alphabet].weighted_entry(entry, 0.5));
+    }
+
+    #[test]
+    #[should_panic(expected = "items (position, entry index) [(1, 5)] are out of range for an alphabet with 1 entries")]
+    fn items_past_the_alphabet_entries_are_rejected() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let wrong = item(5, 0.0, 0.0);
+        group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0), wrong]);
+    }
+
+    #[test]
+    fn frozen_groups_keep_item_positions() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let color = na::Vector4::new(0, 0, 0, 255);
+        let moved = na::Projective3::from_matrix_unchecked(
+            na::Matrix4::new_translation(&na::Vector3::new(10.0, 20.0, 0.0))
+        );
+        let a = flatland.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet, vec![item(entry, 1.0, 2.0)]);
+        let b = flatland.create_flatland_group_with_items(&moved, color, alphabet, vec![item(entry, 1.0, 2.0)]);
+
+        let frozen = flatland.freeze_groups(&[a, b]);
+
+        assert!(!flatland.group_data.contains_key(a));
+        assert!(!flatland.group_data.contains_key(b));
+        let offsets: Vec<(f32, f32)> = flatland.group_data[frozen].items.iter().map(|i| (i.x(), i.y())).collect();
+        assert_eq!(vec![(1.0, 2.0), (11.0, -18.0)], offsets);
+    }
+
+    #[test]
+    fn split_alphabets_keep_ids_under_the_vertex_limit() {
+        let mut flatland = Flatland::new();
+        let slot = flatland.create_alphabet();
+        for id in 0..3 {
+            let (vertices, indices) = triangle(1.0 + id as f32);
+            flatland.add_alphabet_entry_with_advance(slot, id, vertices, indices, id as f32);
+        }
+        flatland.set_alphabet_kerning(slot, 0, 1, -0.5);
+        flatland.set_alphabet_kerning(slot, 1, 2, -0.25);
+
+        let parts = flatland.split_alphabet(slot, 6);
+
+        assert_eq!(2, parts.len());
+        assert_eq!(vec![(0, 0), (1, 1)], flatland.alphabet_entry_ids(parts[0]));
+        assert_eq!(vec![(2, 0)], flatland.alphabet_entry_ids(parts[1]));
+        assert_eq!(2.0, flatland.alphabet_metrics(parts[1]).advance(0));
+        assert_eq!(Some(&-0.5), flatland.alphabet_metrics(parts[0]).kerning.get(&(0, 1)));
+        assert!(flatland.alphabet_metrics(parts[1]).kerning.is_empty());
+    }
+
+    #[test]
+    fn pivots_keep_their_point_in_place() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let scaled = na::Projective3::from_matrix_unchecked(na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(2.0, 2.0, 1.0)));
+        let group = flatland.create_flatland_group_with_items(&scaled, na::Vector4::new(0, 0, 0, 255), alphabet, vec![item(entry, 4.0, 2.0)]);
+
+        let pivot = flatland.pivot_point(group, Pivot::BaselineStart);
+        assert_eq!(na::Vector2::new(4.0, -2.0), pivot);
+        flatland.update_pivot(group, pivot);
+
+        let draw_data: Vec<_> = flatland.groups_draw_data().collect();
+        let matrix = draw_data[0].transform.matrix();
+        assert_eq!((-4.0, 2.0), (matrix[(0, 3)], matrix[(1, 3)]));
+    }
+
+    #[test]
+    fn static_groups_are_numbered_and_uploaded_apart() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+
+        let color = na::Vector4::new(0, 0, 0, 255);
+        let dynamic = flatland.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet, vec![item(entry, 0.0, 0.0)]);
+        let fixed = flatland.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet, vec![item(entry, 0.0, 0.0), item(entry, 0.0, 0.0)]);
+        flatland.update_usage(fixed, Usage::Static);
+
+        let instances: Vec<(Usage, u32)> = flatland.groups_draw_data().map(|d| (d.usage, d.cmd.base_instance)).collect();
+        assert_eq!(vec![(Usage::Static, 0), (Usage::Static, 1), (Usage::Dynamic, 0)], instances);
+        let (_, batches) = flatland.draw_commands();
+        assert_eq!(vec![Usage::Static, Usage::Dynamic], batches.iter().map(|b| b.usage).collect::<Vec<_>>());
+
+        assert!(flatland.static_groups_dirty());
+        flatland.mark_static_groups_uploaded();
+        flatland.update_color(dynamic, na::Vector4::new(255, 0, 0, 255));
+        assert!(!flatland.static_groups_dirty());
+        flatland.update_color(fixed, na::Vector4::new(255, 0, 0, 255));
+        assert!(flatland.static_groups_dirty());
+    }
+
+    #[test]
+    fn screen_pixels_map_back_to_the_screen_space_plane() {
+        let viewport = na::Vector2::new(800.0, 600.0);
+        let vp = crate::ortho_screen(viewport.x, viewport.y);
+
+        let world = screen_to_world(na::Vector2::new(100.0, 50.0), &vp, viewport).unwrap();
+        assert!((world - na::Vector2::new(100.0, 50.0)).norm() < 1e-3);
+        assert_eq!(None, screen_to_world(na::Vector2::new(100.0, 50.0), &na::Matrix4::zeros(), viewport));
+    }
+
+    #[test]
+    fn layers_draw_only_their_groups() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let groups: Vec<GroupSlot> = (0..3)
+            .map(|_| group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]))
+            .collect();
+
+        let world = flatland.layer("world");
+        let ui = flatland.layer("ui");
+        assert_eq!(world, flatland.layer("world"));
+        flatland.add_to_layer(world, groups[0]);
+        flatland.add_to_layer(ui, groups[1]);
+        flatland.add_to_layer(ui, groups[2]);
+
+        let base_instance = flatland.layer_draw_commands(world).0[0].base_instance;
+        assert_eq!(0, base_instance);
+        assert_eq!(2, flatland.layer_draw_commands(ui).0.len());
+
+        // a group moves between layers, and leaves them when deleted
+        flatland.add_to_layer(world, groups[1]);
+        assert_eq!(2, flatland.layer_draw_commands(world).0.len());
+        flatland.delete_flatland_group(groups[2]);
+        assert!(flatland.layer_draw_commands(ui).0.is_empty());
+        assert!(!flatland.in_enabled_layer(groups[2]));
+    }
+
+    #[test]
+    fn caret_positions_follow_the_laid_out_glyphs() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry_with_advance(alphabet, 1, vertices, indices, 3.0);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, -2.0), item(entry, 2.5, -2.0)]);
+
+        assert_eq!(na::Vector2::new(0.0, 2.0), flatland.caret_position(group, 0));
+        assert_eq!(na::Vector2::new(2.5, 2.0), flatland.caret_position(group, 1));
+        assert_eq!(na::Vector2::new(5.5, 2.0), flatland.caret_position(group, 2));
+        assert_eq!(na::Vector2::new(5.5, 2.0), flatland.caret_position(group, 10));
+
+        flatland.update_items(group, [].iter());
+        assert_eq!(na::Vector2::zeros(), flatland.caret_position(group, 0));
+    }
+
+    #[test]
+    fn char_index_at_picks_the_closest_boundary_on_the_closest_line() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry_with_advance(alphabet, 1, vertices, indices, 2.0);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0), item(entry, 2.0, 0.0), item(entry, 0.0, -10.0)]);
+
+        assert_eq!(0, flatland.char_index_at(group, na::Vector2::new(-5.0, -1.0)));
+        assert_eq!(1, flatland.char_index_at(group, na::Vector2::new(2.9, 0.0)));
+        // the end of the first line is the start of the second
+        assert_eq!(2, flatland.char_index_at(group, na::Vector2::new(3.5, 2.0)));
+        assert_eq!(3, flatland.char_index_at(group, na::Vector2::new(1.5, 9.0)));
+
+        flatland.update_items(group, [].iter());
+        assert_eq!(0, flatland.char_index_at(group, na::Vector2::new(1.0, 0.0)));
+    }
+
+    #[test]
+    fn selections_get_a_rectangle_per_line_behind_the_glyphs() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry_with_advance(alphabet, 1, vertices, indices, 2.0);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0), item(entry, 2.0, 0.0), item(entry, 0.0, -10.0)]);
+
+        let highlight = na::Vector4::new(0, 0, 255, 64);
+        flatland.update_selection(group, 3, 1, highlight);
+        let selection_items = flatland.group_data[group].selection_items.clone();
+        assert_eq!(vec![
+            QuadItem { min: na::Vector2::new(2.0, 0.0), max: na::Vector2::new(4.0, 1.0) },
+            QuadItem { min: na::Vector2::new(0.0, -10.0), max: na::Vector2::new(2.0, -9.0) },
+        ], selection_items);
+
+        // moving the selection reuses the unit quad
+        let vertices_len = flatland.alphabet_vertices_len();
+        flatland.update_selection(group, 0, 3, highlight);
+        assert_eq!(1, flatland.alphabet_data[alphabet].entries.len());
+        assert_eq!(vertices_len, flatland.alphabet_vertices_len());
+        flatland.update_selection(group, 3, 1, highlight);
+
+        let colors: Vec<_> = flatland.group_data[group].draw_items().map(|(_, color)| color).collect();
+        assert_eq!(vec![highlight, highlight], colors[..2].to_vec());
+        assert_eq!(5, flatland.group_data[group].draw_items_len());
+
+        flatland.update_selection(group, 1, 1, highlight);
+        assert!(flatland.group_data[group].selection_items.is_empty());
+    }
+
+    #[test]
+    fn anchored_groups_follow_the_viewport_size() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let transform = na::Projective3::from_matrix_unchecked(na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(2.0, -2.0, 1.0)));
+        let group = flatland.create_flatland_group_with_items(&transform, na::Vector4::new(0, 0, 0, 255), alphabet, vec![]);
+
+        flatland.set_viewport_size(na::Vector2::new(800.0, 600.0));
+        flatland.update_screen_anchor(group, Some((Anchor::BottomRight, na::Vector2::new(-100.0, -20.0))));
+        let matrix = *flatland.group_data[group].transform.matrix();
+        assert_eq!((700.0, 580.0), (matrix[(0, 3)], matrix[(1, 3)]));
+        assert_eq!((2.0, -2.0), (matrix[(0, 0)], matrix[(1, 1)]));
+
+        flatland.set_viewport_size(na::Vector2::new(1024.0, 768.0));
+        let matrix = *flatland.group_data[group].transform.matrix();
+        assert_eq!((924.0, 748.0), (matrix[(0, 3)], matrix[(1, 3)]));
+
+        flatland.update_screen_anchor(group, None);
+        flatland.set_viewport_size(na::Vector2::new(640.0, 480.0));
+        let matrix = *flatland.group_data[group].transform.matrix();
+        assert_eq!((924.0, 748.0), (matrix[(0, 3)], matrix[(1, 3)]));
+    }
+
+    #[test]
+    fn glyph_effects_change_each_item_draw() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0), item(entry, 1.0, 0.0), item(entry, 2.0, 0.0)]);
+
+        let command_counts = |flatland: &Flatland| flatland.draw_commands().0.iter().map(|c| c.count).collect::<Vec<u32>>();
+        flatland.update_glyph_effect(group, Some(GlyphEffect::Typewriter { chars_per_sec: 2.0 }));
+        assert!(flatland.draw_invalidated);
+        flatland.draw_invalidated = false;
+        flatland.advance_animations(0.75);
+        assert!(flatland.draw_invalidated, "the commands of the next frame are uploaded again");
+        assert_eq!(vec![3, 3, 0], command_counts(&flatland));
+        assert!(flatland.group_data[group].is_glyph_effect_playing());
+        flatland.draw_invalidated = false;
+        flatland.advance_animations(1.0);
+        assert!(flatland.draw_invalidated);
+        assert!(!flatland.group_data[group].is_glyph_effect_playing());
+        assert_eq!(vec![3, 3, 3], command_counts(&flatland));
+
+        let quarter_turn = ::std::f32::consts::FRAC_PI_2;
+        flatland.update_glyph_effect(group, Some(GlyphEffect::Wave { amplitude: 2.0, frequency: quarter_turn, speed: quarter_turn }));
+        flatland.advance_animations(1.0);
+        let offsets: Vec<f32> = flatland.groups_draw_data().map(|d| d.y_offset.round()).collect();
+        assert_eq!(vec![2.0, 0.0, -2.0], offsets);
+    }
+
+    #[test]
+    fn weights_draw_generated_entries_between_regular_and_bold() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (regular, indices) = triangle(2.0);
+        let (bold, _) = triangle(4.0);
+        let entry = flatland.add_interpolable_alphabet_entry(alphabet, 1, (regular, indices), bold, 5.0);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        let first_index = |flatland: &Flatland| flatland.draw_commands().0[0].first_index;
+        let regular_first_index = first_index(&flatland);
+
+        flatland.draw_invalidated = false;
+        flatland.update_weight(group, 0.5);
+        assert!(flatland.draw_invalidated, "the commands of the new weight are uploaded");
+        let half = flatland.alphabet_data[alphabet].weighted_entry(entry, 0.5);
+        assert_ne!(entry, half);
+        assert_ne!(regular_first_index, first_index(&flatland));
+        let bounds = flatland.alphabet_metrics(alphabet).bounds(half).unwrap();
+        assert_eq!((3.0, 3.0), (bounds.max.x, bounds.max.y));
+
+        // weights in the same step share the generated entry
+        let entries = flatland.alphabet_data[alphabet].entries.len();
+        flatland.update_weight(group, 0.51);
+        assert_eq!(entries, flatland.alphabet_data[alphabet].entries.len());
+
+        flatland.update_weight(group, 0.0);
+        assert_eq!(regular_first_index, first_index(&flatland));
+    }
+
+    #[test]
+    fn dirty_screen_rect_covers_old_and_new_places_of_changed_groups() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(10.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let at = |x: f32, y: f32| na::Projective3::from_matrix_unchecked(na::Matrix4::new_translation(&na::Vector3::new(x, y, 0.0)));
+        let moving = flatland.create_flatland_group_with_items(&at(20.0, 50.0), na::Vector4::new(0, 0, 0, 255), alphabet, vec![item(entry, 0.0, 0.0)]);
+        let still = flatland.create_flatland_group_with_items(&at(70.0, 50.0), na::Vector4::new(0, 0, 0, 255), alphabet, vec![item(entry, 0.0, 0.0)]);
+
+        let viewport = na::Vector2::new(100.0, 100.0);
+        let vp = crate::ortho_screen(viewport.x, viewport.y);
+        assert_eq!(Some(na::Vector4::new(0.0, 0.0, 100.0, 100.0)), flatland.take_dirty_screen_rect(&vp, viewport));
+        assert_eq!(None, flatland.take_dirty_screen_rect(&vp, viewport));
+
+        flatland.update_transform(moving, &at(30.0, 50.0));
+        // rounded outwards to whole pixels
+        let covers = |rect: na::Vector4<f32>, left: f32, right: f32| {
+            (left - 1.0..=left).contains(&rect.x) && (right..=right + 1.0).contains(&(rect.x + rect.z))
+        };
+        let moved = flatland.take_dirty_screen_rect(&vp, viewport).unwrap();
+        assert!(covers(moved, 20.0, 40.0), "{:?}", moved);
+
+        flatland.delete_flatland_group(still);
+        let deleted = flatland.take_dirty_screen_rect(&vp, viewport).unwrap();
+        assert!(covers(deleted, 70.0, 80.0), "{:?}", deleted);
+        assert_eq!((moved.y, moved.w), (deleted.y, deleted.w));
+        assert_eq!(None, flatland.take_dirty_screen_rect(&vp, viewport));
+    }
+
+    #[test]
+    fn duplicated_groups_change_independently() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let items = vec![
+            item(entry, 0.0, 0.0),
+            item(entry, 2.0, 0.0),
+        ];
+        let transform = na::Projective3::from_matrix_unchecked(na::Matrix4::new_translation(&na::Vector3::new(5.0, 0.0, 0.0)));
+        let original = flatland.create_flatland_group_with_items(&transform, na::Vector4::new(255, 0, 0, 255), alphabet, items);
+
+        let copy = flatland.duplicate_flatland_group(original);
+        assert_ne!(original, copy);
+        let offsets = |slot| flatland.group_data[slot].items.iter().map(|i| i.x()).collect::<Vec<_>>();
+        assert_eq!(offsets(original), offsets(copy));
+        assert_eq!(transform.matrix(), flatland.group_data[copy].transform.matrix());
+        assert_eq!(na::Vector4::new(255, 0, 0, 255), flatland.group_data[copy].color);
+
+        flatland.update_color(copy, na::Vector4::new(0, 0, 255, 255));
+        flatland.delete_flatland_group(original);
+        assert_eq!(na::Vector4::new(0, 0, 255, 255), flatland.group_data[copy].color);
+        assert_eq!(2, flatland.group_data[copy].items.len());
+    }
+
+    #[test]
+    fn triangle_count_covers_repeated_and_layered_entries() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let single = flatland.add_alphabet_entry(alphabet, 1, vertices.clone(), indices.clone());
+        let (square, square_indices) = (
+            vec![vertices[0], vertices[1], vertices[2], vertices[0]],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        let layered = flatland.add_layered_alphabet_entry(alphabet, 2, vec![
+            (na::Vector4::new(255, 0, 0, 255), vertices, indices),
+            (na::Vector4::new(0, 255, 0, 255), square, square_indices),
+        ], 0.0);
+
+        let data = &flatland.alphabet_data[alphabet];
+        assert_eq!(0, data.triangle_count(&[]));
+        assert_eq!(2, data.triangle_count(&[single, single]));
+        assert_eq!(4, data.triangle_count(&[single, layered]));
+    }
+
+    #[test]
+    fn flips_mirror_about_the_bounds_center_in_batches_of_their_own() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(2.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let color = na::Vector4::new(0, 0, 0, 255);
+        let flipped = flatland.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet, vec![item(entry, 0.0, 0.0), item(entry, 8.0, 0.0)]);
+        flatland.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet, vec![item(entry, 0.0, 0.0)]);
+
+        flatland.update_flip(flipped, true, false);
+        let matrix = *flatland.group_data[flipped].placed_transform().matrix();
+        assert_eq!((-1.0, 1.0, 10.0), (matrix[(0, 0)], matrix[(1, 1)], matrix[(0, 3)]));
+        let (_, batches) = flatland.draw_commands();
+        assert_eq!(vec![(true, 2), (false, 1)], batches.iter().map(|b| (b.mirrored, b.command_count)).collect::<Vec<_>>());
+
+        // flipping both axes is a half turn, which keeps the winding
+        flatland.update_flip(flipped, true, true);
+        assert!(!flatland.group_data[flipped].is_mirrored());
+        let (_, batches) = flatland.draw_commands();
+        assert_eq!(1, batches.len());
+
+        flatland.update_flip(flipped, false, false);
+        assert_eq!(na::Matrix4::identity(), *flatland.group_data[flipped].placed_transform().matrix());
+    }
+
+    #[test]
+    fn points_hit_glyph_triangles_not_just_bounds() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(4.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let items = vec![
+            item(entry, 0.0, 0.0),
+            FlatlandItem { rotation: ::std::f32::consts::PI, ..item(entry, 10.0, 0.0) },
+        ];
+        let group = group(&mut flatland, alphabet, items);
+
+        // the point is given with the y of the transform's space
+        assert!(flatland.point_in_glyphs(group, na::Vector2::new(1.0, -1.0)));
+        assert!(!flatland.point_in_glyphs(group, na::Vector2::new(3.5, -3.5)));
+        assert!(!flatland.point_in_glyphs(group, na::Vector2::new(1.0, 1.0)));
+
+        // the second triangle is turned around its origin
+        assert!(flatland.point_in_glyphs(group, na::Vector2::new(9.0, 1.0)));
+        assert!(!flatland.point_in_glyphs(group, na::Vector2::new(11.0, -1.0)));
+    }
+
+    #[test]
+    fn picking_colors_number_groups_in_order_without_shadows() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let color = na::Vector4::new(10, 20, 30, 128);
+        let groups: Vec<GroupSlot> = (0..3)
+            .map(|_| flatland.create_flatland_group_with_items(&na::Projective3::identity(), color, alphabet, vec![item(entry, 0.0, 0.0)]))
+            .collect();
+        flatland.update_usage(groups[2], Usage::Static);
+        flatland.update_shadow(groups[1], Some((na::Vector2::new(1.0, 1.0), color)));
+
+        let colors = |usage| flatland.picking_draw_data(usage).map(|d| d.color).collect::<Vec<_>>();
+        assert_eq!(vec![picking_color(2)], colors(Usage::Static));
+        assert_eq!(vec![picking_color(0), na::Vector4::new(0, 0, 0, 0), picking_color(1)], colors(Usage::Dynamic));
+
+        for &index in &[0, 255, 256, 70_000] {
+            let c = picking_color(index);
+            assert_eq!(Some(index), picking_index([c.x, c.y, c.z, c.w]));
+        }
+        assert_eq!(None, picking_index([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn gradients_color_glyphs_by_their_offsets() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0), item(entry, 10.0, 0.0), item(entry, 0.0, 20.0)]);
+        flatland.update_decoration(group, Decoration::Underline { thickness: 1.0 });
+
+        flatland.apply_color_gradient(group, |p| na::Vector4::new(p.x as u8, (-p.y) as u8, 0, 255));
+        let colors: Vec<_> = flatland.groups_draw_data().map(|d| (d.color.x, d.color.y)).collect();
+        assert_eq!((0, 0), colors[0]);
+        assert_eq!((10, 0), colors[1]);
+        assert_eq!((0, 20), colors[2]);
+        assert!(colors[3..].iter().all(|&c| c == (0, 0)));
+
+        flatland.update_items(group, vec![item(entry, 5.0, 0.0)].iter());
+        assert!(flatland.groups_draw_data().all(|d| (d.color.x, d.color.y) == (0, 0)));
+    }
+
+    #[test]
+    fn decorations_scale_the_unit_quad_without_growing_the_alphabet() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry_with_advance(alphabet, 1, vertices, indices, 4.0);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 2.0, 0.0), item(entry, 6.0, 0.0)]);
+        flatland.update_decoration_baseline(group, DecorationBaseline { underline: -3.0, strikethrough: 5.0 });
+        let hash = flatland.alphabet_data[alphabet].content_hash();
+
+        for thickness in 1..20 {
+            flatland.update_decoration(group, Decoration::Underline { thickness: thickness as f32 });
+        }
+        let vertices_len = flatland.alphabet_vertices_len();
+        flatland.update_decoration(group, Decoration::UnderlineAndStrikethrough { thickness: 2.0 });
+
+        assert_eq!(1, flatland.alphabet_data[alphabet].entries.len());
+        assert_eq!(hash, flatland.alphabet_data[alphabet].content_hash());
+        assert_eq!(vertices_len, flatland.alphabet_vertices_len());
+        assert!(flatland.find_duplicate_alphabets().is_empty());
+
+        // corners of the unit quad, placed like the vertices in the shader
+        let draws: Vec<FlatlanderGroupDrawData> = flatland.groups_draw_data().collect();
+        assert_eq!(4, draws.len());
+        let corner = |d: &FlatlanderGroupDrawData, x: f32, y: f32| {
+            let p = model_matrix(&d.transform) * na::Vector4::new(x + d.x_offset, y + d.y_offset, 0.0, 1.0);
+            (p.x, p.y)
+        };
+        assert_eq!(((2.0, 4.0), (10.0, 2.0)), (corner(&draws[2], 0.0, 0.0), corner(&draws[2], 1.0, 1.0)));
+        assert_eq!(((2.0, -4.0), (10.0, -6.0)), (corner(&draws[3], 0.0, 0.0), corner(&draws[3], 1.0, 1.0)));
+        let (first, second) = (draws[2].cmd.first_index, draws[3].cmd.first_index);
+        assert_eq!(first, second);
+        assert_eq!(Some(GlyphBounds { min: na::Vector2::new(2.0, -4.0), max: na::Vector2::new(10.0, 6.0) }), flatland.group_bounds(group));
+    }
+
+    #[test]
+    fn world_quads_cover_their_rectangles_without_touching_groups() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(1.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        group(&mut flatland, alphabet, vec![item(entry, 0.0, 0.0)]);
+        let (_, generation) = flatland.groups_changed_since(0);
+
+        let rects = [(na::Vector2::new(-10.0, 2.0), na::Vector2::new(20.0, 0.5)), (na::Vector2::new(3.0, -5.0), na::Vector2::new(0.5, 10.0))];
+        let draws = flatland.world_quads_draw_data(&rects, na::Vector4::new(0, 255, 0, 255));
+        let vertices_len = flatland.alphabet_vertices_len();
+        flatland.world_quads_draw_data(&rects, na::Vector4::new(0, 255, 0, 255));
+
+        assert_eq!(vertices_len, flatland.alphabet_vertices_len());
+        assert_eq!(1, flatland.group_data.len());
+        assert!(flatland.groups_changed_since(generation).0.is_empty());
+        let corner = |d: &FlatlanderGroupDrawData, x: f32, y: f32| {
+            let p = model_matrix(&d.transform) * na::Vector4::new(x + d.x_offset, y + d.y_offset, 0.0, 1.0);
+            (p.x, p.y)
+        };
+        assert_eq!(((-10.0, 2.5), (10.0, 2.0)), (corner(&draws[0], 0.0, 0.0), corner(&draws[0], 1.0, 1.0)));
+        assert_eq!(((3.0, 5.0), (3.5, -5.0)), (corner(&draws[1], 0.0, 0.0), corner(&draws[1], 1.0, 1.0)));
+        let (first, second) = (draws[0].cmd.base_instance, draws[1].cmd.base_instance);
+        assert_eq!((0, 1), (first, second));
+    }
+
+    #[test]
+    fn backgrounds_scale_the_unit_quad_behind_the_glyphs() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = triangle(2.0);
+        let entry = flatland.add_alphabet_entry(alphabet, 1, vertices, indices);
+        let group = group(&mut flatland, alphabet, vec![item(entry, 1.0, 0.0), item(entry, 5.0, 0.0)]);
+
+        for padding in 1..20 {
+            flatland.update_background(group, Some((na::Vector4::new(255, 0, 0, 255), padding as f32)));
+        }
+        let vertices_len = flatland.alphabet_vertices_len();
+        flatland.update_background(group, Some((na::Vector4::new(255, 0, 0, 255), 0.5)));
+        assert_eq!(1, flatland.alphabet_data[alphabet].entries.len());
+        assert_eq!(vertices_len, flatland.alphabet_vertices_len());
+
+        let background = flatland.groups_draw_data().next().unwrap();
+        assert_eq!(na::Vector4::new(255, 0, 0, 255), background.color);
+        let corner = |x: f32, y: f32| {
+            let p = model_matrix(&background.transform) * na::Vector4::new(x + background.x_offset, y + background.y_offset, 0.0, 1.0);
+            (p.x, p.y)
+        };
+        assert_eq!(((0.5, 0.5), (7.5, -2.5)), (corner(0.0, 0.0), corner(1.0, 1.0)));
+
+        flatland.update_background(group, None);
+        assert!(flatland.group_data[group].background_item.is_none());
+        assert_eq!(2, flatland.groups_draw_data().count());
+    }
+
+    #[test]
+    fn appending_entries_keeps_indices_and_compacting_remaps_them() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (small, small_indices) = triangle(1.0);
+        let (big, big_indices) = triangle(2.0);
+
+        let replaced = flatland.add_alphabet_entry(alphabet, 1, small.clone(), small_indices.clone());
+        let used = flatland.add_alphabet_entry(alphabet, 2, big.clone(), big_indices.clone());
+        let group = group(&mut flatland, alphabet, vec![item(used, 0.0, 0.0)]);
+        let drawn = flatland.group_bounds(group).unwrap();
+
+        // appending, including for an id that already has an entry, leaves existing entries alone
+        flatland.add_alphabet_shape(alphabet, small.clone(), vec![0, 2, 1]);
+        let kept = flatland.add_alphabet_entry(alphabet, 1, big, big_indices);
+        assert_eq!(used, flatland.group_data[group].items[0].alphabet_entry_index);
+        assert_eq!(drawn.max, flatland.group_bounds(group).unwrap().max);
+
+        let remap = flatland.compact_alphabet(alphabet);
+        assert_eq!(vec![None, Some(0), None, Some(1)], remap);
+        assert_eq!(Some(1), flatland.get_alphabet_entry_index(alphabet, 1));
+        assert_eq!(Some(0), flatland.get_alphabet_entry_index(alphabet, 2));
+        assert_eq!(remap[used], Some(flatland.group_data[group].items[0].alphabet_entry_index));
+        assert_eq!(drawn.max, flatland.group_bounds(group).unwrap().max);
+        assert_eq!((None, Some(1)), (remap[replaced], remap[kept]));
+        assert_eq!(2, flatland.alphabet_data[alphabet].entries.len());
+        assert_eq!(6, flatland.alphabet_vertex_count(alphabet));
+        assert!(flatland.has_wasted_alphabet_space());
+    }
+
+    #[test]
+    fn fallback_glyphs_are_centered_in_their_advance() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let (vertices, indices) = super::super::glyph::tofu_box(10.0);
+
+        let index = flatland.set_alphabet_fallback_glyph(alphabet, vertices, indices);
+        assert_eq!(Some(index), flatland.alphabet_data[alphabet].fallback_entry);
+        assert_eq!(10.0, flatland.alphabet_metrics(alphabet).advance(index));
+
+        let bounds = flatland.alphabet_metrics(alphabet).bounds(index).unwrap();
+        assert_eq!((1.0, 0.0), (bounds.min.x, bounds.min.y));
+        assert_eq!((9.0, 12.5), (bounds.max.x, bounds.max.y));
+        assert_eq!(8, flatland.alphabet_data[alphabet].triangle_count(&[index]));
+    }
+
+    #[test]
+    fn world_anchored_groups_follow_their_projection_and_hide_behind_the_camera() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let transform = na::Projective3::from_matrix_unchecked(na::Matrix4::new_scaling(2.0));
+        let group = flatland.create_flatland_group_with_items(&transform, na::Vector4::new(0, 0, 0, 255), alphabet, vec![]);
+        flatland.update_world_anchor(group, Some(na::Point3::new(0.5, 0.5, -2.0)));
+
+        // w is the distance in front of the camera, looking down -z
+        let mut vp = na::Matrix4::identity();
+        vp[(3, 2)] = -1.0;
+        vp[(3, 3)] = 0.0;
+        let viewport = na::Vector2::new(800.0, 600.0);
+
+        flatland.update_anchored_groups(&vp, viewport);
+        let matrix = *flatland.group_data[group].transform.matrix();
+        assert_eq!((500.0, 225.0), (matrix[(0, 3)], matrix[(1, 3)]));
+        assert_eq!(2.0, matrix[(0, 0)]);
+        assert_eq!(CullResult { visible: 1, culled: 0 }, flatland.cull_result(|_, _| true));
+
+        flatland.update_world_anchor(group, Some(na::Point3::new(0.5, 0.5, 2.0)));
+        flatland.update_anchored_groups(&vp, viewport);
+        assert_eq!(CullResult { visible: 0, culled: 1 }, flatland.cull_result(|_, _| true));
+        assert!(flatland.groups_draw_data().all(|d| !d.visible));
+
+        flatland.update_world_anchor(group, None);
+        assert_eq!(CullResult { visible: 1, culled: 0 }, flatland.cull_result(|_, _| true));
+    }
+
+    #[test]
+    fn palette_cycles_step_the_group_color() {
+        let mut flatland = Flatland::new();
+        let alphabet = flatland.create_alphabet();
+        let white = na::Vector4::new(255, 255, 255, 255);
+        let group = flatland.create_flatland_group_with_items(&na::Projective3::identity(), white, alphabet, vec![]);
+        let palette = vec![na::Vector4::new(255, 0, 0, 255), na::Vector4::new(0, 255, 0, 255), na::Vector4::new(0, 0, 255, 255)];
+
+        flatland.update_palette_cycle(group, palette.clone(), 3.0);
+        assert_eq!(palette[0], flatland.group_data[group].color);
+
+        let (_, generation) = flatland.groups_changed_since(0);
+        flatland.advance_animations(0.5);
+        assert_eq!(palette[0], flatland.group_data[group].color);
+        assert!(flatland.groups_changed_since(generation).0.is_empty());
+
+        flatland.advance_animations(1.0);
+        assert_eq!(palette[1], flatland.group_data[group].color);
+        flatland.advance_animations(1.0);
+        assert_eq!(palette[2], flatland.group_data[group].color);
+        flatland.advance_animations(1.0);
+        assert_eq!(palette[0], flatland.group_data[group].color);
+
+        // a single color stays, an empty palette leaves the cycle running
+        flatland.update_palette_cycle(group, vec![white], 3.0);
+        flatland.advance_animations(2.0);
+        assert_eq!(white, flatland.group_data[group].color);
+
+        flatland.update_palette_cycle(group, palette.clone(), 3.0);
+        flatland.advance_animations(1.5);
+        flatland.update_palette_cycle(group, vec![], 3.0);
+        assert_eq!(palette[1], flatland.group_data[group].color);
+        flatland.advance_animations(1.0);
+        assert_eq!(palette[2], flatland.group_data[group].color);
+
+        // clearing stops the cycle where it is
+        flatland.clear_palette_cycle(group);
+        flatland.advance_animations(1.0);
+        assert_eq!(palette[2], flatland.group_data[group].color);
+        assert!(flatland.group_data[group].palette_cycle.is_none());
+    }
+
 }
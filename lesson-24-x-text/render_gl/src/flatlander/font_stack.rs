@@ -0,0 +1,129 @@
+use crate::na;
+use std::collections::HashMap;
+use super::layout::{self, LayoutContext};
+use super::{Alphabet, AlphabetSlot, FlatlandGroup, FlatlandItem};
+
+/// Alphabets tried in order for each character, e.g. a latin font, then a CJK font
+/// and a symbol font for what neither covers.
+#[derive(Default)]
+pub struct FontStack {
+    fonts: Vec<(Alphabet, HashMap<char, usize>)>,
+}
+
+impl FontStack {
+    pub fn new() -> FontStack {
+        FontStack::default()
+    }
+
+    /// Adds an alphabet after the others, with the entry of each character it covers.
+    pub fn push(&mut self, alphabet: Alphabet, char_to_entry: HashMap<char, usize>) {
+        self.fonts.push((alphabet, char_to_entry));
+    }
+
+    /// Places `text` on a single line, each character with the first alphabet covering it,
+    /// like `Flatlander::draw_text` does with a single alphabet.
+    ///
+    /// Kerning only applies between neighbours from the same alphabet. Characters no alphabet
+    /// covers are skipped.
+    pub fn layout(&self, context: &LayoutContext, text: &str) -> Vec<(AlphabetSlot, FlatlandItem)> {
+        self.layout_runs(context, text)
+            .into_iter()
+            .flat_map(|(font, items)| {
+                let slot = self.fonts[font].0.slot;
+                items.into_iter().map(move |item| (slot, item))
+            })
+            .collect()
+    }
+
+    /// Draws `text` laid out like `layout` as a group per run of characters from the same alphabet,
+    /// since the items of a group share an alphabet. The groups share `transform` and `color`.
+    pub fn draw_text(&self, context: &LayoutContext, text: &str, transform: &na::Projective3<f32>, color: na::Vector4<u8>) -> Vec<FlatlandGroup> {
+        self.layout_runs(context, text)
+            .into_iter()
+            .map(|(font, items)| FlatlandGroup::new(transform, color, self.fonts[font].0.clone(), items))
+            .collect()
+    }
+
+    /// Items of each run of characters covered by the same font, with the pen carried across runs.
+    fn layout_runs(&self, context: &LayoutContext, text: &str) -> Vec<(usize, Vec<FlatlandItem>)> {
+        let mut pen = 0.0;
+
+        self.runs(text)
+            .into_iter()
+            .map(|(font, entry_indices)| {
+                let alphabet = &self.fonts[font].0;
+                let advances = alphabet.flatland.borrow().alphabet_metrics(alphabet.slot).pen_advances(&entry_indices);
+                let start = context.offset(pen);
+                pen += advances.iter().sum::<f32>();
+
+                let items = layout::layout_line(context, &entry_indices, &advances)
+                    .into_iter()
+                    .map(|item| FlatlandItem { x_offset: item.x_offset + start, ..item })
+                    .collect();
+                (font, items)
+            })
+            .collect()
+    }
+
+    /// Font index and entry indices of each run of consecutive characters covered by the same font.
+    fn runs(&self, text: &str) -> Vec<(usize, Vec<usize>)> {
+        let mut runs: Vec<(usize, Vec<usize>)> = Vec::new();
+
+        for c in text.chars() {
+            let found = self.fonts.iter()
+                .enumerate()
+                .filter_map(|(font, &(_, ref char_to_entry))| char_to_entry.get(&c).map(|&entry| (font, entry)))
+                .next();
+
+            if let Some((font, entry)) = found {
+                match runs.last_mut() {
+                    Some(&mut (last, ref mut entries)) if last == font => entries.push(entry),
+                    _ => runs.push((font, vec![entry])),
+                }
+            }
+        }
+
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::super::flatland::Flatland;
+
+    fn alphabet(flatland: &Rc<RefCell<Flatland>>, advance: f32) -> Alphabet {
+        let slot = flatland.borrow_mut().create_alphabet();
+        flatland.borrow_mut().add_alphabet_entry_with_advance(slot, 0, Vec::new(), Vec::new(), advance);
+        Alphabet { slot, flatland: flatland.clone() }
+    }
+
+    #[test]
+    fn characters_use_the_first_alphabet_covering_them() {
+        let flatland = Rc::new(RefCell::new(Flatland::new()));
+        let latin = alphabet(&flatland, 10.0);
+        let symbols = alphabet(&flatland, 20.0);
+        let (latin_slot, symbols_slot) = (latin.slot, symbols.slot);
+
+        let mut stack = FontStack::new();
+        stack.push(latin, [('a', 0)].iter().cloned().collect());
+        stack.push(symbols, [('a', 0), ('*', 0)].iter().cloned().collect());
+
+        let placed: Vec<_> = stack.layout(&LayoutContext::default(), "a**a?a")
+            .into_iter()
+            .map(|(slot, item)| (slot, item.x()))
+            .collect();
+        assert_eq!(vec![
+            (latin_slot, 0.0),
+            (symbols_slot, 10.0),
+            (symbols_slot, 30.0),
+            (latin_slot, 50.0),
+            (latin_slot, 60.0),
+        ], placed);
+
+        let runs: Vec<_> = stack.runs("a**a?a").into_iter().map(|(font, entries)| (font, entries.len())).collect();
+        assert_eq!(vec![(0, 1), (1, 2), (0, 2)], runs);
+    }
+}
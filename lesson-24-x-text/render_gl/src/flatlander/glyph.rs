@@ -0,0 +1,168 @@
+use crate::na;
+use crate::data;
+use super::{FlatlanderVertex, Winding};
+use super::flatland;
+
+/// Longest miter join, relative to half the stroke width, before it's beveled instead.
+const MITER_LIMIT: f32 = 4.0;
+
+/// Outline of a box standing on the baseline, as wide as most of `advance`, e.g. for
+/// `Alphabet::set_fallback_glyph` to make missing glyphs obvious.
+///
+/// The box leaves a tenth of the advance on either side, and is a quarter taller than the advance.
+pub fn tofu_box(advance: f32) -> (Vec<FlatlanderVertex>, Vec<u16>) {
+    let margin = advance * 0.1;
+    let thickness = advance * 0.08;
+    let min = na::Vector2::new(margin, 0.0);
+    let max = na::Vector2::new(advance - margin, advance * 1.25);
+
+    let sides = [
+        (min, na::Vector2::new(max.x, min.y + thickness)),
+        (na::Vector2::new(min.x, max.y - thickness), max),
+        (na::Vector2::new(min.x, min.y + thickness), na::Vector2::new(min.x + thickness, max.y - thickness)),
+        (na::Vector2::new(max.x - thickness, min.y + thickness), na::Vector2::new(max.x, max.y - thickness)),
+    ];
+
+    let mut vertices = Vec::with_capacity(16);
+    let mut indices = Vec::with_capacity(24);
+    for &(min, max) in sides.iter() {
+        let (side_vertices, side_indices) = flatland::quad(min, max);
+        let first = vertices.len() as u16;
+        vertices.extend(side_vertices);
+        indices.extend(side_indices.into_iter().map(|index| index + first));
+    }
+
+    (vertices, indices)
+}
+
+/// Filled ribbon `width` wide centered on each closed contour of `outline`, e.g. the flattened
+/// contours of a glyph, to add as a regular entry for text drawn as outlines only.
+///
+/// Corners are mitered, or beveled when the miter would stick out further than `MITER_LIMIT`
+/// half widths. Contours are closed from their last point back to the first; repeated points
+/// are skipped, and contours left with less than two points are ignored. Triangles are
+/// counter-clockwise, see `Alphabet::add_entry_normalized`.
+pub fn stroke(outline: &[Vec<na::Vector2<f32>>], width: f32) -> (Vec<FlatlanderVertex>, Vec<u16>) {
+    let half = width * 0.5;
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for contour in outline {
+        let mut points: Vec<na::Vector2<f32>> = Vec::with_capacity(contour.len());
+        for &point in contour {
+            if points.last() != Some(&point) {
+                points.push(point);
+            }
+        }
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() < 2 {
+            continue;
+        }
+
+        // vertices ending the incoming and starting the outgoing segment on the left, then the right
+        let count = points.len();
+        let mut joins: Vec<[u16; 4]> = Vec::with_capacity(count);
+        for i in 0..count {
+            let (previous, corner, next) = (points[(i + count - 1) % count], points[i], points[(i + 1) % count]);
+            let (incoming, outgoing) = ((corner - previous).normalize(), (next - corner).normalize());
+            let (normal_in, normal_out) = (perpendicular(incoming), perpendicular(outgoing));
+
+            let sum = normal_in + normal_out;
+            let cos_half_angle = if sum.norm() > 1e-6 { sum.normalize().dot(&normal_in) } else { 0.0 };
+
+            if cos_half_angle * MITER_LIMIT > 1.0 {
+                let miter = sum.normalize() * (half / cos_half_angle);
+                let left = push_vertex(&mut vertices, corner + miter);
+                let right = push_vertex(&mut vertices, corner - miter);
+                joins.push([left, left, right, right]);
+            } else {
+                // the outer side is cut straight, each segment keeps its width on the inner side
+                let outer = if incoming.x * outgoing.y - incoming.y * outgoing.x > 0.0 { -half } else { half };
+                let outer_in = push_vertex(&mut vertices, corner + normal_in * outer);
+                let outer_out = push_vertex(&mut vertices, corner + normal_out * outer);
+                let inner_in = push_vertex(&mut vertices, corner - normal_in * outer);
+                let inner_out = push_vertex(&mut vertices, corner - normal_out * outer);
+                indices.extend_from_slice(&[outer_in, outer_out, inner_in, outer_in, inner_in, inner_out]);
+
+                joins.push(if outer > 0.0 {
+                    [outer_in, outer_out, inner_in, inner_out]
+                } else {
+                    [inner_in, inner_out, outer_in, outer_out]
+                });
+            }
+        }
+
+        for i in 0..count {
+            let (start, end) = (joins[i], joins[(i + 1) % count]);
+            indices.extend_from_slice(&[start[1], start[3], end[2], start[1], end[2], end[0]]);
+        }
+    }
+
+    assert!(vertices.len() <= ::std::u16::MAX as usize, "stroke has too many vertices for u16 indices");
+    flatland::normalize_winding(&vertices, &mut indices, Winding::CounterClockwise);
+    (vertices, indices)
+}
+
+/// Pointing left of `direction`, with y going up.
+fn perpendicular(direction: na::Vector2<f32>) -> na::Vector2<f32> {
+    na::Vector2::new(-direction.y, direction.x)
+}
+
+fn push_vertex(vertices: &mut Vec<FlatlanderVertex>, position: na::Vector2<f32>) -> u16 {
+    vertices.push(FlatlanderVertex {
+        pos: data::f16_f16::from((position.x, position.y)),
+        normal: data::f16_f16::from((0.0, 0.0)),
+    });
+    (vertices.len() - 1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(vertices: &[FlatlanderVertex], indices: &[u16]) -> f32 {
+        let position = |index: u16| {
+            let pos = vertices[index as usize].pos;
+            na::Vector2::new(pos.d0.to_f32(), pos.d1.to_f32())
+        };
+
+        indices.chunks(3)
+            .map(|t| {
+                let (a, b, c) = (position(t[0]), position(t[1]), position(t[2]));
+                (b - a).perp(&(c - a)) * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn strokes_are_ribbons_centered_on_the_contours() {
+        let square = vec![na::Vector2::new(0.0, 0.0), na::Vector2::new(10.0, 0.0), na::Vector2::new(10.0, 10.0), na::Vector2::new(0.0, 10.0)];
+        let (vertices, indices) = stroke(&[square.clone()], 2.0);
+
+        // mitered corners, every triangle counter-clockwise; half floats round the corners a bit
+        assert_eq!((8, 24), (vertices.len(), indices.len()));
+        assert!((area(&vertices, &indices) - (12.0 * 12.0 - 8.0 * 8.0)).abs() < 0.5);
+        assert!(indices.chunks(3).all(|t| area(&vertices, t) > 0.0));
+
+        // a closing point and repeated points change nothing
+        let mut repeated = square.clone();
+        repeated.insert(1, square[0]);
+        repeated.push(square[0]);
+        assert_eq!(indices, stroke(&[repeated, vec![square[0]]], 2.0).1);
+
+        // the tip of a thin spike is beveled
+        let spike = vec![na::Vector2::new(0.0, 0.0), na::Vector2::new(20.0, 1.0), na::Vector2::new(0.0, 2.0)];
+        let (vertices, indices) = stroke(&[spike], 0.5);
+        assert_eq!(2 + 4 + 2, vertices.len());
+        assert_eq!(3 * 6 + 6, indices.len());
+        assert!(indices.chunks(3).all(|t| area(&vertices, t) > 0.0));
+
+        // the width is kept up to the tip on both sides, half of it above and below
+        for vertex in &vertices[2..6] {
+            let offset = vertex.pos.d1.to_f32() - 1.0;
+            assert!((offset.abs() - 0.25).abs() < 0.02, "{}", offset);
+        }
+    }
+}
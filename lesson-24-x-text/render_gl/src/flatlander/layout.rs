@@ -0,0 +1,764 @@
+use metrohash::MetroHashMap;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use crate::na;
+use super::{FlatlandItem, ShapedGlyph};
+
+const BEZIER_SUBDIVISIONS: usize = 64;
+
+/// Units of the layout inputs.
+///
+/// Advances and positions given to layout helpers are in pixels and are converted to
+/// item offsets, which are in the units of the alphabet geometry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LayoutContext {
+    pub pixels_per_unit: f32,
+}
+
+impl LayoutContext {
+    pub fn units(&self, pixels: f32) -> f32 {
+        pixels / self.pixels_per_unit
+    }
+
+    /// Item offset for a position in pixels, in subpixels; see `FlatlandItem::snapped` for whole units.
+    pub fn offset(&self, pixels: f32) -> i32 {
+        super::to_subpixels(self.units(pixels))
+    }
+}
+
+impl Default for LayoutContext {
+    fn default() -> LayoutContext {
+        LayoutContext { pixels_per_unit: 1.0 }
+    }
+}
+
+struct CubicBezier {
+    p0: na::Vector2<f32>,
+    p1: na::Vector2<f32>,
+    p2: na::Vector2<f32>,
+    p3: na::Vector2<f32>,
+}
+
+impl CubicBezier {
+    fn point(&self, t: f32) -> na::Vector2<f32> {
+        let u = 1.0 - t;
+        self.p0 * (u * u * u)
+            + self.p1 * (3.0 * u * u * t)
+            + self.p2 * (3.0 * u * t * t)
+            + self.p3 * (t * t * t)
+    }
+
+    fn tangent(&self, t: f32) -> na::Vector2<f32> {
+        let u = 1.0 - t;
+        (self.p1 - self.p0) * (3.0 * u * u)
+            + (self.p2 - self.p1) * (6.0 * u * t)
+            + (self.p3 - self.p2) * (3.0 * t * t)
+    }
+
+    /// Cumulative arc length at evenly spaced `t`, approximated by the subdivided polyline.
+    fn arc_lengths(&self) -> Vec<f32> {
+        let mut lengths = Vec::with_capacity(BEZIER_SUBDIVISIONS + 1);
+        let mut previous = self.p0;
+        let mut length = 0.0;
+
+        lengths.push(0.0);
+        for i in 1..=BEZIER_SUBDIVISIONS {
+            let point = self.point(i as f32 / BEZIER_SUBDIVISIONS as f32);
+            length += (point - previous).norm();
+            lengths.push(length);
+            previous = point;
+        }
+
+        lengths
+    }
+}
+
+/// Maps a distance along the curve to the curve parameter, clamped to `[0, 1]`.
+fn t_at_length(lengths: &[f32], s: f32) -> f32 {
+    let total = lengths[lengths.len() - 1];
+    if s <= 0.0 || total <= 0.0 {
+        return 0.0;
+    }
+    if s >= total {
+        return 1.0;
+    }
+
+    let segment = match lengths.iter().position(|l| *l >= s) {
+        Some(i) => i - 1,
+        None => return 1.0,
+    };
+    let segment_length = lengths[segment + 1] - lengths[segment];
+    let fraction = if segment_length > 0.0 { (s - lengths[segment]) / segment_length } else { 0.0 };
+
+    (segment as f32 + fraction) / BEZIER_SUBDIVISIONS as f32
+}
+
+/// Extent of an entry's geometry, in alphabet geometry units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlyphBounds {
+    pub min: na::Vector2<f32>,
+    pub max: na::Vector2<f32>,
+}
+
+/// Layout metrics of alphabet entries, indexed by entry index.
+///
+/// Plain data, so layout can run without a GL context, e.g. from metrics
+/// exported with `Alphabet::metrics`.
+#[derive(Clone, Debug, Default)]
+pub struct GlyphMetrics {
+    pub advances: Vec<f32>,
+    /// `None` for entries without geometry, such as spaces.
+    pub bounds: Vec<Option<GlyphBounds>>,
+    /// Adjustment of the left entry's advance when followed by the right entry.
+    pub kerning: MetroHashMap<(usize, usize), f32>,
+}
+
+impl GlyphMetrics {
+    pub fn advance(&self, entry_index: usize) -> f32 {
+        self.advances.get(entry_index).cloned().unwrap_or(0.0)
+    }
+
+    pub fn bounds(&self, entry_index: usize) -> Option<GlyphBounds> {
+        self.bounds.get(entry_index).and_then(|b| *b)
+    }
+
+    pub fn kerning(&self, left: usize, right: usize) -> f32 {
+        self.kerning.get(&(left, right)).cloned().unwrap_or(0.0)
+    }
+
+    /// Advance of each entry in the sequence, adjusted by its kerning with the entry that follows.
+    pub fn pen_advances(&self, entry_indices: &[usize]) -> Vec<f32> {
+        entry_indices.iter()
+            .enumerate()
+            .map(|(i, &index)| {
+                let kerning = entry_indices.get(i + 1)
+                    .map(|&next| self.kerning(index, next))
+                    .unwrap_or(0.0);
+                self.advance(index) + kerning
+            })
+            .collect()
+    }
+}
+
+/// Width of the entries laid out on a line, in pixels.
+pub fn measure_line(metrics: &GlyphMetrics, entry_indices: &[usize]) -> f32 {
+    metrics.pen_advances(entry_indices).iter().sum()
+}
+
+/// Width and height in pixels of `text` with glyphs spaced like `Flatlander::draw_text`
+/// and a line per `\n`, without creating any items.
+///
+/// The width is that of the widest line; every line, even an empty one, adds `line_height`.
+/// Characters missing from `char_to_entry` are skipped.
+pub fn measure_text<S: BuildHasher>(metrics: &GlyphMetrics, char_to_entry: &HashMap<char, usize, S>, text: &str, line_height: f32) -> (f32, f32) {
+    let mut width: f32 = 0.0;
+    let mut lines = 0;
+
+    for line in text.split('\n') {
+        let entry_indices: Vec<usize> = line.chars()
+            .filter_map(|c| char_to_entry.get(&c).cloned())
+            .collect();
+        width = width.max(measure_line(metrics, &entry_indices));
+        lines += 1;
+    }
+
+    (width, lines as f32 * line_height)
+}
+
+/// Entry of each character of `text`, with `fallback` for characters missing from `char_to_entry`,
+/// or none if there is no fallback, and the missing characters once each in order of appearance.
+pub fn text_entries<S: BuildHasher>(char_to_entry: &HashMap<char, usize, S>, text: &str, fallback: Option<usize>) -> (Vec<usize>, Vec<char>) {
+    let mut missing = Vec::new();
+    let entry_indices = text.chars()
+        .filter_map(|c| match char_to_entry.get(&c) {
+            Some(&index) => Some(index),
+            None => {
+                if !missing.contains(&c) {
+                    missing.push(c);
+                }
+                fallback
+            },
+        })
+        .collect();
+
+    (entry_indices, missing)
+}
+
+/// Places entries on a line, spaced by their advances and kerning.
+pub fn layout_text(context: &LayoutContext, metrics: &GlyphMetrics, entry_indices: &[usize]) -> Vec<FlatlandItem> {
+    layout_line(context, entry_indices, &metrics.pen_advances(entry_indices))
+}
+
+/// Places the entries of each line of `text`, split on `\n`, like `layout_text`, with lines
+/// going down from the origin by `line_height` like `wrap`, and the missing characters like `text_entries`.
+///
+/// Every `\n` starts a new line at x = 0, so consecutive, leading and trailing newlines
+/// leave empty lines. Kerning doesn't apply across lines.
+pub fn layout_text_lines<S: BuildHasher>(
+    context: &LayoutContext,
+    metrics: &GlyphMetrics,
+    char_to_entry: &HashMap<char, usize, S>,
+    text: &str,
+    fallback: Option<usize>,
+    line_height: f32,
+) -> (Vec<FlatlandItem>, Vec<char>) {
+    let mut items = Vec::with_capacity(text.len());
+    let mut missing: Vec<char> = Vec::new();
+
+    for (line_index, line) in text.split('\n').enumerate() {
+        let (entry_indices, line_missing) = text_entries(char_to_entry, line, fallback);
+        for c in line_missing {
+            if !missing.contains(&c) {
+                missing.push(c);
+            }
+        }
+
+        let y_offset = context.offset(-(line_index as f32) * line_height);
+        items.extend(layout_text(context, metrics, &entry_indices)
+            .into_iter()
+            .map(|item| FlatlandItem { y_offset, ..item }));
+    }
+
+    (items, missing)
+}
+
+/// A run of entries that is never broken across lines.
+#[derive(Clone, Debug)]
+pub struct Word {
+    pub entry_indices: Vec<usize>,
+    /// Pen advance of each entry.
+    pub advances: Vec<f32>,
+    /// Width of the word without the whitespace after it.
+    pub width: f32,
+    /// Whitespace after the word, dropped when the word ends a line.
+    pub space_after: f32,
+}
+
+impl Word {
+    pub fn new(metrics: &GlyphMetrics, entry_indices: Vec<usize>, space_after: f32) -> Word {
+        let advances = metrics.pen_advances(&entry_indices);
+        let width = advances.iter().sum();
+
+        Word {
+            entry_indices,
+            advances,
+            width,
+            space_after,
+        }
+    }
+}
+
+/// Horizontal placement of wrapped lines within the maximum width.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+    /// Stretches the space between words so lines fill the width.
+    /// The last line, and lines of a single word, are left aligned.
+    Justified,
+}
+
+/// Lays out words on left aligned lines no wider than `max_width`, breaking between words.
+///
+/// Lines go down from the origin by `line_height`. A word wider than `max_width`
+/// is put on a line of its own and overflows it.
+pub fn wrap(context: &LayoutContext, words: &[Word], max_width: f32, line_height: f32) -> Vec<FlatlandItem> {
+    wrap_aligned(context, words, max_width, line_height, Alignment::Left)
+}
+
+/// Like `wrap`, placing each line according to `alignment`.
+///
+/// Overflowing lines start at the origin whatever the alignment.
+pub fn wrap_aligned(context: &LayoutContext, words: &[Word], max_width: f32, line_height: f32, alignment: Alignment) -> Vec<FlatlandItem> {
+    let mut items = Vec::with_capacity(words.iter().map(|w| w.entry_indices.len()).sum());
+    let lines = break_lines(words, max_width);
+    let mut y = 0.0;
+
+    for (line_index, line) in lines.iter().enumerate() {
+        let line = &words[line.clone()];
+        let spaces: f32 = line[..line.len() - 1].iter().map(|w| w.space_after).sum();
+        let width = line.iter().map(|w| w.width).sum::<f32>() + spaces;
+        let slack = (max_width - width).max(0.0);
+        let is_last = line_index + 1 == lines.len();
+
+        let (mut x, stretch) = match alignment {
+            Alignment::Left => (0.0, 0.0),
+            Alignment::Right => (slack, 0.0),
+            Alignment::Center => (slack * 0.5, 0.0),
+            Alignment::Justified if is_last || line.len() < 2 => (0.0, 0.0),
+            Alignment::Justified => (0.0, slack / (line.len() - 1) as f32),
+        };
+
+        for word in line {
+            let mut pen = x;
+            for (&alphabet_entry_index, &advance) in word.entry_indices.iter().zip(word.advances.iter()) {
+                items.push(FlatlandItem {
+                    alphabet_entry_index,
+                    x_offset: context.offset(pen),
+                    y_offset: context.offset(y),
+                    rotation: 0.0,
+                });
+                pen += advance;
+            }
+
+            x += word.width + word.space_after + stretch;
+        }
+
+        y -= line_height;
+    }
+
+    items
+}
+
+/// Ranges of the words on each line.
+fn break_lines(words: &[Word], max_width: f32) -> Vec<::std::ops::Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut x = 0.0;
+
+    for (i, word) in words.iter().enumerate() {
+        if i > start && x + word.width > max_width {
+            lines.push(start..i);
+            start = i;
+            x = 0.0;
+        }
+        x += word.width + word.space_after;
+    }
+    if start < words.len() {
+        lines.push(start..words.len());
+    }
+
+    lines
+}
+
+/// Places glyphs one after another on a straight line, starting at the origin.
+pub fn layout_line(context: &LayoutContext, entry_indices: &[usize], advances: &[f32]) -> Vec<FlatlandItem> {
+    let mut pen = 0.0;
+
+    entry_indices.iter()
+        .zip(advances.iter())
+        .map(|(&alphabet_entry_index, &advance)| {
+            let x_offset = pen;
+            pen += advance;
+
+            FlatlandItem {
+                alphabet_entry_index,
+                x_offset: context.offset(x_offset),
+                y_offset: 0,
+                rotation: 0.0,
+            }
+        })
+        .collect()
+}
+
+/// Like `layout_line`, but when the line is wider than `max_width`, drops trailing glyphs until
+/// the rest and the ellipsis entry fit, and ends the line with the ellipsis.
+///
+/// Lines that fit are left as they are. Nothing is placed if even the ellipsis alone doesn't fit.
+pub fn truncate_with_ellipsis(
+    context: &LayoutContext,
+    entry_indices: &[usize],
+    advances: &[f32],
+    max_width: f32,
+    ellipsis_entry: usize,
+    ellipsis_advance: f32,
+) -> Vec<FlatlandItem> {
+    let width: f32 = advances.iter().take(entry_indices.len()).sum();
+    if width <= max_width {
+        return layout_line(context, entry_indices, advances);
+    }
+    if ellipsis_advance > max_width {
+        return Vec::new();
+    }
+
+    let mut kept = 0;
+    let mut kept_width = 0.0;
+    for &advance in advances.iter().take(entry_indices.len()) {
+        if kept_width + advance + ellipsis_advance > max_width {
+            break;
+        }
+        kept_width += advance;
+        kept += 1;
+    }
+
+    let mut items = layout_line(context, &entry_indices[..kept], &advances[..kept]);
+    items.push(FlatlandItem {
+        alphabet_entry_index: ellipsis_entry,
+        x_offset: context.offset(kept_width),
+        y_offset: 0,
+        rotation: 0.0,
+    });
+    items
+}
+
+/// Places shaped glyphs at their pen positions plus offsets, starting at the origin.
+///
+/// `entry_index` maps glyph ids to entries; unmapped glyphs are skipped but still advance the pen.
+pub fn layout_shaped(context: &LayoutContext, glyphs: &[ShapedGlyph], entry_index: impl Fn(u32) -> Option<usize>) -> Vec<FlatlandItem> {
+    let mut items = Vec::with_capacity(glyphs.len());
+    let mut pen = na::Vector2::new(0.0, 0.0);
+
+    for glyph in glyphs {
+        if let Some(alphabet_entry_index) = entry_index(glyph.glyph_id) {
+            items.push(FlatlandItem {
+                alphabet_entry_index,
+                x_offset: context.offset(pen.x + glyph.x_offset),
+                y_offset: context.offset(pen.y + glyph.y_offset),
+                rotation: 0.0,
+            });
+        }
+        pen += na::Vector2::new(glyph.x_advance, glyph.y_advance);
+    }
+
+    items
+}
+
+/// Places glyphs on a fixed grid of `cols` columns, ignoring their advances, e.g. for terminals.
+///
+/// Rows go down from the origin by `cell_height` and wrap after `cols` glyphs.
+/// The `newline` entry, if given, isn't drawn and starts a new row.
+pub fn monospace(
+    context: &LayoutContext,
+    entry_indices: &[usize],
+    newline: Option<usize>,
+    cell_width: f32,
+    cell_height: f32,
+    cols: usize,
+) -> Vec<FlatlandItem> {
+    let mut items = Vec::with_capacity(entry_indices.len());
+    let mut col = 0;
+    let mut row = 0;
+
+    for &alphabet_entry_index in entry_indices {
+        if Some(alphabet_entry_index) == newline {
+            col = 0;
+            row += 1;
+            continue;
+        }
+        if col == cols {
+            col = 0;
+            row += 1;
+        }
+
+        items.push(FlatlandItem {
+            alphabet_entry_index,
+            x_offset: context.offset(col as f32 * cell_width),
+            y_offset: context.offset(-(row as f32) * cell_height),
+            rotation: 0.0,
+        });
+        col += 1;
+    }
+
+    items
+}
+
+/// Places runs of entries around a circle of `radius`, e.g. for the labels of a dial.
+///
+/// Each run is an angle in radians, counterclockwise from the x axis, and its entries.
+/// It gets a transform for its own group, centering the run on that point of the circle and
+/// rotating it tangent to it, so runs read clockwise with their baselines on the circle.
+pub fn radial(
+    context: &LayoutContext,
+    metrics: &GlyphMetrics,
+    entry_runs: &[(f32, Vec<usize>)],
+    radius: f32,
+) -> Vec<(na::Projective3<f32>, Vec<FlatlandItem>)> {
+    entry_runs.iter()
+        .map(|&(angle, ref entry_indices)| {
+            let half_width = context.offset(measure_line(metrics, entry_indices) * 0.5);
+            let mut items = layout_text(context, metrics, entry_indices);
+            for item in &mut items {
+                item.x_offset -= half_width;
+            }
+
+            let position = na::Vector3::new(angle.cos() * radius, angle.sin() * radius, 0.0);
+            let tangent = angle - ::std::f32::consts::FRAC_PI_2;
+            let transform = na::Projective3::from_matrix_unchecked(
+                na::Matrix4::new_translation(&position) * na::Matrix4::new_rotation(na::Vector3::z() * tangent)
+            );
+
+            (transform, items)
+        })
+        .collect()
+}
+
+/// Places glyphs one after another along a cubic bezier curve.
+///
+/// Each glyph origin sits on the curve at its pen distance, rotated to follow the curve tangent
+/// at the glyph's middle. Glyphs that run past the end continue along the end tangent.
+pub fn layout_on_bezier(
+    context: &LayoutContext,
+    entry_indices: &[usize],
+    advances: &[f32],
+    p0: na::Vector2<f32>,
+    p1: na::Vector2<f32>,
+    p2: na::Vector2<f32>,
+    p3: na::Vector2<f32>,
+) -> Vec<FlatlandItem> {
+    let curve = CubicBezier { p0, p1, p2, p3 };
+    let lengths = curve.arc_lengths();
+    let total = lengths[lengths.len() - 1];
+
+    let end_tangent = curve.tangent(1.0);
+    let end_direction = if end_tangent.norm() > 0.0 { end_tangent.normalize() } else { na::Vector2::new(1.0, 0.0) };
+
+    let mut pen = 0.0;
+
+    entry_indices.iter()
+        .zip(advances.iter())
+        .map(|(&alphabet_entry_index, &advance)| {
+            let position = if pen <= total {
+                curve.point(t_at_length(&lengths, pen))
+            } else {
+                p3 + end_direction * (pen - total)
+            };
+
+            let middle = pen + advance * 0.5;
+            let tangent = if middle <= total {
+                curve.tangent(t_at_length(&lengths, middle))
+            } else {
+                end_direction
+            };
+
+            pen += advance;
+
+            FlatlandItem {
+                alphabet_entry_index,
+                x_offset: context.offset(position.x),
+                y_offset: context.offset(position.y),
+                rotation: tangent.y.atan2(tangent.x),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_places_glyphs_by_accumulated_advance() {
+        let items = layout_line(&LayoutContext::default(), &[3, 1, 2], &[10.5, 10.25, 10.0]);
+
+        let offsets: Vec<_> = items.iter().map(|i| (i.alphabet_entry_index, i.x_offset)).collect();
+        assert_eq!(vec![(3, 0), (1, 672), (2, 1328)], offsets);
+        assert_eq!(20.75, items[2].x());
+        assert_eq!(21.0, items[2].snapped().x());
+    }
+
+    #[test]
+    fn text_is_spaced_by_advances_and_kerning() {
+        let mut metrics = GlyphMetrics::default();
+        metrics.advances = vec![10.0, 20.0];
+        metrics.kerning.insert((0, 1), -2.0);
+
+        let items = layout_text(&LayoutContext::default(), &metrics, &[0, 1, 0]);
+
+        let offsets: Vec<_> = items.iter().map(|i| i.x()).collect();
+        assert_eq!(vec![0.0, 8.0, 28.0], offsets);
+        assert_eq!(38.0, measure_line(&metrics, &[0, 1, 0]));
+    }
+
+    fn word(entries: usize, space_after: f32) -> Word {
+        Word {
+            entry_indices: vec![0; entries],
+            advances: vec![10.0; entries],
+            width: 10.0 * entries as f32,
+            space_after,
+        }
+    }
+
+    fn item_positions(items: &[FlatlandItem]) -> Vec<(f32, f32)> {
+        items.iter().map(|i| (i.x(), i.y())).collect()
+    }
+
+    #[test]
+    fn measured_text_is_as_wide_as_its_widest_line() {
+        let mut metrics = GlyphMetrics { advances: vec![10.0, 6.0], ..GlyphMetrics::default() };
+        metrics.kerning.insert((0, 1), -2.0);
+        let char_to_entry: HashMap<char, usize> = vec![('a', 0), ('b', 1)].into_iter().collect();
+
+        assert_eq!((14.0, 12.0), measure_text(&metrics, &char_to_entry, "ab", 12.0));
+        assert_eq!((20.0, 36.0), measure_text(&metrics, &char_to_entry, "b?\naa\n", 12.0));
+        assert_eq!((0.0, 12.0), measure_text(&metrics, &char_to_entry, "", 12.0));
+    }
+
+    #[test]
+    fn newlines_start_lines_at_the_origin() {
+        let mut metrics = GlyphMetrics { advances: vec![10.0, 6.0], ..GlyphMetrics::default() };
+        metrics.kerning.insert((1, 0), -2.0);
+        let char_to_entry: HashMap<char, usize> = vec![('a', 0), ('b', 1)].into_iter().collect();
+        let lines = |text| {
+            let (items, missing) = layout_text_lines(&LayoutContext::default(), &metrics, &char_to_entry, text, None, 12.0);
+            (item_positions(&items), missing)
+        };
+
+        assert_eq!((vec![(0.0, 0.0), (10.0, 0.0), (0.0, -12.0), (4.0, -12.0)], vec![]), lines("ab\nba"));
+        assert_eq!((vec![(0.0, 0.0), (0.0, -36.0)], vec![]), lines("b\n\n\na"));
+        assert_eq!((vec![(0.0, -12.0), (0.0, -24.0)], vec!['?']), lines("\na?\nb\n"));
+        assert_eq!((vec![], vec![]), lines("\n\n"));
+    }
+
+    #[test]
+    fn overflowing_lines_end_with_an_ellipsis() {
+        let context = LayoutContext::default();
+        let ellipsis = 9;
+
+        let fitting = truncate_with_ellipsis(&context, &[1, 2], &[10.0, 10.0], 20.0, ellipsis, 6.0);
+        assert_eq!(vec![(0.0, 0.0), (10.0, 0.0)], item_positions(&fitting));
+
+        let truncated = truncate_with_ellipsis(&context, &[1, 2, 3], &[10.0, 10.0, 10.0], 25.0, ellipsis, 6.0);
+        let entries: Vec<_> = truncated.iter().map(|i| i.alphabet_entry_index).collect();
+        assert_eq!(vec![1, ellipsis], entries);
+        assert_eq!(vec![(0.0, 0.0), (10.0, 0.0)], item_positions(&truncated));
+
+        assert!(truncate_with_ellipsis(&context, &[1, 2], &[10.0, 10.0], 5.0, ellipsis, 6.0).is_empty());
+    }
+
+    #[test]
+    fn wrap_breaks_lines_between_words() {
+        let items = wrap(&LayoutContext::default(), &[word(2, 5.0), word(2, 5.0), word(1, 5.0)], 50.0, 12.0);
+
+        assert_eq!(vec![(0.0, 0.0), (10.0, 0.0), (25.0, 0.0), (35.0, 0.0), (0.0, -12.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn wrap_ignores_trailing_whitespace() {
+        let items = wrap(&LayoutContext::default(), &[word(2, 5.0), word(2, 50.0)], 45.0, 12.0);
+
+        assert_eq!(vec![(0.0, 0.0), (10.0, 0.0), (25.0, 0.0), (35.0, 0.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn wrap_puts_overlong_words_on_their_own_line() {
+        let items = wrap(&LayoutContext::default(), &[word(3, 5.0), word(1, 5.0)], 20.0, 12.0);
+
+        assert_eq!(vec![(0.0, 0.0), (10.0, 0.0), (20.0, 0.0), (0.0, -12.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn right_and_center_alignment_use_the_line_width_without_trailing_space() {
+        let words = [word(2, 5.0), word(1, 5.0)];
+
+        let items = wrap_aligned(&LayoutContext::default(), &words, 50.0, 12.0, Alignment::Right);
+        assert_eq!(vec![(15.0, 0.0), (25.0, 0.0), (40.0, 0.0)], item_positions(&items));
+
+        let items = wrap_aligned(&LayoutContext::default(), &words, 50.0, 12.0, Alignment::Center);
+        assert_eq!(vec![(7.5, 0.0), (17.5, 0.0), (32.5, 0.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn justified_lines_fill_the_width_except_the_last() {
+        let words = [word(1, 5.0), word(1, 5.0), word(1, 5.0), word(1, 5.0)];
+        let items = wrap_aligned(&LayoutContext::default(), &words, 45.0, 12.0, Alignment::Justified);
+
+        assert_eq!(vec![(0.0, 0.0), (17.5, 0.0), (35.0, 0.0), (0.0, -12.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn justified_single_word_lines_are_not_stretched() {
+        let items = wrap_aligned(&LayoutContext::default(), &[word(2, 5.0), word(2, 5.0)], 30.0, 12.0, Alignment::Justified);
+
+        assert_eq!(vec![(0.0, 0.0), (10.0, 0.0), (0.0, -12.0), (10.0, -12.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn line_converts_pixels_to_units() {
+        let context = LayoutContext { pixels_per_unit: 2.0 };
+        let items = layout_line(&context, &[0, 0, 0], &[10.0, 10.0, 10.0]);
+
+        let offsets: Vec<_> = items.iter().map(|i| i.x()).collect();
+        assert_eq!(vec![0.0, 5.0, 10.0], offsets);
+    }
+
+    #[test]
+    fn shaped_glyphs_follow_the_pen_and_skip_missing_entries() {
+        let glyph = |glyph_id, x_advance, x_offset, y_offset| ShapedGlyph { glyph_id, x_advance, y_advance: 0.0, x_offset, y_offset };
+        let glyphs = [glyph(7, 10.0, 0.0, 0.0), glyph(99, 6.0, 0.0, 0.0), glyph(8, 0.0, -4.0, 3.0), glyph(7, 10.0, 0.0, 0.0)];
+
+        let items = layout_shaped(&LayoutContext::default(), &glyphs, |id| if id < 10 { Some(id as usize) } else { None });
+
+        let entries: Vec<_> = items.iter().map(|i| i.alphabet_entry_index).collect();
+        assert_eq!(vec![7, 8, 7], entries);
+        assert_eq!(vec![(0.0, 0.0), (12.0, 3.0), (16.0, 0.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn monospace_wraps_at_column_count() {
+        let items = monospace(&LayoutContext::default(), &[1, 2, 3, 4, 5], None, 8.0, 16.0, 2);
+
+        assert_eq!(vec![(0.0, 0.0), (8.0, 0.0), (0.0, -16.0), (8.0, -16.0), (0.0, -32.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn monospace_newline_starts_a_row_without_a_glyph() {
+        let newline = 9;
+        let items = monospace(&LayoutContext::default(), &[1, newline, 2, 3, 4, newline, newline, 5], Some(newline), 8.0, 16.0, 2);
+
+        let entries: Vec<_> = items.iter().map(|i| i.alphabet_entry_index).collect();
+        assert_eq!(vec![1, 2, 3, 4, 5], entries);
+        assert_eq!(vec![(0.0, 0.0), (0.0, -16.0), (8.0, -16.0), (0.0, -32.0), (0.0, -64.0)], item_positions(&items));
+    }
+
+    #[test]
+    fn radial_runs_are_centered_on_the_circle_and_tangent_to_it() {
+        let metrics = GlyphMetrics { advances: vec![10.0, 10.0], ..GlyphMetrics::default() };
+        let runs = radial(&LayoutContext::default(), &metrics, &[
+            (::std::f32::consts::FRAC_PI_2, vec![0, 1]),
+            (0.0, vec![1]),
+        ], 100.0);
+
+        assert_eq!(2, runs.len());
+        assert_eq!(vec![(-10.0, 0.0), (0.0, 0.0)], item_positions(&runs[0].1));
+
+        let top = runs[0].0.matrix();
+        assert!(top[(0, 3)].abs() < 0.001 && (top[(1, 3)] - 100.0).abs() < 0.001);
+        assert!((top[(0, 0)] - 1.0).abs() < 0.001, "runs at the top read upright");
+
+        let right = runs[1].0.matrix();
+        assert!((right[(0, 3)] - 100.0).abs() < 0.001 && right[(1, 3)].abs() < 0.001);
+        assert!((right[(1, 0)] + 1.0).abs() < 0.001, "runs on the right read downwards");
+    }
+
+    #[test]
+    fn first_glyph_on_bezier_sits_at_start() {
+        let items = layout_on_bezier(
+            &LayoutContext::default(),
+            &[0, 1, 2],
+            &[10.0, 10.0, 10.0],
+            na::Vector2::new(5.0, 7.0),
+            na::Vector2::new(40.0, 80.0),
+            na::Vector2::new(80.0, -40.0),
+            na::Vector2::new(120.0, 7.0),
+        );
+
+        assert_eq!(3, items.len());
+        assert_eq!((5.0, 7.0), (items[0].x(), items[0].y()));
+    }
+
+    #[test]
+    fn straight_bezier_places_glyphs_by_advance() {
+        let items = layout_on_bezier(
+            &LayoutContext::default(),
+            &[0, 1, 2],
+            &[10.0, 20.0, 10.0],
+            na::Vector2::new(0.0, 0.0),
+            na::Vector2::new(100.0, 0.0),
+            na::Vector2::new(200.0, 0.0),
+            na::Vector2::new(300.0, 0.0),
+        );
+
+        assert_eq!(vec![(0.0, 0.0), (10.0, 0.0), (30.0, 0.0)], item_positions(&items));
+        assert!(items.iter().all(|i| i.rotation.abs() < 0.001));
+    }
+
+    #[test]
+    fn missing_characters_are_replaced_by_the_fallback_and_reported() {
+        let char_to_entry: HashMap<char, usize> = [('a', 1), ('b', 2)].iter().cloned().collect();
+
+        assert_eq!((vec![1, 2], vec!['x', 'y']), text_entries(&char_to_entry, "axbyx", None));
+        assert_eq!((vec![1, 9, 2, 9, 9], vec!['x', 'y']), text_entries(&char_to_entry, "axbyx", Some(9)));
+        assert_eq!((vec![2, 1], vec![]), text_entries(&char_to_entry, "ba", Some(9)));
+    }
+}
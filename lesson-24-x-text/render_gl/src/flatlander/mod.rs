@@ -4,38 +4,657 @@ use failure;
 use resources::Resources;
 use crate::ColorBuffer;
 use crate::Program;
+use crate::Shader;
+use crate::Texture;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
 
 mod buffers;
 mod flatland;
+mod font_stack;
+pub mod glyph;
+pub mod layout;
 
-pub use self::buffers::{FlatlanderVertex, FlatlanderGroupDrawData, DrawIndirectCmd};
+pub use self::buffers::{FlatlanderVertex, FlatlanderGroupDrawData, DrawIndirectCmd, DrawBatch, SyncError, BufferMismatch};
+#[cfg(feature = "upload_report")]
+pub use self::buffers::{UploadReport, UploadStats};
+pub use self::flatland::AlphabetSlot;
+pub use self::font_stack::FontStack;
+pub use self::flatland::GroupSlot as GroupId;
+
+/// How a group's fragments are combined with the target.
+///
+/// A multi-draw can't switch blend state, so a batch ends wherever the mode changes
+/// from one draw to the next; groups are still drawn in their order.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum BlendMode {
+    /// Default mode; leaves the target's blend enable state as it is.
+    Alpha,
+    /// For colors already multiplied by their alpha, see `color::premultiply`.
+    PremultipliedAlpha,
+    Additive,
+    Multiply,
+}
+
+/// Whether a group's triangles are filled or drawn as lines, like `Flatlander::toggle_wireframe`
+/// does for everything. A change of style between draws ends a batch too.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum RenderStyle {
+    Fill,
+    Outline,
+}
+
+/// Lines drawn across a group's text.
+///
+/// The lines are a shared unit quad scaled by the group's draws, so changing them adds
+/// nothing to the group's alphabet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Decoration {
+    None,
+    Underline { thickness: f32 },
+    Strikethrough { thickness: f32 },
+    UnderlineAndStrikethrough { thickness: f32 },
+}
+
+/// Vertical positions of decoration lines relative to the text baseline, in glyph units.
+///
+/// Lines are centered on these positions. Both default to the baseline itself,
+/// so set them from the font metrics.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DecorationBaseline {
+    pub underline: f32,
+    pub strikethrough: f32,
+}
+
+/// When alphabet buffers are repacked to reclaim the space of cleared or deleted alphabets.
+///
+/// Repacking re-uploads all geometry, and only works with `Flatlander::set_retain_cpu_data`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DefragPolicy {
+    Never,
+    /// Only on `Flatlander::defrag`.
+    Manual,
+    /// On the next upload once the wasted fraction of the buffers exceeds `threshold`.
+    Auto { threshold: f32 },
+}
+
+impl Default for DefragPolicy {
+    fn default() -> DefragPolicy {
+        DefragPolicy::Auto { threshold: 0.0 }
+    }
+}
+
+/// How group instance data and draw commands are written when they change, see
+/// `Flatlander::set_buffer_update_mode`. Which one is fastest depends on the driver.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BufferUpdateMode {
+    /// `glBufferSubData` into the existing storage, which can wait for draws still reading it.
+    SubData,
+    /// `glBufferData` with no data first, so the driver can give out fresh storage
+    /// instead of waiting, then `glBufferSubData`.
+    Orphan,
+    /// Maps the written range with `GL_MAP_INVALIDATE_RANGE_BIT`, the default.
+    ///
+    /// Persistent mappings need GL 4.4 buffer storage, which the flatlander doesn't require, so they aren't used.
+    MapRange,
+}
+
+impl Default for BufferUpdateMode {
+    fn default() -> BufferUpdateMode {
+        BufferUpdateMode::MapRange
+    }
+}
+
+/// How far the gpu buffers grow past what's needed when they are reallocated,
+/// see `Flatlander::set_growth_policy`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GrowthPolicy {
+    /// To exactly the size needed, reallocating on every growth.
+    Exact,
+    /// Doubles until a buffer holds `cap_bytes`, then grows by `cap_bytes` at a time,
+    /// so text built up over many frames reallocates rarely without over-allocating much.
+    Geometric { cap_bytes: usize },
+}
+
+impl Default for GrowthPolicy {
+    fn default() -> GrowthPolicy {
+        GrowthPolicy::Geometric { cap_bytes: 4 * 1024 * 1024 }
+    }
+}
+
+/// How the fragment shader of the flatland program reads group textures,
+/// see `Flatlander::set_texture_shading`.
+///
+/// Only the fragment shader differs; geometry is drawn the same way in both.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TextureShading {
+    /// Textured groups are multiplied by their texture.
+    Multiplied,
+    /// Textured groups are drawn as signed distance fields: the texture alpha is the distance,
+    /// 0.5 on the glyph edge, sampled at the vertex normals, so their entries are quads with
+    /// their coordinates in the atlas as normals. Untextured groups are drawn like `Multiplied`.
+    DistanceField,
+}
+
+impl Default for TextureShading {
+    fn default() -> TextureShading {
+        TextureShading::Multiplied
+    }
+}
+
+impl TextureShading {
+    fn load_program(self, gl: &gl::Gl, res: &Resources) -> Result<Program, failure::Error> {
+        match self {
+            TextureShading::Multiplied => Ok(Program::from_res(gl, res, "shaders/render_gl/flatland")?),
+            TextureShading::DistanceField => {
+                let shaders = [
+                    Shader::from_res(gl, res, "shaders/render_gl/flatland.vert")?,
+                    Shader::from_res(gl, res, "shaders/render_gl/flatland_distance_field.frag")?,
+                ];
+                Program::from_shaders(gl, &shaders).map_err(|e| format_err!("failed to link flatland distance field program: {}", e))
+            },
+        }
+    }
+}
+
+/// Order of a triangle's corners in alphabet units, with y going up.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// How often a group is expected to change, see `FlatlandGroup::new_with_usage`.
+///
+/// Static and dynamic groups keep their instance data in separate buffers, so changing
+/// a dynamic group doesn't upload the static ones again.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Usage {
+    /// Rarely changes, e.g. labels of a menu. Stored with `GL_STATIC_DRAW`.
+    Static,
+    /// Changes most frames, the default. Stored with `GL_DYNAMIC_DRAW`.
+    Dynamic,
+}
+
+/// Points of a group's contents to rotate and scale about, see `FlatlandGroup::set_pivot_at`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Pivot {
+    /// The origin of the items, the default.
+    Origin,
+    /// The center of the group's bounds, or the origin if nothing has geometry.
+    Center,
+    /// The start of the first item on its baseline.
+    BaselineStart,
+}
+
+/// Point of the screen a screen-space group is placed from, see `FlatlandGroup::set_screen_anchor`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Position in pixels on a screen of `size`, with y going down like `ortho_screen`.
+    pub fn position(&self, size: na::Vector2<f32>) -> na::Vector2<f32> {
+        let (x, y) = match *self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        };
+        na::Vector2::new(size.x * x, size.y * y)
+    }
+}
+
+/// A common label behavior, played by `Flatlander::advance_animations`.
+///
+/// Animations change how the group is drawn, not its color or transform, which stay
+/// the end state. Times are in seconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GroupAnimation {
+    /// Alpha goes from 0 to the group's.
+    FadeIn { secs: f32 },
+    /// Alpha goes from the group's to 0, and stays there.
+    FadeOut { secs: f32 },
+    /// Alpha goes from the group's to 0 and back once per period, until replaced.
+    Pulse { period: f32 },
+    /// Moves linearly from `offset` away, in the transform's space, to the group's transform.
+    SlideFrom { offset: na::Vector2<f32>, secs: f32 },
+}
+
+impl GroupAnimation {
+    /// Length of the animation, `None` if it never ends.
+    pub fn duration(&self) -> Option<f32> {
+        match *self {
+            GroupAnimation::FadeIn { secs } | GroupAnimation::FadeOut { secs } | GroupAnimation::SlideFrom { secs, .. } => Some(secs),
+            GroupAnimation::Pulse { .. } => None,
+        }
+    }
+
+    /// Factor of the group's alpha after `elapsed` seconds.
+    pub fn alpha(&self, elapsed: f32) -> f32 {
+        match *self {
+            GroupAnimation::FadeIn { secs } => progress(elapsed, secs),
+            GroupAnimation::FadeOut { secs } => 1.0 - progress(elapsed, secs),
+            GroupAnimation::Pulse { period } if period > 0.0 => {
+                0.5 + 0.5 * (elapsed / period * 2.0 * ::std::f32::consts::PI).cos()
+            },
+            _ => 1.0,
+        }
+    }
+
+    /// Offset from the group's transform after `elapsed` seconds.
+    pub fn offset(&self, elapsed: f32) -> na::Vector2<f32> {
+        match *self {
+            GroupAnimation::SlideFrom { offset, secs } => offset * (1.0 - progress(elapsed, secs)),
+            _ => na::Vector2::zeros(),
+        }
+    }
+}
+
+/// An effect played on each glyph of a group by `Flatlander::advance_animations`,
+/// see `FlatlandGroup::set_glyph_effect`. Times are in seconds.
+///
+/// Backgrounds, selections and decorations are drawn without the effect.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GlyphEffect {
+    /// Reveals the glyphs one after the other.
+    Typewriter { chars_per_sec: f32 },
+    /// Moves each glyph up and down, `frequency` radians behind the glyph before it,
+    /// by `speed` radians per second.
+    Wave { amplitude: f32, frequency: f32, speed: f32 },
+}
+
+impl GlyphEffect {
+    /// Whether the glyph at `glyph_index` is drawn after `elapsed` seconds.
+    pub fn visible(&self, glyph_index: usize, elapsed: f32) -> bool {
+        match *self {
+            GlyphEffect::Typewriter { chars_per_sec } => (glyph_index as f32) < elapsed * chars_per_sec,
+            GlyphEffect::Wave { .. } => true,
+        }
+    }
+
+    /// Vertical offset of the glyph at `glyph_index` after `elapsed` seconds.
+    pub fn y_offset(&self, glyph_index: usize, elapsed: f32) -> f32 {
+        match *self {
+            GlyphEffect::Typewriter { .. } => 0.0,
+            GlyphEffect::Wave { amplitude, frequency, speed } => {
+                amplitude * (elapsed * speed - glyph_index as f32 * frequency).sin()
+            },
+        }
+    }
+
+    /// Whether the effect still changes after `elapsed` seconds on `glyph_count` glyphs.
+    pub fn is_playing(&self, glyph_count: usize, elapsed: f32) -> bool {
+        match *self {
+            GlyphEffect::Typewriter { chars_per_sec } => chars_per_sec > 0.0 && elapsed * chars_per_sec < glyph_count as f32,
+            GlyphEffect::Wave { .. } => true,
+        }
+    }
+}
+
+fn progress(elapsed: f32, secs: f32) -> f32 {
+    if secs > 0.0 {
+        (elapsed / secs).max(0.0).min(1.0)
+    } else {
+        1.0
+    }
+}
+
+/// How indirect draw commands are submitted, picked from the loaded GL functions.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DrawPath {
+    /// `glMultiDrawElementsIndirect`, OpenGL 4.3.
+    MultiDrawIndirect,
+    /// One `glDrawElementsIndirect` per command, OpenGL 4.1 fallback.
+    DrawIndirectLoop,
+}
+
+impl DrawPath {
+    fn detect(gl: &gl::Gl) -> DrawPath {
+        if gl.MultiDrawElementsIndirect.is_loaded() {
+            DrawPath::MultiDrawIndirect
+        } else {
+            DrawPath::DrawIndirectLoop
+        }
+    }
+}
+
+/// Where alphabet glyphs come from: a font tessellator, prebaked data or a generator.
+///
+/// Closures from a `char` and tolerance to the glyph are sources too.
+pub trait GlyphSource {
+    /// Geometry and advance of the glyph for `ch`, `None` if the source doesn't have it.
+    ///
+    /// `tolerance` is the maximum distance between a curve and its flattened segments,
+    /// in the units of the glyph outline. Sources with fixed geometry can ignore it.
+    fn glyph(&self, ch: char, tolerance: f32) -> Option<(Vec<FlatlanderVertex>, Vec<u16>, f32)>;
+}
+
+impl<F> GlyphSource for F where F: Fn(char, f32) -> Option<(Vec<FlatlanderVertex>, Vec<u16>, f32)> {
+    fn glyph(&self, ch: char, tolerance: f32) -> Option<(Vec<FlatlanderVertex>, Vec<u16>, f32)> {
+        self(ch, tolerance)
+    }
+}
+
+/// What `Flatlander::render_filtered` predicates know about a group.
+#[derive(Copy, Clone, Debug)]
+pub struct GroupInfo {
+    pub id: GroupId,
+    pub alphabet: AlphabetSlot,
+    pub transform: na::Projective3<f32>,
+    pub color: na::Vector4<u8>,
+    pub blend_mode: BlendMode,
+    pub user_data: u64,
+}
+
+/// How many groups the last draw command build kept and left out.
+///
+/// Groups are left out when their alphabet is hidden, by the predicate of `render_filtered`,
+/// the z band of `draw_range` or by not being in an enabled layer for `render_layers`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CullResult {
+    pub visible: usize,
+    pub culled: usize,
+}
+
+/// How much changed between two renders, see `Flatlander::churn_since_last_frame`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChurnStats {
+    /// Groups created or changed, not counting deleted ones.
+    pub groups_changed: usize,
+    /// Geometry, instance data and draw commands written to the gpu buffers.
+    pub bytes_reuploaded: usize,
+}
+
+/// Layer registered with `Flatlander::layer`, valid for the `Flatlander` that returned it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LayerHandle {
+    index: usize,
+}
+
+/// A glyph placed by a `Shaper`, with positions in pixels like other layout inputs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShapedGlyph {
+    /// Alphabet entry id of the glyph, e.g. the font's glyph index.
+    pub glyph_id: u32,
+    /// Pen movement after the glyph.
+    pub x_advance: f32,
+    pub y_advance: f32,
+    /// Displacement of the glyph from the pen position, not affecting the pen.
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+/// Turns text into positioned glyphs, for scripts that need substitution or reordering,
+/// e.g. by wrapping HarfBuzz. Draw the output with `Flatlander::draw_shaped`.
+pub trait Shaper {
+    fn shape(&self, text: &str) -> Vec<ShapedGlyph>;
+}
+
+/// Callbacks `render` runs around its draw calls, see `Flatlander::set_pre_draw_hook`.
+#[derive(Default)]
+struct DrawHooks {
+    pre: Option<Box<dyn Fn(&gl::Gl)>>,
+    post: Option<Box<dyn Fn(&gl::Gl)>>,
+}
+
+impl DrawHooks {
+    fn around<R>(&self, gl: &gl::Gl, draw: impl FnOnce() -> R) -> R {
+        if let Some(ref hook) = self.pre {
+            hook(gl);
+        }
+        let drawn = draw();
+        if let Some(ref hook) = self.post {
+            hook(gl);
+        }
+        drawn
+    }
+}
 
 pub struct Flatlander {
     program: Program,
+    /// The program came from `from_program`, not from the shaders of `texture_shading`.
+    program_adopted: bool,
+    texture_shading: TextureShading,
     program_view_projection_location: Option<i32>,
+    program_wireframe_color_location: Option<i32>,
+    program_use_wireframe_color_location: Option<i32>,
+    program_time_location: Option<i32>,
+    program_texture_location: Option<i32>,
+    program_use_texture_location: Option<i32>,
     flatland: Rc<RefCell<flatland::Flatland>>,
     buffers: Option<buffers::Buffers>,
+    draw_path: DrawPath,
+    layout_context: layout::LayoutContext,
+    batching: bool,
     draw_enabled: bool,
     wireframe: bool,
+    wireframe_color: Option<na::Vector4<u8>>,
+    alpha_to_coverage: bool,
+    draw_hooks: DrawHooks,
+    defrag_policy: DefragPolicy,
+    buffer_update_mode: BufferUpdateMode,
+    growth_policy: GrowthPolicy,
+    view_projection: na::Matrix4<f32>,
+    last_cull_result: CullResult,
+    churn: ChurnStats,
+    /// `Flatland::generation` and uploaded bytes at the end of the last `render`.
+    churn_start: (u64, usize),
+    /// Value of the program's view-projection uniform, `None` if not set yet.
+    uploaded_view_projection: Option<na::Matrix4<f32>>,
+    /// Screen-space projection for the size from `set_viewport_size`.
+    screen_projection: na::Matrix4<f32>,
+    /// Seconds passed to the program's `Time` uniform.
+    time: f32,
+    grid_enabled: bool,
+    /// Program of `render_picking` and its view-projection uniform.
+    picking_program: Option<(Program, i32)>,
+    /// Groups in the order of their picking colors at the last `render_picking`.
+    picking_ids: Vec<GroupId>,
+}
+
+/// More lines than this are left undrawn, the spacing is too small to be useful.
+const MAX_GRID_LINES: usize = 4096;
+
+/// Lines of `render_grid` as world space rectangles, one pixel thick in a `viewport` sized
+/// screen, covering the view of `vp`; `None` if it can't be inverted or has too many lines.
+fn grid_lines(vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>, spacing: f32) -> Option<Vec<(na::Vector2<f32>, na::Vector2<f32>)>> {
+    let inverse = vp.try_inverse()?;
+    let corners: Vec<na::Point3<f32>> = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter()
+        .map(|&(x, y)| inverse.transform_point(&na::Point3::new(x, y, 0.0)))
+        .collect();
+    let (min, max) = corners.iter().fold(
+        (na::Vector2::repeat(::std::f32::MAX), na::Vector2::repeat(::std::f32::MIN)),
+        |(min, max), p| (
+            na::Vector2::new(min.x.min(p.x), min.y.min(p.y)),
+            na::Vector2::new(max.x.max(p.x), max.y.max(p.y)),
+        )
+    );
+    let size = max - min;
+    let first = na::Vector2::new((min.x / spacing).ceil(), (min.y / spacing).ceil());
+    let columns = ((max.x / spacing).floor() - first.x) as isize + 1;
+    let rows = ((max.y / spacing).floor() - first.y) as isize + 1;
+    if columns < 0 || rows < 0 || (columns + rows) as usize > MAX_GRID_LINES {
+        return None;
+    }
+
+    let pixel = na::Vector2::new(size.x / viewport.x, size.y / viewport.y);
+    let horizontal = (0..rows).map(|row| (
+        na::Vector2::new(min.x, (first.y + row as f32) * spacing - pixel.y * 0.5),
+        na::Vector2::new(size.x, pixel.y),
+    ));
+    let vertical = (0..columns).map(|column| (
+        na::Vector2::new((first.x + column as f32) * spacing - pixel.x * 0.5, min.y),
+        na::Vector2::new(pixel.x, size.y),
+    ));
+    Some(horizontal.chain(vertical).collect())
 }
 
 impl Flatlander {
     pub fn new(gl: &gl::Gl, res: &Resources) -> Result<Flatlander, failure::Error> {
-        let program = Program::from_res(gl, res, "shaders/render_gl/flatland")?;
-        let program_view_projection_location = program.get_uniform_location("ViewProjection");
+        let program = TextureShading::default().load_program(gl, res)?;
+        let mut flatlander = Flatlander::from_program(gl, program)?;
+        flatlander.program_adopted = false;
+        Ok(flatlander)
+    }
+
+    /// Adopts an already compiled flatland program, e.g. from an application shader cache.
+    ///
+    /// Fails if the program has no `ViewProjection` uniform. The wireframe, `Time` and
+    /// texture uniforms are optional. After a context loss, pass the program compiled again
+    /// to `recreate_gl_resources_with_program`.
+    pub fn from_program(gl: &gl::Gl, program: Program) -> Result<Flatlander, failure::Error> {
+        let program_view_projection_location = Some(program.get_uniform_location("ViewProjection")
+            .ok_or_else(|| format_err!("flatland program has no ViewProjection uniform"))?);
+        let program_wireframe_color_location = program.get_uniform_location("WireframeColor");
+        let program_use_wireframe_color_location = program.get_uniform_location("UseWireframeColor");
+        let program_time_location = program.get_uniform_location("Time");
+        let program_texture_location = program.get_uniform_location("GroupTexture");
+        let program_use_texture_location = program.get_uniform_location("UseGroupTexture");
 
         Ok(Flatlander {
             program,
+            program_adopted: true,
+            texture_shading: TextureShading::default(),
             program_view_projection_location,
+            program_wireframe_color_location,
+            program_use_wireframe_color_location,
+            program_time_location,
+            program_texture_location,
+            program_use_texture_location,
             flatland: Rc::new(RefCell::new(flatland::Flatland::new())),
             buffers: None,
+            draw_path: DrawPath::detect(gl),
+            layout_context: layout::LayoutContext::default(),
+            batching: false,
             draw_enabled: true,
             wireframe: false,
+            wireframe_color: None,
+            alpha_to_coverage: false,
+            draw_hooks: DrawHooks::default(),
+            defrag_policy: DefragPolicy::default(),
+            buffer_update_mode: BufferUpdateMode::default(),
+            growth_policy: GrowthPolicy::default(),
+            view_projection: na::Matrix4::identity(),
+            last_cull_result: CullResult::default(),
+            churn: ChurnStats::default(),
+            churn_start: (0, 0),
+            uploaded_view_projection: None,
+            screen_projection: na::Matrix4::identity(),
+            time: 0.0,
+            grid_enabled: false,
+            picking_program: None,
+            picking_ids: Vec::new(),
         })
     }
 
+    pub fn active_draw_path(&self) -> DrawPath {
+        self.draw_path
+    }
+
+    /// Whether everything created so far is on the gpu, with no upload pending.
+    ///
+    /// Uploads happen in `render` or `end_batch`, so a loading screen can keep rendering
+    /// until this turns true before revealing the text.
+    pub fn is_uploaded(&self) -> bool {
+        let flatland = self.flatland.borrow();
+        self.buffers.is_some()
+            && !flatland.alphabets_invalidated
+            && !flatland.groups_invalidated
+            && !flatland.draw_invalidated
+    }
+
+    /// Reads the gpu buffers back and compares them with the cpu data, reporting the first
+    /// differing element of each buffer, e.g. to track down a partial or misplaced upload.
+    ///
+    /// Needs `set_retain_cpu_data(true)` and nothing waiting for upload, so call it after `render`.
+    /// Only in debug builds: the readback stalls the pipeline.
+    #[cfg(debug_assertions)]
+    pub fn verify_gpu_sync(&self) -> Result<(), SyncError> {
+        let buffers = match self.buffers {
+            Some(ref buffers) => buffers,
+            None => return Ok(()),
+        };
+        if !self.is_uploaded() {
+            return Err(SyncError::Pending);
+        }
+
+        let flatland = self.flatland.borrow();
+        let entries = flatland.retained_entries().ok_or(SyncError::NotRetained)?;
+        let mut mismatches = Vec::new();
+
+        let vertices = buffers.read_vertices();
+        let indices = buffers.read_indices();
+        let vertex_difference = entries.iter()
+            .filter_map(|e| buffers::first_difference(&vertices, e.first_vertex, &e.vertices))
+            .min();
+        let index_difference = entries.iter()
+            .filter_map(|e| {
+                buffers::first_difference(&indices, e.first_index, &e.indices)
+            })
+            .min();
+
+        let draw_ids: Vec<_> = flatland.groups_draw_data().map(|d| buffers::draw_id(&d)).collect();
+        let (commands, _) = flatland.draw_commands();
+        let gpu_draw_ids = buffers.read_draw_ids();
+        let gpu_commands = buffers.read_draw_commands();
+        let draw_id_difference = buffers::first_difference(&gpu_draw_ids, 0, &draw_ids)
+            .or_else(|| Some(draw_ids.len()).filter(|&len| len < gpu_draw_ids.len()));
+        let command_difference = buffers::first_difference(&gpu_commands, 0, &commands)
+            .or_else(|| Some(commands.len()).filter(|&len| len < gpu_commands.len()));
+
+        for &(buffer, difference) in &[
+            ("vertices", vertex_difference),
+            ("indices", index_difference),
+            ("draw_id", draw_id_difference),
+            ("indirect", command_difference),
+        ] {
+            if let Some(first_difference) = difference {
+                mismatches.push(BufferMismatch { buffer, first_difference });
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(SyncError::Mismatch(mismatches))
+        }
+    }
+
+    /// Name of the vertex array used for drawing dynamic groups, `None` until the first upload.
+    ///
+    /// The vertex array is owned by the flatlander; don't delete it.
+    pub fn vao_id(&self) -> Option<gl::types::GLuint> {
+        self.buffers.as_ref().map(|b| b.lines_vao.id())
+    }
+
+    /// Labels the program, vertex array and buffers for GPU debuggers such as RenderDoc.
+    ///
+    /// Buffers are created on the first upload, so call this after the first `render`
+    /// to get them labelled too.
+    pub fn set_debug_labels(&self, gl: &gl::Gl) {
+        buffers::object_label(gl, gl::PROGRAM, self.program.id(), "flatland_program");
+        if let Some(ref buffers) = self.buffers {
+            buffers.set_debug_labels(gl);
+        }
+    }
+
     pub fn toggle(&mut self) {
         self.draw_enabled = !self.draw_enabled;
     }
@@ -43,113 +662,1075 @@ impl Flatlander {
         self.wireframe = !self.wireframe;
     }
 
-    fn check_if_invalidated_and_reinitialize(&mut self, gl: &gl::Gl) {
-        let mut flatland = self.flatland.borrow_mut();
-
-        if flatland.alphabets_invalidated {
-            if self.buffers.is_none() {
-                self.buffers = Some(buffers::Buffers::new(gl));
-            }
+    /// Draws wireframe lines in a fixed color instead of the group colors.
+    pub fn set_wireframe_color(&mut self, color: Option<na::Vector4<u8>>) {
+        self.wireframe_color = color;
+    }
+
+    /// Turns fragment alpha into multisample coverage while drawing, for antialiased
+    /// translucent text that doesn't depend on draw order.
+    ///
+    /// Only has an effect on multisampled framebuffers. Blending still applies on top,
+    /// so groups are usually switched to a blend mode that ignores alpha, or left opaque.
+    pub fn set_alpha_to_coverage(&mut self, enabled: bool) {
+        self.alpha_to_coverage = enabled;
+    }
+
+    /// Elements and bytes the last render uploaded to each buffer, all zero if nothing changed,
+    /// to find which changes cause expensive uploads. Needs the `upload_report` feature.
+    ///
+    /// Changing any group re-uploads the instance data of all groups.
+    #[cfg(feature = "upload_report")]
+    pub fn last_upload_report(&self) -> UploadReport {
+        self.buffers.as_ref().map(|b| b.report).unwrap_or_default()
+    }
+
+    /// Point of the flatland plane, z = 0, under a pixel of the viewport, e.g. to put a group
+    /// under the mouse. `screen` counts from the top-left corner like window events do.
+    ///
+    /// `None` if `vp` can't be inverted or is looking along the plane.
+    pub fn screen_to_world(&self, screen: na::Vector2<f32>, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> Option<na::Vector2<f32>> {
+        flatland::screen_to_world(screen, vp, viewport)
+    }
+
+    /// Groups kept and left out by the most recent draw command build, of `render` or of
+    /// `render_filtered` and `draw_range`, e.g. to show in a HUD or check filters in tests.
+    pub fn last_cull_result(&self) -> CullResult {
+        self.last_cull_result
+    }
+
+    /// Groups changed and bytes uploaded from the end of the render before the last one to the
+    /// end of the last one, e.g. to watch in a HUD: high churn with little visible change means
+    /// groups are updated more than needed. Uploads of `render_filtered` count towards the next `render`.
+    pub fn churn_since_last_frame(&self) -> ChurnStats {
+        self.churn
+    }
+
+    fn update_churn(&mut self) {
+        let (generation, bytes) = self.churn_start;
+        let (changed, generation_now) = self.flatland.borrow().groups_changed_since(generation);
+        let bytes_now = self.buffers.as_ref().map(|b| b.uploaded_bytes).unwrap_or(0);
+
+        self.churn = ChurnStats {
+            groups_changed: changed.len(),
+            bytes_reuploaded: bytes_now - bytes,
+        };
+        self.churn_start = (generation_now, bytes_now);
+    }
+
+    /// Seconds pushed to the program's `Time` uniform on every render, for animated shader effects.
+    ///
+    /// Ignored by programs without the uniform, like the default one.
+    pub fn set_time(&mut self, t: f32) {
+        self.time = t;
+    }
+
+    /// Resizes the screen for anchored groups, moving each to its anchor on the new size, e.g. when
+    /// the window resizes. Also sets `screen_projection` to `ortho_screen` of the size.
+    pub fn set_viewport_size(&mut self, w: f32, h: f32) {
+        self.screen_projection = crate::ortho_screen(w, h);
+        self.flatland.borrow_mut().set_viewport_size(na::Vector2::new(w, h));
+    }
+
+    /// View-projection to render screen-space groups with, identity until `set_viewport_size`.
+    pub fn screen_projection(&self) -> na::Matrix4<f32> {
+        self.screen_projection
+    }
+
+    /// Moves the groups with a world anchor to where the 3D camera `vp` shows their anchor on a
+    /// `viewport` sized screen, e.g. once per frame before rendering them with `screen_projection`.
+    /// Groups whose anchor is behind the camera are hidden until it comes back in front.
+    pub fn update_anchored_groups(&mut self, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) {
+        self.flatland.borrow_mut().update_anchored_groups(vp, viewport);
+    }
+
+    /// Turns `render_grid` on or off; it draws nothing by default.
+    pub fn set_grid_enabled(&mut self, enabled: bool) {
+        self.grid_enabled = enabled;
+    }
+
+    /// Draws lines every `spacing` world units across the view, one pixel thick, to check
+    /// the alignment of text. Call it before `render` to have the grid behind the groups.
+    ///
+    /// A developer tool drawn with the flatland program, meant for orthographic cameras.
+    /// The lines stretch the flatland's unit quad, so they add no groups or alphabet geometry;
+    /// the next render uploads the group data again.
+    /// Does nothing unless enabled with `set_grid_enabled`, or if the lines would be too dense,
+    /// and draws nothing between `begin_batch` and `end_batch`, like `render_filtered`.
+    pub fn render_grid(&mut self, gl: &gl::Gl, target: &ColorBuffer, vp: &na::Matrix4<f32>, spacing: f32, color: na::Vector4<u8>) {
+        if !self.grid_enabled || !(spacing > 0.0) {
+            return;
+        }
+        if !self.draw_enabled || self.batching {
+            return;
+        }
+
+        let mut viewport = [0; 4];
+        unsafe {
+            gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        let lines = match grid_lines(vp, na::Vector2::new(viewport[2] as f32, viewport[3] as f32), spacing) {
+            Some(lines) => lines,
+            None => return,
+        };
+
+        self.set_view_projection(*vp);
+        self.draw_world_quads(gl, target, &lines, color);
+    }
+
+    /// Draws the unit quad stretched over world space rectangles, in place of the dynamic
+    /// group data, which the next render uploads again.
+    fn draw_world_quads(&mut self, gl: &gl::Gl, target: &ColorBuffer, rects: &[(na::Vector2<f32>, na::Vector2<f32>)], color: na::Vector4<u8>) {
+        // uploads the unit quad along with any pending changes
+        let draws = self.flatland.borrow_mut().world_quads_draw_data(rects, color);
+        self.upload(gl);
+
+        {
+            let mut flatland = self.flatland.borrow_mut();
+            if let Some(ref mut buffers) = self.buffers {
+                buffers.upload_groups(Usage::Dynamic, draws.len(), draws.iter().cloned());
+                let commands: Vec<DrawIndirectCmd> = draws.iter().map(|d| d.cmd).collect();
+                buffers.upload_draw_commands(&commands, &[DrawBatch {
+                    usage: Usage::Dynamic,
+                    texture: 0,
+                    blend_mode: BlendMode::Alpha,
+                    style: RenderStyle::Fill,
+                    mirrored: false,
+                    first_command: 0,
+                    command_count: commands.len(),
+                }]);
+            }
+            // the next render uploads the dynamic group data and the full command set again
+            flatland.groups_invalidated = true;
+            flatland.draw_invalidated = true;
+        }
+
+        self.draw(gl, target);
+    }
+
+    /// Called in `render` right before the draw calls, with the flatland program and buffers bound.
+    ///
+    /// Use it to set up custom state such as a stencil test; undo it in the post-draw hook.
+    pub fn set_pre_draw_hook(&mut self, f: Box<dyn Fn(&gl::Gl)>) {
+        self.draw_hooks.pre = Some(f);
+    }
+
+    /// Called in `render` right after the draw calls.
+    pub fn set_post_draw_hook(&mut self, f: Box<dyn Fn(&gl::Gl)>) {
+        self.draw_hooks.post = Some(f);
+    }
+
+    /// Units used by `draw_text` to turn entry advances into item offsets.
+    pub fn set_layout_context(&mut self, context: layout::LayoutContext) {
+        self.layout_context = context;
+    }
+
+    pub fn layout_context(&self) -> layout::LayoutContext {
+        self.layout_context
+    }
+
+    /// Holds back buffer uploads until `end_batch`, for bulk edits.
+    ///
+    /// In between, `render` keeps drawing whatever was last uploaded.
+    pub fn begin_batch(&mut self) {
+        self.batching = true;
+    }
+
+    /// Uploads everything changed since `begin_batch` in one go.
+    pub fn end_batch(&mut self, gl: &gl::Gl) {
+        self.batching = false;
+        self.check_if_invalidated_and_reinitialize(gl);
+    }
+
+    /// Keeps alphabet geometry in RAM after it has been uploaded to the gpu.
+    ///
+    /// Off by default: uploaded vertices and indices are freed, so glyph geometry is only held
+    /// by the gpu buffers. Entries are written to those buffers once and never re-read, so this
+    /// costs nothing while alphabets only grow. The price is paid when alphabets are cleared or
+    /// deleted: without cpu data the freed space can't be compacted and is only reused by later
+    /// entries that fit in it, while with retained data the buffers are repacked, see `set_defrag_policy`.
+    ///
+    /// Turn it on to read geometry back with `Alphabet::entry_geometry`, for debugging or export.
+    /// It only affects entries uploaded after the call; released data can't be recovered.
+    /// Group items are always kept, since draw commands are rebuilt from them.
+    pub fn set_retain_cpu_data(&mut self, retain: bool) {
+        self.flatland.borrow_mut().set_retain_cpu_data(retain);
+    }
+
+    /// Rebuilds the program and buffers after the GL context was lost, and uploads everything again.
+    ///
+    /// The flatlander can't tell that the context is gone; callers must detect it themselves
+    /// (e.g. after a driver reset or resume from sleep) and call this with the new context.
+    /// Needs `set_retain_cpu_data(true)` from the start, since geometry is re-uploaded from RAM.
+    /// Fails without touching anything if some alphabet geometry was already released or the
+    /// program doesn't compile. A program adopted with `from_program` can't be reloaded from
+    /// `res`, so this fails for it too; use `recreate_gl_resources_with_program` instead.
+    pub fn recreate_gl_resources(&mut self, gl: &gl::Gl, res: &Resources) -> Result<(), failure::Error> {
+        if self.program_adopted {
+            return Err(format_err!("can't reload a flatland program adopted with from_program, pass it to recreate_gl_resources_with_program"));
+        }
+        self.check_gpu_data_retained()?;
+
+        let program = self.texture_shading.load_program(gl, res)?;
+        self.recreate_gl_resources_with(gl, program);
+        Ok(())
+    }
+
+    /// Like `recreate_gl_resources`, drawing with `program` compiled again by the caller for the
+    /// new context, e.g. the program given to `from_program`. Fails like `from_program` if the
+    /// program has no `ViewProjection` uniform.
+    pub fn recreate_gl_resources_with_program(&mut self, gl: &gl::Gl, program: Program) -> Result<(), failure::Error> {
+        if program.get_uniform_location("ViewProjection").is_none() {
+            return Err(format_err!("flatland program has no ViewProjection uniform"));
+        }
+        self.check_gpu_data_retained()?;
+
+        self.recreate_gl_resources_with(gl, program);
+        self.program_adopted = true;
+        Ok(())
+    }
+
+    fn check_gpu_data_retained(&self) -> Result<(), failure::Error> {
+        match self.flatland.borrow().retained_entries() {
+            Some(_) => Ok(()),
+            None => Err(format_err!("can't recreate flatlander buffers: alphabet geometry was not retained")),
+        }
+    }
+
+    /// Everything after the checks of `recreate_gl_resources`, which can't fail anymore.
+    fn recreate_gl_resources_with(&mut self, gl: &gl::Gl, program: Program) {
+        let invalidated = self.flatland.borrow_mut().invalidate_gpu_data();
+        debug_assert!(invalidated, "retained geometry was checked first");
+        self.draw_path = DrawPath::detect(gl);
+
+        // the old names belong to the lost context; deleting them could hit new objects
+        ::std::mem::forget(self.replace_program(program));
+        ::std::mem::forget(self.buffers.take());
+        // the new buffers count their uploads from scratch
+        self.churn_start.1 = 0;
+        ::std::mem::forget(self.picking_program.take());
+
+        self.check_if_invalidated_and_reinitialize(gl);
+    }
+
+    /// Switches the fragment shader between multiplying textured groups by their texture and
+    /// reading it as a distance field, e.g. to compare a font drawn from a distance field atlas
+    /// with the same font tessellated, by compiling the program of `shading`.
+    ///
+    /// Only the fragment shader changes: the vertex layout, buffers and draw path stay as they
+    /// are and nothing is uploaded again, so alphabets aren't converted between tessellated
+    /// glyphs and distance field quads; each group keeps the geometry it was created with.
+    /// Fails leaving the current shading if the program doesn't compile.
+    pub fn set_texture_shading(&mut self, gl: &gl::Gl, res: &Resources, shading: TextureShading) -> Result<(), failure::Error> {
+        if shading == self.texture_shading {
+            return Ok(());
+        }
+
+        let program = shading.load_program(gl, res)?;
+        self.replace_program(program);
+        self.texture_shading = shading;
+        self.program_adopted = false;
+        Ok(())
+    }
+
+    pub fn texture_shading(&self) -> TextureShading {
+        self.texture_shading
+    }
+
+    /// Draws with `program` from now on, returning the previous one.
+    fn replace_program(&mut self, program: Program) -> Program {
+        self.program_view_projection_location = program.get_uniform_location("ViewProjection");
+        self.program_wireframe_color_location = program.get_uniform_location("WireframeColor");
+        self.program_use_wireframe_color_location = program.get_uniform_location("UseWireframeColor");
+        self.program_time_location = program.get_uniform_location("Time");
+        self.program_texture_location = program.get_uniform_location("GroupTexture");
+        self.program_use_texture_location = program.get_uniform_location("UseGroupTexture");
+        self.uploaded_view_projection = None;
+
+        ::std::mem::replace(&mut self.program, program)
+    }
+
+    /// Picks when freed alphabet space is reclaimed; by default as soon as there is any.
+    pub fn set_defrag_policy(&mut self, policy: DefragPolicy) {
+        self.defrag_policy = policy;
+    }
+
+    /// Picks how changed group data is uploaded, e.g. `Orphan` for heavily animated text on drivers
+    /// that stall on `MapRange`. Performance only; every mode draws the same.
+    pub fn set_buffer_update_mode(&mut self, mode: BufferUpdateMode) {
+        self.buffer_update_mode = mode;
+        if let Some(ref mut buffers) = self.buffers {
+            buffers.set_update_mode(mode);
+        }
+    }
+
+    /// Picks how much buffers grow past what's needed when they run out of space.
+    /// Applies to the next reallocation; buffers don't shrink.
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy) {
+        self.growth_policy = policy;
+        if let Some(ref mut buffers) = self.buffers {
+            buffers.set_growth_policy(policy);
+        }
+    }
+
+    /// Repacks the alphabet buffers now and uploads them, whatever the policy.
+    ///
+    /// Returns `false` if there was nothing to reclaim, or if geometry wasn't retained.
+    pub fn defrag(&mut self, gl: &gl::Gl) -> bool {
+        let compacted = {
+            let mut flatland = self.flatland.borrow_mut();
+            flatland.has_wasted_alphabet_space() && flatland.compact_alphabets()
+        };
+
+        if compacted {
+            self.check_if_invalidated_and_reinitialize(gl);
+        }
+        compacted
+    }
+
+    fn check_if_invalidated_and_reinitialize(&mut self, gl: &gl::Gl) {
+        let mut flatland = self.flatland.borrow_mut();
+        // layered entries can change the number of draws of any group
+        let alphabets_changed = flatland.alphabets_invalidated;
+
+        #[cfg(feature = "upload_report")]
+        {
+            if let Some(ref mut buffers) = self.buffers {
+                buffers.report = buffers::UploadReport::default();
+            }
+        }
+
+        if flatland.alphabets_invalidated {
+            if self.buffers.is_none() {
+                let mut buffers = buffers::Buffers::new(gl);
+                buffers.set_update_mode(self.buffer_update_mode);
+                buffers.set_growth_policy(self.growth_policy);
+                self.buffers = Some(buffers);
+            }
+
+            let compact = match self.defrag_policy {
+                DefragPolicy::Auto { threshold } => flatland.wasted_alphabet_fraction() > threshold,
+                DefragPolicy::Never | DefragPolicy::Manual => false,
+            };
+            if compact && flatland.retains_cpu_data() {
+                flatland.compact_alphabets();
+            }
+
+            if let Some(ref mut buffers) = self.buffers {
+                buffers.upload_vertices(flatland.uploaded_alphabet_vertices_len(), flatland.alphabet_vertices_len(), &flatland.pending_alphabet_vertices());
+                buffers.upload_indices(flatland.uploaded_alphabet_indices_len(), flatland.alphabet_indices_len(), &flatland.pending_alphabet_indices());
+            }
+
+            flatland.mark_alphabets_uploaded();
+        }
+
+        if flatland.groups_invalidated {
+            if self.buffers.is_none() {
+                return;
+            }
+
+            if let Some(ref mut buffers) = self.buffers {
+                if alphabets_changed || flatland.static_groups_dirty() {
+                    buffers.upload_groups(Usage::Static, flatland.groups_len_with_usage(Usage::Static), flatland.groups_draw_data_with_usage(Usage::Static));
+                    flatland.mark_static_groups_uploaded();
+                }
+                buffers.upload_groups(Usage::Dynamic, flatland.groups_len_with_usage(Usage::Dynamic), flatland.groups_draw_data_with_usage(Usage::Dynamic));
+            }
+
+            flatland.groups_invalidated = false;
+        }
+
+        if flatland.draw_invalidated {
+            if self.buffers.is_none() {
+                return;
+            }
+
+            if let Some(ref mut buffers) = self.buffers {
+                let (commands, batches) = flatland.draw_commands();
+                buffers.upload_draw_commands(&commands, &batches);
+                self.last_cull_result = flatland.cull_result(|_, _| true);
+            }
+
+            flatland.draw_invalidated = false;
+        }
+    }
+
+    pub fn create_alphabet(&self) -> Alphabet {
+        let mut flatland = self.flatland.borrow_mut();
+        let slot = flatland.create_alphabet();
+        Alphabet {
+            slot,
+            flatland: self.flatland.clone(),
+        }
+    }
+
+    /// Creates an alphabet with an entry per char the source has, using the char as entry id.
+    ///
+    /// Returns the entry index of each added char, ready for `draw_text`.
+    /// Chars the source doesn't have are left out of the map.
+    ///
+    /// `tolerance` is passed on to the source for curve flattening. Halving it roughly
+    /// multiplies the vertices per curve by 1.4, so use larger values for small or distant
+    /// text and tighter ones for headlines. Indices are `u16` and local to each glyph,
+    /// so a single glyph can have at most 65536 vertices.
+    pub fn create_alphabet_from_source(&self, source: &dyn GlyphSource, chars: &[char], tolerance: f32) -> (Alphabet, HashMap<char, usize>) {
+        let alphabet = self.create_alphabet();
+        let mut char_to_entry = HashMap::with_capacity(chars.len());
+
+        for &ch in chars {
+            if char_to_entry.contains_key(&ch) {
+                continue;
+            }
+            if let Some((vertices, indices, advance)) = source.glyph(ch, tolerance) {
+                char_to_entry.insert(ch, alphabet.add_entry_with_advance(ch as u32, vertices, indices, advance));
+            }
+        }
+
+        (alphabet, char_to_entry)
+    }
+
+    /// Lays out `text` on a single line and draws it as a new group, see `draw_text_lines`
+    /// for text with newlines.
+    ///
+    /// Glyphs are spaced by their entry advances, adjusted by the alphabet's kerning pairs
+    /// and converted with the layout context.
+    /// Characters missing from `char_to_entry` are drawn with the alphabet's fallback entry,
+    /// or skipped if it has none; they are returned once each, in order of appearance, e.g.
+    /// to bake them and draw the text again. The text is drawn for as long as the returned
+    /// group is alive.
+    pub fn draw_text<S: BuildHasher>(
+        &mut self,
+        alphabet: &Alphabet,
+        char_to_entry: &HashMap<char, usize, S>,
+        text: &str,
+        transform: &na::Projective3<f32>,
+        color: na::Vector4<u8>
+    ) -> (FlatlandGroup, Vec<char>) {
+        let (items, missing) = alphabet.flatland.borrow().text_items(&self.layout_context, alphabet.slot, char_to_entry, text);
+
+        (FlatlandGroup::new(transform, color, alphabet.clone(), items), missing)
+    }
+
+    /// Like `draw_text`, but starts a new line at x = 0 for every `\n`, each line `line_height`
+    /// pixels below the previous one. Consecutive newlines leave empty lines.
+    ///
+    /// `draw_text` puts everything on one line and reports `\n` as missing.
+    pub fn draw_text_lines<S: BuildHasher>(
+        &mut self,
+        alphabet: &Alphabet,
+        char_to_entry: &HashMap<char, usize, S>,
+        text: &str,
+        line_height: f32,
+        transform: &na::Projective3<f32>,
+        color: na::Vector4<u8>
+    ) -> (FlatlandGroup, Vec<char>) {
+        let (items, missing) = {
+            let flatland = alphabet.flatland.borrow();
+            let fallback_entry = flatland.alphabet_data[alphabet.slot].fallback_entry;
+            layout::layout_text_lines(&self.layout_context, flatland.alphabet_metrics(alphabet.slot), char_to_entry, text, fallback_entry, line_height)
+        };
+
+        (FlatlandGroup::new(transform, color, alphabet.clone(), items), missing)
+    }
+
+    /// Draws shaped glyphs as a new group, looking up each glyph id as an alphabet entry id.
+    ///
+    /// Glyphs missing from the alphabet are drawn with its fallback entry, or skipped if it has
+    /// none, but still move the pen.
+    pub fn draw_shaped(
+        &mut self,
+        alphabet: &Alphabet,
+        shaped: &[ShapedGlyph],
+        transform: &na::Projective3<f32>,
+        color: na::Vector4<u8>
+    ) -> FlatlandGroup {
+        let fallback_entry = alphabet.flatland.borrow().alphabet_data[alphabet.slot].fallback_entry;
+        let items = layout::layout_shaped(&self.layout_context, shaped, |id| alphabet.get_entry_index(id).or(fallback_entry));
+
+        FlatlandGroup::new(transform, color, alphabet.clone(), items)
+    }
+
+    /// Moves every group animation forward by `dt` seconds, e.g. once per frame.
+    ///
+    /// Group data is only uploaded again while some animation is still running, and the draw
+    /// commands while a glyph effect is, since typewriter glyphs appear through their index counts.
+    pub fn advance_animations(&mut self, dt: f32) {
+        self.flatland.borrow_mut().advance_animations(dt);
+    }
+
+    /// Updates the transforms of many groups in one go; ids of dropped groups are skipped.
+    pub fn update_transforms(&mut self, updates: &[(GroupId, na::Projective3<f32>)]) {
+        self.flatland.borrow_mut().update_transforms(updates);
+    }
+
+    /// Hides or shows every group drawn with the alphabet, e.g. to compare two fonts.
+    ///
+    /// Only the draw commands are rebuilt; geometry and group data stay uploaded.
+    pub fn set_alphabet_visible(&mut self, alphabet: &Alphabet, visible: bool) {
+        self.flatland.borrow_mut().set_alphabet_visible(alphabet.slot, visible);
+    }
+
+    /// Deletes groups by id under a single borrow, e.g. to clear a selection of labels.
+    ///
+    /// Live `FlatlandGroup` handles of deleted groups do nothing when dropped;
+    /// any other use of them panics.
+    pub fn delete_groups(&mut self, ids: &[GroupId]) {
+        self.flatland.borrow_mut().delete_flatland_groups(ids);
+    }
+
+    /// Bakes the groups into one new group with their transforms applied to the item offsets,
+    /// deleting the originals, to draw many static labels with a single group.
+    ///
+    /// The frozen group can only be transformed as a whole afterward. Only the translation and
+    /// rotation about the z axis of each transform carry over; the color, blend mode and style
+    /// are the first group's, and backgrounds, shadows and animations are dropped. Decorations
+    /// are added to the alphabet as shapes.
+    /// Fails if no groups are listed, any was deleted, they don't share an alphabet, or they
+    /// have decorations and the alphabet is frozen.
+    pub fn freeze_groups(&mut self, ids: &[GroupId]) -> Result<FlatlandGroup, failure::Error> {
+        let (alphabet_slot, slot) = {
+            let mut flatland = self.flatland.borrow_mut();
+            let alphabets = ids.iter()
+                .map(|&id| flatland.group_data.get(id).map(|group| group.base_alphabet_slot))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| format_err!("can't freeze deleted groups"))?;
+            let alphabet_slot = match alphabets.split_first() {
+                None => return Err(format_err!("no groups to freeze")),
+                Some((first, rest)) if rest.iter().any(|a| a != first) => {
+                    return Err(format_err!("frozen groups must share an alphabet"));
+                },
+                Some((&first, _)) => first,
+            };
+            if flatland.alphabet_data[alphabet_slot].frozen && ids.iter().any(|&id| !flatland.group_data[id].decoration_items.is_empty()) {
+                return Err(format_err!("can't freeze decorated groups of a frozen alphabet"));
+            }
+            flatland.inc_alphabet(alphabet_slot);
+            (alphabet_slot, flatland.freeze_groups(ids))
+        };
+
+        Ok(FlatlandGroup {
+            alphabet: Alphabet { slot: alphabet_slot, flatland: self.flatland.clone() },
+            group_slot: slot,
+        })
+    }
+
+    /// Groups changed since `generation` was returned, e.g. to sync a picking buffer incrementally.
+    ///
+    /// Start from generation 0 and pass the returned generation on the next call.
+    /// Deleted groups aren't reported.
+    pub fn groups_changed_since(&self, generation: u64) -> (Vec<GroupId>, u64) {
+        self.flatland.borrow().groups_changed_since(generation)
+    }
+
+    /// Area to redraw since the last call, as x, y, width and height in pixels from the bottom-left
+    /// corner, ready for `glScissor`: where changed or deleted groups were drawn and are drawn now.
+    ///
+    /// The whole viewport the first time and whenever `vp` or `viewport` change, `None` when nothing did.
+    /// Changes to alphabet visibility aren't tracked.
+    pub fn take_dirty_screen_rect(&mut self, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> Option<na::Vector4<f32>> {
+        self.flatland.borrow_mut().take_dirty_screen_rect(vp, viewport)
+    }
+
+    /// Reports pairs of alphabets holding the same geometry, which usually means
+    /// the same font was baked twice.
+    pub fn find_duplicate_alphabets(&self) -> Vec<(AlphabetSlot, AlphabetSlot)> {
+        self.flatland.borrow().find_duplicate_alphabets()
+    }
+
+    pub fn render(&mut self, gl: &gl::Gl, target: &ColorBuffer, vp_matrix: &na::Matrix4<f32>) {
+        self.set_view_projection(*vp_matrix);
+        self.render_cached(gl, target);
+    }
+
+    /// Renders like `render`, but only fills pixels inside `rect`, e.g. to keep text inside a UI panel.
+    ///
+    /// `rect` is x, y, width and height in pixels from the bottom-left corner, like
+    /// `take_dirty_screen_rect`, and is rounded outward. When the scissor test is already on,
+    /// only the part of `rect` inside the current box is drawn, so calls nest; the scissor test
+    /// and box are restored afterwards. Groups have no clip rects of their own; to clip some of them further, or to
+    /// other shapes, mask them with the stencil test as well, which applies within the scissor.
+    pub fn render_in_scissor(&mut self, gl: &gl::Gl, target: &ColorBuffer, vp_matrix: &na::Matrix4<f32>, rect: na::Vector4<f32>) {
+        let mut previous_box: [gl::types::GLint; 4] = [0; 4];
+        let scissor_was_enabled = unsafe {
+            gl.GetIntegerv(gl::SCISSOR_BOX, previous_box.as_mut_ptr());
+            gl.IsEnabled(gl::SCISSOR_TEST) == gl::TRUE
+        };
+
+        let (mut x, mut y) = (rect.x.floor(), rect.y.floor());
+        let (mut right, mut top) = ((rect.x + rect.z).ceil(), (rect.y + rect.w).ceil());
+        if scissor_was_enabled {
+            let [px, py, pw, ph] = previous_box;
+            x = x.max(px as f32);
+            y = y.max(py as f32);
+            right = right.min((px + pw) as f32);
+            top = top.min((py + ph) as f32);
+        }
+        unsafe {
+            gl.Enable(gl::SCISSOR_TEST);
+            gl.Scissor(x as i32, y as i32, (right - x).max(0.0) as i32, (top - y).max(0.0) as i32);
+        }
+
+        self.render(gl, target, vp_matrix);
+
+        unsafe {
+            gl.Scissor(previous_box[0], previous_box[1], previous_box[2], previous_box[3]);
+            if !scissor_was_enabled {
+                gl.Disable(gl::SCISSOR_TEST);
+            }
+        }
+    }
+
+    /// Camera matrix used by `render_cached`, kept until replaced.
+    pub fn set_view_projection(&mut self, vp: na::Matrix4<f32>) {
+        self.view_projection = vp;
+    }
+
+    pub fn view_projection(&self) -> na::Matrix4<f32> {
+        self.view_projection
+    }
+
+    /// Renders with the matrix from `set_view_projection`.
+    ///
+    /// The uniform is only uploaded again when the matrix changed since the last render.
+    pub fn render_cached(&mut self, gl: &gl::Gl, target: &ColorBuffer) {
+        if self.draw_enabled {
+            self.upload(gl);
+            self.draw(gl, target);
+            self.update_churn();
+        }
+    }
+
+    /// Renders only the groups for which `pred` returns true, e.g. to debug a single label.
+    ///
+    /// Nothing is changed for good: the next `render` draws every group again.
+    /// Draws nothing between `begin_batch` and `end_batch`, since group data isn't uploaded then.
+    pub fn render_filtered(&mut self, gl: &gl::Gl, target: &ColorBuffer, vp_matrix: &na::Matrix4<f32>, pred: impl Fn(&GroupInfo) -> bool) {
+        if !self.draw_enabled || self.batching {
+            return;
+        }
+
+        self.set_view_projection(*vp_matrix);
+        self.upload(gl);
+        self.draw_filtered(gl, target, pred);
+    }
+
+    /// Layer called `name`, e.g. "background", "world", "ui" or "overlay", registering it
+    /// if it is new. `render_layers` draws layers in the order they were registered.
+    pub fn layer(&mut self, name: &str) -> LayerHandle {
+        self.flatland.borrow_mut().layer(name)
+    }
+
+    /// Like `FlatlandGroup::new`, with the group added to `layer`.
+    pub fn create_group_in_layer<A: Into<Alphabet>>(
+        &mut self,
+        layer: LayerHandle,
+        transform: &na::Projective3<f32>,
+        color: na::Vector4<u8>,
+        alphabet: A,
+        items: Vec<FlatlandItem>
+    ) -> FlatlandGroup {
+        let group = FlatlandGroup::new(transform, color, alphabet, items);
+        self.add_to_layer(layer, group.id());
+        group
+    }
+
+    /// Moves an existing group to `layer`, e.g. one from `draw_text`. Deleted groups are skipped.
+    pub fn add_to_layer(&mut self, layer: LayerHandle, id: GroupId) {
+        self.flatland.borrow_mut().add_to_layer(layer, id);
+    }
+
+    /// Hides or shows the groups of a layer in `render_layers`, registering the layer if it is new.
+    pub fn set_layer_enabled(&mut self, name: &str, enabled: bool) {
+        let mut flatland = self.flatland.borrow_mut();
+        let layer = flatland.layer(name);
+        flatland.layers[layer.index].enabled = enabled;
+    }
+
+    /// Renders the groups of each enabled layer, one layer after the other in registration order.
+    ///
+    /// Groups outside of any layer are left out; `render` still draws every group.
+    /// Draws nothing between `begin_batch` and `end_batch`, like `render_filtered`.
+    pub fn render_layers(&mut self, gl: &gl::Gl, target: &ColorBuffer, vp_matrix: &na::Matrix4<f32>) {
+        if !self.draw_enabled || self.batching {
+            return;
+        }
+
+        self.set_view_projection(*vp_matrix);
+        self.upload(gl);
+
+        let layer_count = self.flatland.borrow().layers.len();
+        for index in 0..layer_count {
+            {
+                let mut flatland = self.flatland.borrow_mut();
+                if !flatland.layers[index].enabled {
+                    continue;
+                }
+                if let Some(ref mut buffers) = self.buffers {
+                    let (commands, batches) = flatland.layer_draw_commands(LayerHandle { index });
+                    buffers.upload_draw_commands(&commands, &batches);
+                }
+                // the next render uploads the full command set again
+                flatland.draw_invalidated = true;
+            }
+
+            self.draw(gl, target);
+        }
+
+        let flatland = self.flatland.borrow();
+        self.last_cull_result = flatland.cull_result(|id, _| flatland.in_enabled_layer(id));
+    }
+
+    /// Compiles the program of `render_picking`, the flatland vertex shader
+    /// with a fragment shader writing the group colors as they are.
+    pub fn load_picking_program(&mut self, gl: &gl::Gl, res: &Resources) -> Result<(), failure::Error> {
+        let shaders = [
+            Shader::from_res(gl, res, "shaders/render_gl/flatland.vert")?,
+            Shader::from_res(gl, res, "shaders/render_gl/flatland_picking.frag")?,
+        ];
+        let program = Program::from_shaders(gl, &shaders).map_err(|e| format_err!("failed to link flatland picking program: {}", e))?;
+        let view_projection_location = program.get_uniform_location("ViewProjection")
+            .ok_or_else(|| format_err!("flatland picking program has no ViewProjection uniform"))?;
+
+        self.picking_program = Some((program, view_projection_location));
+        Ok(())
+    }
+
+    /// Renders every group in a flat color encoding it into `target_fbo`, bound as the draw
+    /// framebuffer, so the pixel under the cursor tells which group is on top there,
+    /// whatever its shape or rotation. See `picked_group` to decode a pixel.
+    ///
+    /// Needs `load_picking_program` first. The framebuffer needs an RGBA8 color attachment,
+    /// cleared to 0 beforehand; blending is turned off, and shadows aren't drawn.
+    /// Draws nothing between `begin_batch` and `end_batch`, like `render_filtered`.
+    pub fn render_picking(&mut self, gl: &gl::Gl, target_fbo: gl::types::GLuint, vp_matrix: &na::Matrix4<f32>) -> Result<(), failure::Error> {
+        if self.picking_program.is_none() {
+            return Err(format_err!("flatland picking program not loaded, see Flatlander::load_picking_program"));
+        }
+        if !self.draw_enabled || self.batching {
+            return Ok(());
+        }
+
+        self.set_view_projection(*vp_matrix);
+        self.upload(gl);
+
+        let buffers = match self.buffers {
+            Some(ref mut buffers) => buffers,
+            None => return Ok(()),
+        };
+        let (ref program, view_projection_location) = *self.picking_program.as_ref().unwrap();
+
+        {
+            let mut flatland = self.flatland.borrow_mut();
+            for &usage in &[Usage::Static, Usage::Dynamic] {
+                buffers.upload_groups(usage, flatland.groups_len_with_usage(usage), flatland.picking_draw_data(usage));
+            }
+            // the next render uploads the group colors again
+            flatland.invalidate_group_uploads();
+            self.picking_ids = flatland.group_data.keys().collect();
+        }
+
+        let mut previous_fbo = 0;
+        unsafe {
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, target_fbo);
+        }
+
+        program.set_used();
+        program.set_uniform_matrix_4fv(view_projection_location, &self.view_projection);
+        buffers.indirect.buffer.bind();
+
+        unsafe {
+            let blend_was_enabled = gl.IsEnabled(gl::BLEND) == gl::TRUE;
+            gl.Disable(gl::BLEND);
+
+            for batch in &buffers.batches {
+                match batch.usage {
+                    Usage::Static => buffers.static_vao.bind(),
+                    Usage::Dynamic => buffers.lines_vao.bind(),
+                }
+                gl.FrontFace(if batch.mirrored { gl::CCW } else { gl::CW });
+                multi_draw_indirect(gl, self.draw_path, batch.first_command, batch.command_count);
+            }
+
+            gl.FrontFace(gl::CCW);
+            if blend_was_enabled {
+                gl.Enable(gl::BLEND);
+            }
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+        }
+
+        buffers.indirect.buffer.unbind();
+        buffers.lines_vao.unbind();
+        Ok(())
+    }
+
+    /// Group that drew a pixel read from the `render_picking` target, `None` for the background
+    /// or groups deleted since.
+    pub fn picked_group(&self, pixel: [u8; 4]) -> Option<GroupId> {
+        let id = *self.picking_ids.get(flatland::picking_index(pixel)?)?;
+        match self.flatland.borrow().group_data.contains_key(id) {
+            true => Some(id),
+            false => None,
+        }
+    }
+
+    /// Reads the pixel at `x`, `y`, from the bottom-left, of the `render_picking` target and
+    /// decodes it with `picked_group`. Reading back waits for the gpu to finish drawing.
+    pub fn read_picked_group(&self, gl: &gl::Gl, target_fbo: gl::types::GLuint, x: i32, y: i32) -> Option<GroupId> {
+        let mut pixel = [0u8; 4];
+        unsafe {
+            let mut previous_fbo = 0;
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, target_fbo);
+            gl.ReadPixels(x, y, 1, 1, gl::RGBA, gl::UNSIGNED_BYTE, pixel.as_mut_ptr() as *mut gl::types::GLvoid);
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+        }
+        self.picked_group(pixel)
+    }
+
+    /// Uploads pending alphabet and group changes without drawing, ahead of `draw_range` calls.
+    ///
+    /// Does nothing between `begin_batch` and `end_batch`.
+    pub fn upload(&mut self, gl: &gl::Gl) {
+        if !self.batching {
+            self.select_lod_alphabets(gl);
+            self.check_if_invalidated_and_reinitialize(gl);
+        }
+    }
 
-            if let Some(ref mut buffers) = self.buffers {
-                buffers.upload_vertices(flatland.alphabet_vertices_len(), flatland.alphabet_vertices());
-                buffers.upload_indices(flatland.alphabet_indices_len(), flatland.alphabet_indices());
-            }
+    /// Uploads and draws the flatland's unit quad with color and depth writes off, so that the
+    /// driver finishes compiling the program and allocating buffers before any text appears,
+    /// e.g. during a loading screen instead of on the first frame with text.
+    ///
+    /// Needs a complete framebuffer to be bound. Other pending changes are uploaded too, and
+    /// groups already created are drawn invisibly before the quad. The quad is the one that
+    /// decorations and backgrounds are drawn with, so nothing is left behind in the vertex buffer.
+    /// Does nothing between `begin_batch` and `end_batch`.
+    pub fn prewarm(&mut self, gl: &gl::Gl) {
+        if self.batching {
+            return;
+        }
 
-            flatland.alphabets_invalidated = false;
+        let mut color_mask: [gl::types::GLboolean; 4] = [gl::TRUE; 4];
+        let mut depth_mask: gl::types::GLboolean = gl::TRUE;
+        unsafe {
+            gl.GetBooleanv(gl::COLOR_WRITEMASK, color_mask.as_mut_ptr());
+            gl.GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+            gl.ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+            gl.DepthMask(gl::FALSE);
         }
 
-        if flatland.groups_invalidated {
-            if self.buffers.is_none() {
-                return;
-            }
+        self.upload(gl);
+        self.draw(gl, &ColorBuffer::new());
+        self.draw_world_quads(gl, &ColorBuffer::new(), &[(na::Vector2::zeros(), na::Vector2::new(1.0, 1.0))], na::Vector4::new(0, 0, 0, 0));
 
-            if let Some(ref mut buffers) = self.buffers {
-                buffers.upload_groups(flatland.groups_len(), flatland.groups_draw_data());
-            }
+        unsafe {
+            gl.ColorMask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
+            gl.DepthMask(depth_mask);
+        }
+    }
 
-            flatland.groups_invalidated = false;
+    /// Draws only the groups whose transform places them at a z in `start_z..end_z`,
+    /// with the matrix from `set_view_projection`.
+    ///
+    /// Call `upload` first, then `draw_range` once per band, with other GL drawing between
+    /// the bands. Like `render_filtered`, the next `render` draws every group again.
+    pub fn draw_range(&mut self, gl: &gl::Gl, target: &ColorBuffer, start_z: f32, end_z: f32) {
+        if !self.draw_enabled || self.batching {
+            return;
         }
 
-        if flatland.draw_invalidated {
-            if self.buffers.is_none() {
-                return;
-            }
+        self.draw_filtered(gl, target, |group| {
+            let z = group.transform.matrix()[(2, 3)];
+            z >= start_z && z < end_z
+        });
+    }
 
+    /// Uploads draw commands for the groups matching `pred` and draws them.
+    fn draw_filtered(&mut self, gl: &gl::Gl, target: &ColorBuffer, pred: impl Fn(&GroupInfo) -> bool) {
+        {
+            let mut flatland = self.flatland.borrow_mut();
             if let Some(ref mut buffers) = self.buffers {
-                buffers.upload_draw_commands(flatland.groups_len(), flatland.groups_draw_data());
+                let groups: &flatland::Flatland = &flatland;
+                let matches = |id, group: &flatland::GroupData| pred(&GroupInfo {
+                    id,
+                    alphabet: group.alphabet_slot,
+                    transform: group.transform,
+                    color: group.color,
+                    blend_mode: group.blend_mode,
+                    user_data: groups.group_user_data(id),
+                });
+                let (commands, batches) = groups.draw_commands_filtered(&matches);
+                buffers.upload_draw_commands(&commands, &batches);
+                self.last_cull_result = groups.cull_result(&matches);
             }
-
-            flatland.draw_invalidated = false;
+            // the next render uploads the full command set again
+            flatland.draw_invalidated = true;
         }
+
+        self.draw(gl, target);
     }
 
-    pub fn create_alphabet(&self) -> Alphabet {
+    /// Switches groups with levels of detail to the alphabet for their size in the current viewport.
+    fn select_lod_alphabets(&mut self, gl: &gl::Gl) {
         let mut flatland = self.flatland.borrow_mut();
-        let slot = flatland.create_alphabet();
-        Alphabet {
-            slot,
-            flatland: self.flatland.clone(),
+        if !flatland.has_lod_groups() {
+            return;
         }
-    }
 
-    pub fn render(&mut self, gl: &gl::Gl, target: &ColorBuffer, vp_matrix: &na::Matrix4<f32>) {
-        if self.draw_enabled {
-            self.check_if_invalidated_and_reinitialize(gl);
+        let mut viewport = [0; 4];
+        unsafe {
+            gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        flatland.select_lod_alphabets(&self.view_projection, na::Vector2::new(viewport[2] as f32, viewport[3] as f32));
+    }
 
-            if let Some(ref buffers) = self.buffers {
-                self.program.set_used();
+    /// Draws whatever was last uploaded.
+    fn draw(&mut self, gl: &gl::Gl, target: &ColorBuffer) {
+        if let Some(ref buffers) = self.buffers {
+            self.program.set_used();
+            if self.uploaded_view_projection != Some(self.view_projection) {
                 if let Some(loc) = self.program_view_projection_location {
-                    self.program.set_uniform_matrix_4fv(loc, &vp_matrix);
+                    self.program.set_uniform_matrix_4fv(loc, &self.view_projection);
                 }
+                self.uploaded_view_projection = Some(self.view_projection);
+            }
 
-                buffers.lines_vao.bind();
-                buffers.indirect.buffer.bind();
+            let wireframe_color = self.wireframe_color.filter(|_| self.wireframe);
+            if let Some(loc) = self.program_use_wireframe_color_location {
+                self.program.set_uniform_1i(loc, wireframe_color.is_some() as i32);
+            }
+            if let (Some(loc), Some(color)) = (self.program_wireframe_color_location, wireframe_color) {
+                let color = na::Vector4::new(color.x as f32, color.y as f32, color.z as f32, color.w as f32) / 255.0;
+                self.program.set_uniform_4f(loc, &color);
+            }
+            if let Some(loc) = self.program_time_location {
+                self.program.set_uniform_1f(loc, self.time);
+            }
+            if let Some(loc) = self.program_texture_location {
+                self.program.set_uniform_1i(loc, 0);
+            }
 
-                unsafe {
-                    target.set_default_blend_func(gl);
-//                    target.enable_blend(gl);
-                    target.front_face_cw(gl);
-                    if self.wireframe {
-                        target.polygon_mode_line(gl);
-                    }
+            buffers.lines_vao.bind();
+            buffers.indirect.buffer.bind();
 
-                    if gl.MultiDrawElementsIndirect.is_loaded() {
-                        // open gl 4.3
-                        gl.MultiDrawElementsIndirect(
-                            gl::TRIANGLES,
-                            gl::UNSIGNED_SHORT,
-                            0 as *const ::std::ffi::c_void,
-                            buffers.indirect.len as i32,
-                            ::std::mem::size_of::<DrawIndirectCmd>() as i32
-                        );
-                    } else {
-                        // open gl 4.1
-                        // manual implementation of MultiDrawElementsIndirect
-
-                        for i in 0..buffers.indirect.len {
-                            gl.DrawElementsIndirect(
-                                gl::TRIANGLES,
-                                gl::UNSIGNED_SHORT,
-                                (i as u32 * ::std::mem::size_of::<DrawIndirectCmd>() as u32) as *const ::std::ffi::c_void
-                            );
+            self.draw_hooks.around(gl, || unsafe {
+                if self.wireframe {
+                    target.polygon_mode_line(gl);
+                }
+                if self.alpha_to_coverage {
+                    target.enable_alpha_to_coverage(gl);
+                }
+
+                let blend_was_enabled = target.query_blend_enabled(gl);
+                let mut bound_usage = Some(Usage::Dynamic);
+                for batch in &buffers.batches {
+                    if bound_usage != Some(batch.usage) {
+                        match batch.usage {
+                            Usage::Static => buffers.static_vao.bind(),
+                            Usage::Dynamic => buffers.lines_vao.bind(),
                         }
+                        bound_usage = Some(batch.usage);
+                    }
+                    if batch.texture != 0 {
+                        gl.ActiveTexture(gl::TEXTURE0);
+                        gl.BindTexture(gl::TEXTURE_2D, batch.texture);
+                    }
+                    if let Some(loc) = self.program_use_texture_location {
+                        self.program.set_uniform_1i(loc, (batch.texture != 0) as i32);
+                    }
+                    // mirrored groups show the back of their triangles
+                    if batch.mirrored {
+                        target.front_face_ccw(gl);
+                    } else {
+                        target.front_face_cw(gl);
+                    }
+
+                    match batch.blend_mode {
+                        BlendMode::Alpha => {
+                            target.set_default_blend_func(gl);
+                            if blend_was_enabled {
+                                target.enable_blend(gl);
+                            } else {
+                                target.disable_blend(gl);
+                            }
+                        },
+                        BlendMode::PremultipliedAlpha => {
+                            target.set_premultiplied_blend_func(gl);
+                            target.enable_blend(gl);
+                        },
+                        BlendMode::Additive => {
+                            target.set_additive_blend_func(gl);
+                            target.enable_blend(gl);
+                        },
+                        BlendMode::Multiply => {
+                            target.set_multiply_blend_func(gl);
+                            target.enable_blend(gl);
+                        },
+                    }
+
+                    let outline = batch.style == RenderStyle::Outline && !self.wireframe;
+                    if outline {
+                        target.polygon_mode_line(gl);
                     }
 
-                    if self.wireframe {
+                    multi_draw_indirect(gl, self.draw_path, batch.first_command, batch.command_count);
+
+                    if outline {
                         target.polygon_mode_fill(gl);
                     }
-                    target.front_face_ccw(gl);
-//                    target.disable_blend(gl);
+
+                    if batch.texture != 0 {
+                        gl.BindTexture(gl::TEXTURE_2D, 0);
+                    }
                 }
 
-                buffers.indirect.buffer.unbind();
-                buffers.lines_vao.unbind();
-            }
+                if blend_was_enabled {
+                    target.enable_blend(gl);
+                } else {
+                    target.disable_blend(gl);
+                }
+                if self.alpha_to_coverage {
+                    target.disable_alpha_to_coverage(gl);
+                }
+                if self.wireframe {
+                    target.polygon_mode_fill(gl);
+                }
+                target.front_face_ccw(gl);
+            });
+
+            buffers.indirect.buffer.unbind();
+            buffers.lines_vao.unbind();
         }
     }
 }
 
+unsafe fn multi_draw_indirect(gl: &gl::Gl, draw_path: DrawPath, first_command: usize, command_count: usize) {
+    let stride = ::std::mem::size_of::<DrawIndirectCmd>();
+
+    match draw_path {
+        DrawPath::MultiDrawIndirect => {
+            gl.MultiDrawElementsIndirect(
+                gl::TRIANGLES,
+                gl::UNSIGNED_SHORT,
+                (first_command * stride) as *const ::std::ffi::c_void,
+                command_count as i32,
+                stride as i32
+            );
+        },
+        DrawPath::DrawIndirectLoop => {
+            // manual implementation of MultiDrawElementsIndirect
+            for i in first_command..first_command + command_count {
+                gl.DrawElementsIndirect(
+                    gl::TRIANGLES,
+                    gl::UNSIGNED_SHORT,
+                    (i * stride) as *const ::std::ffi::c_void
+                );
+            }
+        },
+    }
+}
+
 pub struct Alphabet {
     slot: flatland::AlphabetSlot,
     flatland: Rc<RefCell<flatland::Flatland>>,
@@ -167,15 +1748,250 @@ impl Clone for Alphabet {
 }
 
 impl Alphabet {
+    pub fn slot(&self) -> AlphabetSlot {
+        self.slot
+    }
+
     pub fn get_entry_index(&self, id: u32) -> Option<usize> {
         let flatland = self.flatland.borrow();
         flatland.get_alphabet_entry_index(self.slot, id)
     }
 
+    /// Whether an entry was added for `id`.
+    pub fn has_glyph(&self, id: u32) -> bool {
+        self.get_entry_index(id).is_some()
+    }
+
+    /// Entry that `Flatlander::draw_text` draws for characters it has no entry for,
+    /// e.g. a box, instead of leaving them out. `None`, the default, leaves them out.
+    pub fn set_fallback_entry(&self, entry_index: Option<usize>) {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.alphabet_data[self.slot].fallback_entry = entry_index;
+    }
+
+    /// Adds a glyph drawn in place of missing characters and ids, like `set_fallback_entry`,
+    /// e.g. `glyph::tofu_box` to make them obvious during development. The glyph advances the pen
+    /// by its right edge plus the gap left of it.
+    ///
+    /// Setting another fallback glyph leaves the previous one to `compact`.
+    pub fn set_fallback_glyph(&self, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.set_alphabet_fallback_glyph(self.slot, vertices, indices)
+    }
+
+    /// Every `(id, entry index)` pair of the alphabet, by entry index, e.g. to save the mapping
+    /// next to baked geometry. Several ids can share a deduplicated entry.
+    pub fn entries(&self) -> impl Iterator<Item = (u32, usize)> {
+        let flatland = self.flatland.borrow();
+        flatland.alphabet_entry_ids(self.slot).into_iter()
+    }
+
+    /// Adds an entry after the existing ones and returns its index.
+    ///
+    /// Adding entries never renumbers the existing ones, so glyphs can be added to an alphabet
+    /// while groups are drawing it, e.g. as new characters show up in dynamic text. Only `compact`
+    /// and `clear` renumber entries.
     pub fn add_entry(&self, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
         let mut flatland = self.flatland.borrow_mut();
         flatland.add_alphabet_entry(self.slot, id, vertices, indices)
     }
+
+    /// Adds an entry with the distance to move the pen after drawing it, used by text layout.
+    /// Like other layout inputs, the advance is in pixels (see `layout::LayoutContext`).
+    pub fn add_entry_with_advance(&self, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>, advance: f32) -> usize {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.add_alphabet_entry_with_advance(self.slot, id, vertices, indices, advance)
+    }
+
+    /// Adds an entry after turning every triangle to `winding`, for sources that disagree on it.
+    ///
+    /// The flatlander draws all entries with one front face, so with face culling enabled,
+    /// pick the winding the projection shows as front faces. Degenerate triangles are kept as they are.
+    pub fn add_entry_normalized(&self, id: u32, vertices: Vec<FlatlanderVertex>, mut indices: Vec<u16>, winding: Winding) -> usize {
+        flatland::normalize_winding(&vertices, &mut indices, winding);
+        self.add_entry(id, vertices, indices)
+    }
+
+    /// Adds an entry drawn as several colored triangle sets, in order, e.g. for color emoji.
+    ///
+    /// The group color multiplies each layer color, so white groups show the layers as they are.
+    pub fn add_layered_entry(&self, id: u32, layers: Vec<(na::Vector4<u8>, Vec<FlatlanderVertex>, Vec<u16>)>) -> usize {
+        self.add_layered_entry_with_advance(id, layers, 0.0)
+    }
+
+    pub fn add_layered_entry_with_advance(&self, id: u32, layers: Vec<(na::Vector4<u8>, Vec<FlatlanderVertex>, Vec<u16>)>, advance: f32) -> usize {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.add_layered_alphabet_entry(self.slot, id, layers, advance)
+    }
+
+    /// Triangles drawn for the entries, e.g. those of a string, counting every layer of layered entries.
+    ///
+    /// Together with `layout::measure_line` over the same entries, it tells how heavy a label
+    /// is going to be before creating it. Works after the cpu data is dropped.
+    pub fn triangle_count_for(&self, entry_indices: &[usize]) -> usize {
+        let flatland = self.flatland.borrow();
+        flatland.alphabet_data[self.slot].triangle_count(entry_indices)
+    }
+
+    /// Copy of the entry metrics, for layout away from the renderer.
+    pub fn metrics(&self) -> layout::GlyphMetrics {
+        let flatland = self.flatland.borrow();
+        (**flatland.alphabet_metrics(self.slot)).clone()
+    }
+
+    /// The alphabet itself if its entries have at most 65536 vertices in total, otherwise copies
+    /// of its entries split across new alphabets of at most that many vertices each.
+    ///
+    /// Indices are local to each entry and drawn with its place in the shared vertex buffer as
+    /// base vertex, so each part is indexed on its own wherever its geometry lands.
+    /// Entries keep their ids, so groups are moved over by looking up each item's entry id
+    /// with `get_entry_index` on the alphabet holding it; the indices themselves change.
+    /// Call it before the first render, or with cpu data retained, since the geometry is copied.
+    /// Drop this alphabet once its groups moved, so its space can be reused.
+    pub fn split_if_needed(&self) -> Vec<Alphabet> {
+        if self.flatland.borrow().alphabet_vertex_count(self.slot) <= flatland::MAX_ALPHABET_VERTICES {
+            return vec![self.clone()];
+        }
+
+        let slots = self.flatland.borrow_mut().split_alphabet(self.slot, flatland::MAX_ALPHABET_VERTICES);
+        slots.into_iter()
+            .map(|slot| Alphabet { slot, flatland: self.flatland.clone() })
+            .collect()
+    }
+
+    /// Every entry index with its stored advance, 0 for entries added without one,
+    /// for external layout that does its own shaping.
+    pub fn advances(&self) -> Vec<(usize, f32)> {
+        let flatland = self.flatland.borrow();
+        flatland.alphabet_metrics(self.slot).advances.iter().cloned().enumerate().collect()
+    }
+
+    /// Turns the alphabet read-only once it is fully built, e.g. after baking a font.
+    ///
+    /// Its storage is trimmed and its metrics can be shared without copying. Every weight of
+    /// its interpolable entries is generated now, so once uploaded the alphabet is never
+    /// invalidated or uploaded again, whatever its groups do.
+    /// Adding entries, kerning or clearing a frozen alphabet panics.
+    pub fn freeze(self) -> FrozenAlphabet {
+        self.flatland.borrow_mut().freeze_alphabet(self.slot);
+        FrozenAlphabet { alphabet: self }
+    }
+
+    /// Adds a glyph whose weight groups can vary from `regular` to `bold` with `FlatlandGroup::set_weight`.
+    ///
+    /// Fails unless both outlines have the same topology: as many vertices, connected
+    /// by the same indices, so each regular vertex moves to its bold counterpart.
+    pub fn add_interpolable_entry(&self, id: u32, regular: (Vec<FlatlanderVertex>, Vec<u16>), bold: (Vec<FlatlanderVertex>, Vec<u16>)) -> Result<usize, failure::Error> {
+        self.add_interpolable_entry_with_advance(id, regular, bold, 0.0)
+    }
+
+    pub fn add_interpolable_entry_with_advance(
+        &self,
+        id: u32,
+        regular: (Vec<FlatlanderVertex>, Vec<u16>),
+        bold: (Vec<FlatlanderVertex>, Vec<u16>),
+        advance: f32
+    ) -> Result<usize, failure::Error> {
+        if regular.0.len() != bold.0.len() {
+            return Err(format_err!("regular outline of entry {} has {} vertices, bold has {}", id, regular.0.len(), bold.0.len()));
+        }
+        if regular.1 != bold.1 {
+            return Err(format_err!("regular and bold outlines of entry {} have different indices", id));
+        }
+
+        let mut flatland = self.flatland.borrow_mut();
+        Ok(flatland.add_interpolable_alphabet_entry(self.slot, id, regular, bold.0, advance))
+    }
+
+    /// Adjusts the advance of `left` when it is followed by `right`.
+    pub fn set_kerning(&self, left: usize, right: usize, adjustment: f32) {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.set_alphabet_kerning(self.slot, left, right, adjustment);
+    }
+
+    /// Adds an entry, or maps `id` to an existing entry that was added with the same `key`
+    /// and identical geometry. Use `Alphabet::geometry_key` when there is no better key at hand.
+    pub fn add_entry_deduped(&self, key: u64, id: u32, vertices: Vec<FlatlanderVertex>, indices: Vec<u16>) -> usize {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.add_alphabet_entry_deduped(self.slot, key, id, vertices, indices)
+    }
+
+    /// Drops the entries that no id, group or fallback refers to anymore and renumbers the others,
+    /// e.g. after replacing many glyphs. Groups using the alphabet keep drawing the same glyphs.
+    ///
+    /// Returns the new index of each old entry, `None` for dropped ones, to update entry indices
+    /// kept elsewhere, such as the `char_to_entry` maps of `Flatlander::draw_text`.
+    /// The space of dropped entries is reused by later entries, or repacked by `Flatlander::defrag`.
+    ///
+    /// Panics if the alphabet is frozen or a level of detail of a group.
+    pub fn compact(&self) -> Vec<Option<usize>> {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.compact_alphabet(self.slot)
+    }
+
+    /// Removes all entries, so the alphabet can be rebuilt from scratch.
+    ///
+    /// Groups using this alphabet keep their entry indices: items pointing at removed
+    /// entries draw nothing, and draw whatever entry gets that index once the alphabet is rebuilt.
+    /// Update or recreate such groups after rebuilding.
+    pub fn clear(&self) {
+        let mut flatland = self.flatland.borrow_mut();
+        flatland.clear_alphabet(self.slot);
+    }
+
+    /// Copy of an entry's vertices and indices, or `None` if there is no such entry
+    /// or its data was freed after upload (see `Flatlander::set_retain_cpu_data`).
+    pub fn entry_geometry(&self, index: usize) -> Option<(Vec<FlatlanderVertex>, Vec<u16>)> {
+        let flatland = self.flatland.borrow();
+        flatland.alphabet_entry_geometry(self.slot, index)
+    }
+
+    pub fn geometry_key(vertices: &[FlatlanderVertex], indices: &[u16]) -> u64 {
+        flatland::geometry_hash(vertices, indices)
+    }
+}
+
+/// Read-only alphabet handle, see `Alphabet::freeze`.
+#[derive(Clone)]
+pub struct FrozenAlphabet {
+    alphabet: Alphabet,
+}
+
+impl FrozenAlphabet {
+    pub fn slot(&self) -> AlphabetSlot {
+        self.alphabet.slot
+    }
+
+    pub fn get_entry_index(&self, id: u32) -> Option<usize> {
+        self.alphabet.get_entry_index(id)
+    }
+
+    pub fn has_glyph(&self, id: u32) -> bool {
+        self.alphabet.has_glyph(id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (u32, usize)> {
+        self.alphabet.entries()
+    }
+
+    pub fn entry_geometry(&self, index: usize) -> Option<(Vec<FlatlanderVertex>, Vec<u16>)> {
+        self.alphabet.entry_geometry(index)
+    }
+
+    pub fn advances(&self) -> Vec<(usize, f32)> {
+        self.alphabet.advances()
+    }
+
+    pub fn metrics(&self) -> Rc<layout::GlyphMetrics> {
+        let flatland = self.alphabet.flatland.borrow();
+        flatland.alphabet_metrics(self.alphabet.slot).clone()
+    }
+}
+
+impl From<FrozenAlphabet> for Alphabet {
+    fn from(frozen: FrozenAlphabet) -> Alphabet {
+        frozen.alphabet
+    }
 }
 
 impl Drop for Alphabet {
@@ -185,11 +2001,61 @@ impl Drop for Alphabet {
     }
 }
 
+/// Subpixels per alphabet unit of `FlatlandItem` offsets.
+pub const SUBPIXEL_SCALE: i32 = 64;
+
 #[derive(Copy, Clone)]
 pub struct FlatlandItem {
+    /// Entry of the group's alphabet; groups check it in debug builds when given new items.
     pub alphabet_entry_index: usize,
+    /// Position of the glyph origin in 26.6 fixed point, like FreeType positions: in 64ths of
+    /// an alphabet unit (`SUBPIXEL_SCALE`), so glyphs can sit between whole units.
+    /// Whole units are `x_offset >> 6`; `x` and `y` give the position in units.
     pub x_offset: i32,
     pub y_offset: i32,
+    /// Rotation of the glyph around its origin, in radians.
+    pub rotation: f32,
+}
+
+impl FlatlandItem {
+    /// Item with its origin at `x`, `y` in alphabet units, rounded to the nearest subpixel.
+    pub fn new(alphabet_entry_index: usize, x: f32, y: f32) -> FlatlandItem {
+        FlatlandItem {
+            alphabet_entry_index,
+            x_offset: to_subpixels(x),
+            y_offset: to_subpixels(y),
+            rotation: 0.0,
+        }
+    }
+
+    /// Horizontal position of the origin in alphabet units.
+    pub fn x(&self) -> f32 {
+        self.x_offset as f32 / SUBPIXEL_SCALE as f32
+    }
+
+    /// Vertical position of the origin in alphabet units.
+    pub fn y(&self) -> f32 {
+        self.y_offset as f32 / SUBPIXEL_SCALE as f32
+    }
+
+    /// Position of the origin in alphabet units.
+    pub fn position(&self) -> na::Vector2<f32> {
+        na::Vector2::new(self.x(), self.y())
+    }
+
+    /// Copy of the item moved to the nearest whole units, for crisp static text.
+    pub fn snapped(&self) -> FlatlandItem {
+        FlatlandItem {
+            x_offset: to_subpixels(self.x().round()),
+            y_offset: to_subpixels(self.y().round()),
+            ..*self
+        }
+    }
+}
+
+/// Nearest item offset for a position in alphabet units.
+pub fn to_subpixels(units: f32) -> i32 {
+    (units * SUBPIXEL_SCALE as f32).round() as i32
 }
 
 pub struct FlatlandGroup {
@@ -198,7 +2064,9 @@ pub struct FlatlandGroup {
 }
 
 impl FlatlandGroup {
-    pub fn new(transform: &na::Projective3<f32>, color: na::Vector4<u8>, alphabet: Alphabet, items: Vec<FlatlandItem>) -> FlatlandGroup {
+    /// Creates a group drawing `items` from an `Alphabet` or a `FrozenAlphabet`.
+    pub fn new<A: Into<Alphabet>>(transform: &na::Projective3<f32>, color: na::Vector4<u8>, alphabet: A, items: Vec<FlatlandItem>) -> FlatlandGroup {
+        let alphabet = alphabet.into();
         let id = alphabet.flatland.borrow_mut().create_flatland_group_with_items(transform, color, alphabet.slot, items);
 
         FlatlandGroup {
@@ -207,6 +2075,43 @@ impl FlatlandGroup {
         }
     }
 
+    /// Like `new`, storing the group's instance data with the other groups of `usage`.
+    pub fn new_with_usage<A: Into<Alphabet>>(transform: &na::Projective3<f32>, color: na::Vector4<u8>, alphabet: A, items: Vec<FlatlandItem>, usage: Usage) -> FlatlandGroup {
+        let group = FlatlandGroup::new(transform, color, alphabet, items);
+        group.alphabet.flatland.borrow_mut().update_usage(group.group_slot, usage);
+        group
+    }
+
+    pub fn id(&self) -> GroupId {
+        self.group_slot
+    }
+
+    /// Another handle to the alphabet the group was created with, e.g. to show its font in an
+    /// inspector or to create more groups with it. The alphabet lives as long as any handle or group
+    /// using it. Groups with levels of detail may draw another alphabet, see `set_lod_alphabets`.
+    pub fn alphabet(&self) -> Alphabet {
+        self.alphabet.clone()
+    }
+
+    /// New group with this group's items, transform and color, e.g. for a copy operation.
+    ///
+    /// Other state such as animations or decorations isn't copied, and the groups change independently afterwards.
+    pub fn duplicate(&self) -> FlatlandGroup {
+        let alphabet = self.alphabet.clone();
+        let group_slot = alphabet.flatland.borrow_mut().duplicate_flatland_group(self.group_slot);
+        FlatlandGroup { alphabet, group_slot }
+    }
+
+    /// Tags the group with an application value, e.g. an entity index to get back to from a picked group.
+    pub fn set_user_data(&self, data: u64) {
+        self.alphabet.flatland.borrow_mut().set_group_user_data(self.group_slot, data);
+    }
+
+    /// The tag from `set_user_data`, 0 if none was set.
+    pub fn user_data(&self) -> u64 {
+        self.alphabet.flatland.borrow().group_user_data(self.group_slot)
+    }
+
     pub fn update_items<'p, I: Iterator<Item = &'p FlatlandItem>>(&self, items: I) {
         self.alphabet.flatland.borrow_mut().update_items(self.group_slot, items);
     }
@@ -215,9 +2120,253 @@ impl FlatlandGroup {
         self.alphabet.flatland.borrow_mut().update_transform(self.group_slot, transform);
     }
 
+    /// Sets the size of the group about the center of its bounds, keeping its rotation;
+    /// the center stays where it is drawn, e.g. for labels growing on hover.
+    pub fn set_scale(&self, scale: f32) {
+        self.set_scale_xy(scale, scale);
+    }
+
+    pub fn set_scale_xy(&self, sx: f32, sy: f32) {
+        self.alphabet.flatland.borrow_mut().update_scale(self.group_slot, sx, sy);
+    }
+
     pub fn update_color(&self, color: na::Vector4<u8>) {
         self.alphabet.flatland.borrow_mut().update_color(self.group_slot, color);
     }
+
+    /// Starts `animation` from the beginning, replacing the previous one.
+    pub fn set_animation(&self, animation: GroupAnimation) {
+        self.alphabet.flatland.borrow_mut().update_animation(self.group_slot, Some(animation));
+    }
+
+    /// Stops the animation, drawing the group with its own color and transform again.
+    pub fn clear_animation(&self) {
+        self.alphabet.flatland.borrow_mut().update_animation(self.group_slot, None);
+    }
+
+    /// Steps the group color through `palette` in order, a full cycle every `period` seconds,
+    /// as `Flatlander::advance_animations` moves time forward, e.g. for retro color cycling.
+    ///
+    /// Unlike animations, this changes the group color itself, starting with the first color,
+    /// and only uploads when the color steps. A single color is set and stays; an empty palette
+    /// changes nothing. Shadow, background and item colors aren't cycled.
+    pub fn set_palette_cycle(&self, palette: Vec<na::Vector4<u8>>, period: f32) {
+        self.alphabet.flatland.borrow_mut().update_palette_cycle(self.group_slot, palette, period);
+    }
+
+    /// Stops the palette cycle; the group keeps its current color.
+    pub fn clear_palette_cycle(&self) {
+        self.alphabet.flatland.borrow_mut().clear_palette_cycle(self.group_slot);
+    }
+
+    /// Draws entries added with `Alphabet::add_interpolable_entry` between their regular outline
+    /// at 0 and bold outline at 1, e.g. animated to emphasize a label. Other entries are unaffected.
+    ///
+    /// Geometry is generated once per alphabet entry and weight, with weights rounded to 16 steps.
+    pub fn set_weight(&self, t: f32) {
+        self.alphabet.flatland.borrow_mut().update_weight(self.group_slot, t);
+    }
+
+    /// Starts `effect` on each glyph from the beginning, replacing the previous one;
+    /// `None` draws every glyph as laid out again. Plays alongside group animations.
+    pub fn set_glyph_effect(&self, effect: Option<GlyphEffect>) {
+        self.alphabet.flatland.borrow_mut().update_glyph_effect(self.group_slot, effect);
+    }
+
+    /// Whether the group has an animation that hasn't ended, e.g. to delete labels once faded out.
+    pub fn is_animating(&self) -> bool {
+        self.alphabet.flatland.borrow().is_group_animating(self.group_slot)
+    }
+
+    /// Stores the color premultiplied by its alpha; draw the group with `BlendMode::PremultipliedAlpha`.
+    pub fn set_color_premultiplied(&self, color: na::Vector4<u8>) {
+        self.update_color(crate::color::premultiply(color));
+    }
+
+    pub fn set_blend_mode(&self, mode: BlendMode) {
+        self.alphabet.flatland.borrow_mut().update_blend_mode(self.group_slot, mode);
+    }
+
+    /// Draws the group filled or as triangle outlines, e.g. to highlight a selected label.
+    pub fn set_style(&self, style: RenderStyle) {
+        self.alphabet.flatland.borrow_mut().update_style(self.group_slot, style);
+    }
+
+    /// Draws lines across the group, from its leftmost item to the end of its last advance.
+    ///
+    /// The lines are rectangles added to the group's alphabet, one entry per distinct width,
+    /// and are rebuilt whenever the items change.
+    pub fn set_decoration(&self, decoration: Decoration) {
+        self.alphabet.flatland.borrow_mut().update_decoration(self.group_slot, decoration);
+    }
+
+    pub fn set_decoration_baseline(&self, baseline: DecorationBaseline) {
+        self.alphabet.flatland.borrow_mut().update_decoration_baseline(self.group_slot, baseline);
+    }
+
+    /// Pen position before the glyph at `char_index`, the place of a caret on the baseline,
+    /// in the space the group transform applies to; the transform gives it in the world.
+    /// Like the geometry, it has the y of the item offsets flipped.
+    ///
+    /// The position comes from the laid out glyphs, so it includes their advances and kerning.
+    /// Indices past the end give the position after the last glyph. Characters that were
+    /// skipped by the layout, e.g. missing from the alphabet, don't count.
+    pub fn caret_position(&self, char_index: usize) -> na::Vector2<f32> {
+        self.alphabet.flatland.borrow().caret_position(self.group_slot, char_index)
+    }
+
+    /// Character index of the caret boundary closest to a point in the group's space, e.g. a
+    /// click moved into the group with the inverse transform, counted like `caret_position`.
+    ///
+    /// The point picks the line with the closest baseline first, then the closest boundary on it.
+    pub fn char_index_at(&self, local: na::Vector2<f32>) -> usize {
+        self.alphabet.flatland.borrow().char_index_at(self.group_slot, local)
+    }
+
+    /// Whether a point in the group's space, like for `char_index_at`, is on the ink of a glyph
+    /// rather than just within its bounds, e.g. to click large letters used as buttons.
+    ///
+    /// Needs the alphabet geometry in RAM, see `Flatlander::set_retain_cpu_data`;
+    /// without it, glyphs stop being hit once uploaded.
+    pub fn point_in_glyphs(&self, local: na::Vector2<f32>) -> bool {
+        self.alphabet.flatland.borrow().point_in_glyphs(self.group_slot, local)
+    }
+
+    /// Extent of the group's glyphs and decorations before the group transform,
+    /// `None` if nothing in the group has geometry.
+    pub fn bounds(&self) -> Option<layout::GlyphBounds> {
+        self.alphabet.flatland.borrow().group_bounds(self.group_slot)
+    }
+
+    /// Draws the group from a different alphabet depending on its apparent height on screen.
+    ///
+    /// Each level is the minimum height in pixels at which its alphabet is used; `render` picks
+    /// the highest level reached, or the lowest level for smaller groups. The alphabets must have
+    /// the same entry indices, e.g. the same glyphs tessellated in more or less detail.
+    /// An empty list draws the group from its own alphabet again.
+    pub fn set_lod_alphabets(&self, levels: Vec<(f32, Alphabet)>) {
+        let slots = levels.iter().map(|&(min_height, ref alphabet)| (min_height, alphabet.slot)).collect();
+        self.alphabet.flatland.borrow_mut().set_group_lod_alphabets(self.group_slot, slots);
+    }
+
+    /// How many pixels the item's bounds cover on screen, with the group transform, `vp`
+    /// and a viewport of `viewport` pixels. E.g. to skip hit-testing glyphs smaller than a pixel.
+    pub fn glyph_pixel_size(&self, item_index: usize, vp: &na::Matrix4<f32>, viewport: na::Vector2<f32>) -> na::Vector2<f32> {
+        self.alphabet.flatland.borrow().group_item_pixel_size(self.group_slot, item_index, vp, viewport)
+    }
+
+    /// Fills the group's bounds, grown by `padding` on each side, with a quad drawn behind the glyphs.
+    ///
+    /// Like decorations, the quad is a shared unit quad scaled to the bounds, adding nothing
+    /// to the group's alphabet, and follows the items when they change.
+    pub fn set_background(&self, color: na::Vector4<u8>, padding: f32) {
+        self.alphabet.flatland.borrow_mut().update_background(self.group_slot, Some((color, padding)));
+    }
+
+    pub fn clear_background(&self) {
+        self.alphabet.flatland.borrow_mut().update_background(self.group_slot, None);
+    }
+
+    /// Places the group `offset` pixels from a point of the screen, and again whenever
+    /// `Flatlander::set_viewport_size` changes the size, e.g. to keep a label in the bottom-right corner.
+    ///
+    /// Only the translation of the transform is replaced, its rotation and scale are kept.
+    /// Render the group with `Flatlander::screen_projection`.
+    pub fn set_screen_anchor(&self, anchor: Anchor, offset: na::Vector2<f32>) {
+        self.alphabet.flatland.borrow_mut().update_screen_anchor(self.group_slot, Some((anchor, offset)));
+    }
+
+    /// Stops moving the group on resizes; it stays where it is.
+    pub fn clear_screen_anchor(&self) {
+        self.alphabet.flatland.borrow_mut().update_screen_anchor(self.group_slot, None);
+    }
+
+    /// Keeps the group where a 3D point shows on screen, e.g. for a nameplate above a character,
+    /// updated by `Flatlander::update_anchored_groups`. The group is hidden while the point is
+    /// behind the camera.
+    ///
+    /// Like `set_screen_anchor`, only the translation is replaced; render the group with
+    /// `Flatlander::screen_projection`.
+    pub fn set_world_anchor(&self, anchor: na::Point3<f32>) {
+        self.alphabet.flatland.borrow_mut().update_world_anchor(self.group_slot, Some(anchor));
+    }
+
+    /// Stops following the world anchor and shows the group again; it stays where it is.
+    pub fn clear_world_anchor(&self) {
+        self.alphabet.flatland.borrow_mut().update_world_anchor(self.group_slot, None);
+    }
+
+    /// Highlights the characters from `start` to `end` with a rectangle per line behind the glyphs,
+    /// e.g. the selection of a text field; `start == end` removes the highlight.
+    ///
+    /// Indices count like `caret_position`. The rectangles are generated like the background
+    /// and follow the items when they change.
+    pub fn set_selection(&self, start: usize, end: usize, color: na::Vector4<u8>) {
+        self.alphabet.flatland.borrow_mut().update_selection(self.group_slot, start, end, color);
+    }
+
+    /// Samples `texture` in the group's draws, e.g. for icons next to text; `None` goes back
+    /// to plain colors. The texture is multiplied by the group color.
+    ///
+    /// Texture coordinates come from the entries' vertex normals, so icon quads are added
+    /// with their coordinates as normals. Glyphs have zero normals and sample the bottom-left
+    /// texel, which is left white to mix them with icons. Groups with the same texture share
+    /// batches, apart from the untextured ones. Shadows stay untextured.
+    pub fn set_texture(&self, texture: Option<Texture>) {
+        self.alphabet.flatland.borrow_mut().update_texture(self.group_slot, texture);
+    }
+
+    /// Rotates and scales the group about `pivot`, in the transform's space, instead of its origin.
+    ///
+    /// The pivot itself is moved by the transform's translation. It applies to every later
+    /// transform and scale update, until set back to zero.
+    pub fn set_pivot(&self, pivot: na::Vector2<f32>) {
+        self.alphabet.flatland.borrow_mut().update_pivot(self.group_slot, pivot);
+    }
+
+    /// Colors every glyph on its own instead of with the group color, by item index.
+    ///
+    /// Decorations, backgrounds and shadows keep their colors, and so do items past the end of `colors`.
+    /// New items from `update_items` are drawn in the group color again.
+    pub fn set_item_colors(&self, colors: Vec<na::Vector4<u8>>) {
+        self.alphabet.flatland.borrow_mut().update_item_colors(self.group_slot, colors);
+    }
+
+    /// Colors each glyph by `f` of its offset, e.g. for vertical or diagonal gradients,
+    /// like `set_item_colors`. Offsets are in the group's space like `caret_position`,
+    /// so lines further down have lower y.
+    pub fn apply_color_gradient(&self, f: impl Fn(na::Vector2<f32>) -> na::Vector4<u8>) {
+        self.alphabet.flatland.borrow_mut().apply_color_gradient(self.group_slot, f);
+    }
+
+    /// Mirrors the group horizontally and/or vertically about the center of its bounds, e.g. for
+    /// reflections. Mirrored glyphs are drawn with the other front face, so they survive face culling.
+    ///
+    /// The center follows later item changes; `(false, false)` draws the group as it is again.
+    pub fn set_flip(&self, flip_x: bool, flip_y: bool) {
+        self.alphabet.flatland.borrow_mut().update_flip(self.group_slot, flip_x, flip_y);
+    }
+
+    /// Sets the pivot to a point of the group's current contents; it isn't moved
+    /// when the items change later.
+    pub fn set_pivot_at(&self, pivot: Pivot) {
+        let mut flatland = self.alphabet.flatland.borrow_mut();
+        let point = flatland.pivot_point(self.group_slot, pivot);
+        flatland.update_pivot(self.group_slot, point);
+    }
+
+    /// Draws the group's glyphs and decorations again behind every group, moved by `offset`
+    /// in the transform's space and filled with `color`.
+    ///
+    /// The shadow reuses the glyph geometry with its own instance data, and all shadows
+    /// are drawn in their own batches before the rest.
+    pub fn set_shadow(&self, offset: na::Vector2<f32>, color: na::Vector4<u8>) {
+        self.alphabet.flatland.borrow_mut().update_shadow(self.group_slot, Some((offset, color)));
+    }
+
+    pub fn clear_shadow(&self) {
+        self.alphabet.flatland.borrow_mut().update_shadow(self.group_slot, None);
+    }
 }
 
 impl Drop for FlatlandGroup {
@@ -20,16 +20,23 @@ mod flatlander;
 mod debug_lines;
 mod shader;
 mod profiler;
+mod texture;
 
 pub mod buffer;
 pub mod data;
 pub mod viewport;
 pub mod color_buffer;
+pub mod color;
 
-pub use self::viewport::Viewport;
+pub use self::viewport::{Viewport, ortho_screen};
 pub use self::color_buffer::ColorBuffer;
 pub use self::debug_lines::{DebugLines, RayMarkers, AabbMarker, RectMarker};
-pub use self::flatlander::{Flatlander, FlatlandGroup, FlatlandItem, Alphabet, FlatlanderVertex};
-pub use self::shader::{Shader, Program, Error};
+pub use self::flatlander::layout;
+pub use self::flatlander::glyph;
+pub use self::flatlander::{Flatlander, FlatlandGroup, FlatlandItem, Alphabet, FrozenAlphabet, FontStack, AlphabetSlot, GroupId, FlatlanderVertex, GlyphSource, Shaper, ShapedGlyph, GroupInfo, BlendMode, RenderStyle, DefragPolicy, BufferUpdateMode, GrowthPolicy, TextureShading, DrawPath, Decoration, DecorationBaseline, GroupAnimation, GlyphEffect, Pivot, Anchor, Usage, Winding, CullResult, ChurnStats, LayerHandle, SyncError, BufferMismatch};
+pub use self::shader::{Shader, Program, CompileTimings, Error};
 pub use self::profiler::{EventCountProfiler, FrameProfiler};
+pub use self::texture::Texture;
+#[cfg(feature = "upload_report")]
+pub use self::flatlander::{UploadReport, UploadStats};
 
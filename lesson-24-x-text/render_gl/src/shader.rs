@@ -3,6 +3,8 @@ use crate::na;
 use resources::{self, Resource, Resources};
 use std;
 use std::ffi::{CStr, CString};
+use std::time::{Duration, Instant};
+use floating_duration::TimeAsFloat;
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -23,13 +25,38 @@ pub enum Error {
     LinkError { name: String, message: String },
 }
 
+/// Time spent compiling each shader and linking the program, see `Program::from_res_timed`.
+#[derive(Clone, Debug)]
+pub struct CompileTimings {
+    /// Resource name and compile time of each shader stage.
+    pub shaders: Vec<(String, Duration)>,
+    pub link: Duration,
+}
+
 pub struct Program {
     gl: gl::Gl,
     id: gl::types::GLuint,
 }
 
 impl Program {
+    /// Loads, compiles and links `name.vert` and `name.frag`.
+    ///
+    /// In debug builds, compile and link times are logged at debug level.
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
+        let (program, timings) = Program::from_res_timed(gl, res, name)?;
+
+        if cfg!(debug_assertions) {
+            for &(ref shader, time) in &timings.shaders {
+                log::debug!("compiled {} in {:.2} ms", shader, time.as_fractional_millis());
+            }
+            log::debug!("linked {} in {:.2} ms", name, timings.link.as_fractional_millis());
+        }
+
+        Ok(program)
+    }
+
+    /// Like `from_res`, also returning how long each stage took to compile and link.
+    pub fn from_res_timed(gl: &gl::Gl, res: &Resources, name: &str) -> Result<(Program, CompileTimings), Error> {
         const POSSIBLE_EXT: [&str; 2] = [".vert", ".frag"];
 
         let resource_names = POSSIBLE_EXT
@@ -37,15 +64,27 @@ impl Program {
             .map(|file_extension| format!("{}{}", name, file_extension))
             .collect::<Vec<String>>();
 
+        let mut shader_timings = Vec::with_capacity(resource_names.len());
         let shaders = resource_names
             .iter()
-            .map(|resource_name| Shader::from_res(gl, res, resource_name))
+            .map(|resource_name| {
+                let started = Instant::now();
+                let shader = Shader::from_res(gl, res, resource_name)?;
+                shader_timings.push((resource_name.clone(), started.elapsed()));
+                Ok(shader)
+            })
             .collect::<Result<Vec<Shader>, Error>>()?;
 
-        Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
+        let started = Instant::now();
+        let program = Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
             name: name.into(),
             message,
-        })
+        })?;
+
+        Ok((program, CompileTimings {
+            shaders: shader_timings,
+            link: started.elapsed(),
+        }))
     }
 
     pub fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<Program, String> {
@@ -151,6 +190,12 @@ impl Program {
             self.gl.Uniform1i(location, index);
         }
     }
+
+    pub fn set_uniform_1f(&self, location: i32, value: f32) {
+        unsafe {
+            self.gl.Uniform1f(location, value);
+        }
+    }
 }
 
 impl Drop for Program {
@@ -0,0 +1,79 @@
+use failure;
+use gl;
+use std::os::raw;
+use std::rc::Rc;
+
+struct TextureObject {
+    gl: gl::Gl,
+    obj: gl::types::GLuint,
+}
+
+impl Drop for TextureObject {
+    fn drop(&mut self) {
+        unsafe { self.gl.DeleteTextures(1, &self.obj) };
+    }
+}
+
+/// RGBA texture, deleted when the last clone is dropped.
+#[derive(Clone)]
+pub struct Texture {
+    inner: Rc<TextureObject>,
+}
+
+impl Texture {
+    /// Uploads `width` by `height` pixels of 4 bytes, starting with the bottom row,
+    /// with linear filtering and edges clamped.
+    pub fn from_rgba(gl: &gl::Gl, width: u32, height: u32, pixels: &[u8]) -> Result<Texture, failure::Error> {
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(format_err!("expected {} bytes for a {}x{} texture, got {}", width * height * 4, width, height, pixels.len()));
+        }
+
+        let mut obj: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut obj);
+        }
+        let texture = Texture {
+            inner: Rc::new(TextureObject { gl: gl.clone(), obj }),
+        };
+
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, obj);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as gl::types::GLint);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as gl::types::GLint);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as gl::types::GLint);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as gl::types::GLint);
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as gl::types::GLint,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const raw::c_void,
+            );
+            gl.BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        Ok(texture)
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.inner.obj
+    }
+
+    pub fn bind_at(&self, index: u32) {
+        unsafe {
+            self.inner.gl.ActiveTexture(gl::TEXTURE0 + index);
+            self.inner.gl.BindTexture(gl::TEXTURE_2D, self.inner.obj);
+        }
+    }
+
+    pub fn unbind_at(&self, index: u32) {
+        unsafe {
+            self.inner.gl.ActiveTexture(gl::TEXTURE0 + index);
+            self.inner.gl.BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+}
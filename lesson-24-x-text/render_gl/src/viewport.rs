@@ -1,4 +1,5 @@
 use gl;
+use crate::na;
 
 pub struct Viewport {
     pub x: i32,
@@ -23,3 +24,15 @@ impl Viewport {
         }
     }
 }
+
+/// View-projection in pixels for screen-space text: the origin is the top-left corner of a
+/// `width` by `height` viewport, x goes right and y goes down, and z is kept in -10..10.
+///
+/// The y axis is mirrored compared to usual world coordinates, which flips the winding of
+/// triangles on screen. Flatland geometry is y-up and flipped by its own model matrix, so
+/// the two flips cancel out and glyphs keep the front face `Flatlander` sets. Geometry
+/// drawn with other programs in this space appears with the opposite winding.
+pub fn ortho_screen(width: f32, height: f32) -> na::Matrix4<f32> {
+    na::Matrix4::new_orthographic(0.0, width, -height, 0.0, -10.0, 10.0)
+        * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(1.0, -1.0, 1.0))
+}
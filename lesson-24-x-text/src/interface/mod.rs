@@ -107,6 +107,8 @@ impl ControlInfo {
     }
 }
 
+const DEFAULT_TESSELLATION_TOLERANCE: f32 = 10.0;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 enum AlphabetKey {
     Font(usize),
@@ -124,7 +126,8 @@ pub struct Interface {
     debug_lines: DebugLines,
     flatlander: Flatlander,
 
-    alphabets: MetroHashMap<AlphabetKey, Alphabet>,
+    alphabets: MetroHashMap<AlphabetKey, (Alphabet, f32)>,
+    tessellation_tolerance: f32,
 }
 
 impl Interface {
@@ -153,9 +156,18 @@ impl Interface {
             debug_lines: DebugLines::new(gl, resources)?,
             flatlander: Flatlander::new(gl, resources)?,
             alphabets: MetroHashMap::default(),
+            tessellation_tolerance: DEFAULT_TESSELLATION_TOLERANCE,
         })
     }
 
+    /// Sets the curve flattening tolerance for font alphabets created from now on.
+    ///
+    /// Alphabets keep the tolerance they were created with, so glyphs added to them
+    /// later stay consistent. See `tessellate_glyph` for the effect on vertex count.
+    pub fn set_tessellation_tolerance(&mut self, tolerance: f32) {
+        self.tessellation_tolerance = tolerance;
+    }
+
     pub fn resize(&mut self, size: BoxSize, window_scale: f32) {
         self.fill.resize(size, window_scale);
     }
@@ -192,10 +204,11 @@ impl Interface {
                 }
                 Effect::TextAdd { buffer } => {
 
-                    let alphabet = match self.alphabets.entry(AlphabetKey::Font(buffer._font_id)) {
+                    let (alphabet, tolerance) = match self.alphabets.entry(AlphabetKey::Font(buffer._font_id)) {
                         collections::hash_map::Entry::Occupied(e) => e.into_mut(),
-                        collections::hash_map::Entry::Vacant(mut e) => e.insert(self.flatlander.create_alphabet()),
+                        collections::hash_map::Entry::Vacant(mut e) => e.insert((self.flatlander.create_alphabet(), self.tessellation_tolerance)),
                     };
+                    let tolerance = *tolerance;
 
                     let buffer = self.fonts.buffer_from_id(buffer._id).expect("buffer missing: self.fonts.buffer_from_id(buffer.id)");
 
@@ -211,12 +224,8 @@ impl Interface {
                     let mut y = 0;
 
                     for glyph in glyph_buffer.iter() {
-                        let ix = ensure_glyph_is_in_alphabet_and_return_index(&mut builder, alphabet, buffer.font(), glyph.id);
-                        flatland_group_items.push(FlatlandItem {
-                            alphabet_entry_index: ix,
-                            x_offset: x,
-                            y_offset: y,
-                        });
+                        let ix = ensure_glyph_is_in_alphabet_and_return_index(&mut builder, alphabet, buffer.font(), glyph.id, tolerance);
+                        flatland_group_items.push(FlatlandItem::new(ix, x as f32, y as f32));
 
                         x += glyph.x_advance + glyph.x_offset;
                         y += glyph.y_advance + glyph.y_offset;
@@ -283,12 +292,22 @@ impl Interface {
     }
 }
 
-fn ensure_glyph_is_in_alphabet_and_return_index(builder: &mut lyon_path::default::Builder, alphabet: &mut Alphabet, font: &Font, glyph_id: u32) -> usize {
+fn ensure_glyph_is_in_alphabet_and_return_index(builder: &mut lyon_path::default::Builder, alphabet: &mut Alphabet, font: &Font, glyph_id: u32, tolerance: f32) -> usize {
     if let Some(index) = alphabet.get_entry_index(glyph_id) {
         return index;
     }
 
-    trace!("tessellate glyph {} from {:?} font", glyph_id, font.full_name());
+    let (vertices, indices) = tessellate_glyph(builder, font, glyph_id, tolerance);
+    alphabet.add_entry(glyph_id, vertices, indices)
+}
+
+/// Fills the glyph outline, flattening curves to within `tolerance` font units.
+///
+/// Vertex count grows roughly with the inverse square root of the tolerance.
+/// Each glyph is addressed by its own `u16` indices, so it must stay under 65536 vertices;
+/// the default keeps even large glyphs far below it.
+fn tessellate_glyph(builder: &mut lyon_path::default::Builder, font: &Font, glyph_id: u32, tolerance: f32) -> (Vec<FlatlanderVertex>, Vec<u16>) {
+    trace!("tessellate glyph {} from {:?} font with tolerance {}", glyph_id, font.full_name(), tolerance);
 
     use lyon_path::builder::{FlatPathBuilder};
     use lyon_tessellation::{BuffersBuilder, FillOptions, FillTessellator, FillVertex, VertexBuffers};
@@ -304,7 +323,7 @@ fn ensure_glyph_is_in_alphabet_and_return_index(builder: &mut lyon_path::default
         // Compute the tessellation.
         tessellator.tessellate_path(
             path.path_iter(),
-            &FillOptions::default().with_tolerance(10.0),
+            &FillOptions::default().with_tolerance(tolerance),
             &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| {
                 FlatlanderVertex {
                     pos: data::f16_f16::from((vertex.position.x, vertex.position.y)),
@@ -314,5 +333,5 @@ fn ensure_glyph_is_in_alphabet_and_return_index(builder: &mut lyon_path::default
         ).unwrap();
     }
 
-    alphabet.add_entry(glyph_id, geometry.vertices, geometry.indices)
+    (geometry.vertices, geometry.indices)
 }
\ No newline at end of file
@@ -14,6 +14,11 @@ mod miniz;
 #[cfg(any(test, feature = "backend_miniz"))]
 pub use self::miniz::Miniz;
 
+#[cfg(any(test, feature = "backend_zip"))]
+mod zip;
+#[cfg(any(test, feature = "backend_zip"))]
+pub use self::zip::Zip;
+
 #[cfg(any(test, feature = "backend_filesystem"))]
 mod filesystem;
 #[cfg(any(test, feature = "backend_filesystem"))]
@@ -0,0 +1,249 @@
+extern crate miniz_oxide as miniz;
+
+use crate::backend::{Backend, BackendSyncPoint, Modification};
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasherDefault;
+use std::path::Path;
+use std::{fs, io};
+use twox_hash::XxHash;
+use crate::{Error, ResourcePath};
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+    method: u16,
+    local_header_offset: usize,
+    compressed_size: usize,
+    uncompressed_size: usize,
+}
+
+/// Read-only backend for the files of a zip archive, named by their paths in the archive.
+///
+/// The archive is read into memory when opened; entries are inflated on every read.
+/// Only stored and deflated entries are supported.
+#[derive(Debug)]
+pub struct Zip {
+    data: Vec<u8>,
+    entries: HashMap<String, Entry, BuildHasherDefault<XxHash>>,
+}
+
+impl Zip {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Zip, Error> {
+        Zip::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Zip, Error> {
+        let end = find_end_of_central_directory(&data)
+            .ok_or_else(|| invalid("no end of central directory record"))?;
+        let entry_count = read_u16(&data, end + 10)? as usize;
+        let mut offset = read_u32(&data, end + 16)? as usize;
+
+        let mut entries = HashMap::default();
+        for _ in 0..entry_count {
+            if read_u32(&data, offset)? != CENTRAL_DIRECTORY_HEADER {
+                return Err(invalid("bad central directory header"));
+            }
+
+            let method = read_u16(&data, offset + 10)?;
+            let compressed_size = read_u32(&data, offset + 20)? as usize;
+            let uncompressed_size = read_u32(&data, offset + 24)? as usize;
+            let name_len = read_u16(&data, offset + 28)? as usize;
+            let extra_len = read_u16(&data, offset + 30)? as usize;
+            let comment_len = read_u16(&data, offset + 32)? as usize;
+            let local_header_offset = read_u32(&data, offset + 42)? as usize;
+
+            let name = data.get(offset + 46..offset + 46 + name_len)
+                .ok_or_else(|| invalid("truncated entry name"))?;
+            let name = String::from_utf8_lossy(name);
+
+            // directories are entries with names ending with a slash
+            if !name.ends_with('/') {
+                entries.insert(
+                    name.trim_start_matches('/').to_string(),
+                    Entry { method, local_header_offset, compressed_size, uncompressed_size }
+                );
+            }
+
+            offset += 46 + name_len + extra_len + comment_len;
+        }
+
+        Ok(Zip { data, entries })
+    }
+
+    fn entry_data(&self, entry: &Entry) -> Result<&[u8], Error> {
+        let header = entry.local_header_offset;
+        if read_u32(&self.data, header)? != LOCAL_FILE_HEADER {
+            return Err(invalid("bad local file header"));
+        }
+
+        // the local extra field can differ from the central directory one
+        let name_len = read_u16(&self.data, header + 26)? as usize;
+        let extra_len = read_u16(&self.data, header + 28)? as usize;
+        let start = header + 30 + name_len + extra_len;
+
+        self.data.get(start..start + entry.compressed_size)
+            .ok_or_else(|| invalid("truncated entry data"))
+    }
+}
+
+impl Backend for Zip {
+    fn can_write(&self) -> bool {
+        false
+    }
+
+    fn exists(&self, path: &ResourcePath) -> bool {
+        self.entries.contains_key(path.as_clean_str())
+    }
+
+    fn notify_changes_synced(&mut self, _point: BackendSyncPoint) {}
+
+    fn new_changes(&mut self, _queue: &mut VecDeque<Modification>) -> Option<BackendSyncPoint> {
+        None
+    }
+
+    fn read_into(&mut self, path: &ResourcePath, output: &mut io::Write) -> Result<(), Error> {
+        let entry = *self.entries.get(path.as_clean_str()).ok_or(Error::NotFound)?;
+        let data = self.entry_data(&entry)?;
+
+        match entry.method {
+            METHOD_STORED => output.write_all(data)?,
+            METHOD_DEFLATED => {
+                let inflated = self::miniz::inflate::decompress_to_vec(data)
+                    .map_err(|status| invalid(&format!("failed to inflate {}: {:?}", path.as_clean_str(), status)))?;
+                if inflated.len() != entry.uncompressed_size {
+                    return Err(invalid("inflated entry has the wrong size"));
+                }
+                output.write_all(&inflated)?;
+            },
+            method => return Err(invalid(&format!("unsupported compression method {}", method))),
+        }
+
+        Ok(())
+    }
+
+    fn write_from(&mut self, _path: &ResourcePath, _buffer: &mut io::Read) -> Result<(), Error> {
+        Err(Error::NotWritable)
+    }
+}
+
+fn find_end_of_central_directory(data: &[u8]) -> Option<usize> {
+    // the record is 22 bytes, followed by a comment of up to 64k
+    let last = data.len().checked_sub(22)?;
+    let first = last.saturating_sub(0xffff);
+    (first..=last).rev().find(|&offset| read_u32(data, offset).ok() == Some(END_OF_CENTRAL_DIRECTORY))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, Error> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from(b[0]) | u16::from(b[1]) << 8)
+        .ok_or_else(|| invalid("unexpected end of archive"))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    Ok(u32::from(read_u16(data, offset)?) | u32::from(read_u16(data, offset + 2)?) << 16)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, format!("invalid zip archive: {}", message)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::miniz;
+    use super::{METHOD_DEFLATED, METHOD_STORED};
+    use crate::backend::{Backend, Zip};
+
+    fn push_u16(out: &mut Vec<u8>, value: u16) {
+        out.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+    }
+
+    fn push_u32(out: &mut Vec<u8>, value: u32) {
+        push_u16(out, value as u16);
+        push_u16(out, (value >> 16) as u16);
+    }
+
+    /// Archive with a single stored entry.
+    fn stored_archive(name: &str, contents: &[u8]) -> Vec<u8> {
+        archive(name, METHOD_STORED, contents, contents.len(), &[])
+    }
+
+    /// Archive with a single entry of `data` compressed with `method`, and `local_extra`
+    /// in its local header only.
+    fn archive(name: &str, method: u16, data: &[u8], uncompressed_size: usize, local_extra: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        push_u32(&mut out, 0x0403_4b50);
+        out.extend_from_slice(&[0; 4]);
+        push_u16(&mut out, method);
+        out.extend_from_slice(&[0; 16]);
+        push_u16(&mut out, name.len() as u16);
+        push_u16(&mut out, local_extra.len() as u16);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(local_extra);
+        out.extend_from_slice(data);
+
+        let central_directory = out.len();
+        push_u32(&mut out, 0x0201_4b50);
+        out.extend_from_slice(&[0; 6]);
+        push_u16(&mut out, method);
+        out.extend_from_slice(&[0; 8]);
+        push_u32(&mut out, data.len() as u32);
+        push_u32(&mut out, uncompressed_size as u32);
+        push_u16(&mut out, name.len() as u16);
+        out.extend_from_slice(&[0; 12]);
+        push_u32(&mut out, 0);
+        out.extend_from_slice(name.as_bytes());
+        let central_directory_len = out.len() - central_directory;
+
+        push_u32(&mut out, 0x0605_4b50);
+        out.extend_from_slice(&[0; 4]);
+        push_u16(&mut out, 1);
+        push_u16(&mut out, 1);
+        push_u32(&mut out, central_directory_len as u32);
+        push_u32(&mut out, central_directory as u32);
+        push_u16(&mut out, 0);
+
+        out
+    }
+
+    #[test]
+    fn test_can_read_stored_entry() {
+        let mut be = Zip::from_bytes(stored_archive("shaders/x.vert", b"hello world")).unwrap();
+
+        assert!(be.exists("shaders/x.vert".into()));
+        assert!(!be.exists("shaders/y.vert".into()));
+        assert_eq!(b"hello world", &be.read_vec("shaders/x.vert".into()).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_can_read_deflated_entry() {
+        let contents = b"hello hello hello hello world";
+        let deflated = miniz::deflate::compress_to_vec(contents, 6);
+        let mut be = Zip::from_bytes(archive("x.frag", METHOD_DEFLATED, &deflated, contents.len(), &[])).unwrap();
+
+        assert_eq!(&contents[..], &be.read_vec("x.frag".into()).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_deflated_entry_of_wrong_size_is_an_error() {
+        let contents = b"hello world";
+        let deflated = miniz::deflate::compress_to_vec(contents, 6);
+        let mut be = Zip::from_bytes(archive("x.frag", METHOD_DEFLATED, &deflated, contents.len() + 1, &[])).unwrap();
+
+        assert!(be.read_vec("x.frag".into()).is_err());
+    }
+
+    #[test]
+    fn test_skips_local_extra_field() {
+        let extra = [0x55, 0x54, 5, 0, 1, 2, 3, 4, 5];
+        let mut be = Zip::from_bytes(archive("x.vert", METHOD_STORED, b"hello world", 11, &extra)).unwrap();
+
+        assert_eq!(b"hello world", &be.read_vec("x.vert".into()).unwrap()[..]);
+    }
+}
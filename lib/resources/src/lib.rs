@@ -39,6 +39,14 @@ impl Resources {
         }
     }
 
+    /// Resources read from the files of a zip archive, e.g. to ship a single pack.
+    ///
+    /// Resource names are the paths of the files in the archive.
+    #[cfg(feature = "backend_zip")]
+    pub fn from_archive<P: AsRef<::std::path::Path>>(path: P) -> Result<Resources, failure::Error> {
+        Ok(Resources::new().loaded_from("archive", 0, backend::Zip::open(path)?))
+    }
+
     pub fn loaded_from<L: backend::Backend + 'static>(
         self,
         loader_id: &str,